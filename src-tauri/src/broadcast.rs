@@ -0,0 +1,296 @@
+use crate::{config::BroadcastFormat, Context};
+use async_stream::stream;
+use axum::{
+  body::{Bytes, StreamBody},
+  extract::State,
+  http::{header, HeaderMap, HeaderName, StatusCode},
+  response::{IntoResponse, Response},
+  routing::get,
+};
+use gst::prelude::*;
+use std::{
+  io,
+  net::SocketAddr,
+  sync::{Arc, Mutex},
+};
+use tokio::sync::broadcast::{channel, error::RecvError, Sender};
+
+const ICY_METAINT: usize = 16000;
+const MP3_BITRATE: i32 = 192;
+const OPUS_BITRATE: i32 = 128000;
+
+#[derive(Default)]
+struct StreamHeaders {
+  buffers: Vec<Bytes>,
+  is_complete: bool,
+}
+
+#[derive(Clone)]
+struct Encoder {
+  format: BroadcastFormat,
+  sender: Sender<Bytes>,
+  // Ogg streams can't be decoded without the pages that start them
+  headers: Arc<Mutex<StreamHeaders>>,
+  valve: gst::Element,
+}
+
+// Every format has its own encoder, so changing the format doesn't rebuild the pipeline.
+// Only the one being listened to gets audio, and the ones that aren't installed are left out
+#[derive(Clone)]
+pub struct Broadcast {
+  encoders: Vec<Encoder>,
+}
+
+fn create_encoder(format: &BroadcastFormat) -> Result<Vec<gst::Element>, gst::glib::BoolError> {
+  Ok(match format {
+    BroadcastFormat::Mp3 => vec![gst::ElementFactory::make("lamemp3enc")
+      .property("bitrate", MP3_BITRATE)
+      .property("cbr", true)
+      .build()?],
+    BroadcastFormat::Opus => vec![
+      gst::ElementFactory::make("opusenc")
+        .property("bitrate", OPUS_BITRATE)
+        .build()?,
+      gst::ElementFactory::make("oggmux").build()?,
+    ],
+  })
+}
+
+// Adds a branch from the tee to an app sink through the encoder
+fn add_encoder(
+  bin: &gst::Bin,
+  tee: &gst::Element,
+  format: BroadcastFormat,
+) -> Result<Encoder, gst::glib::BoolError> {
+  let encoder = create_encoder(&format)?;
+  // A slow encoder drops audio instead of holding up playback
+  let queue = gst::ElementFactory::make("queue")
+    .property_from_str("leaky", "downstream")
+    .build()?;
+  let valve = gst::ElementFactory::make("valve")
+    .property("drop", true)
+    .build()?;
+  let convert = gst::ElementFactory::make("audioconvert").build()?;
+  let resample = gst::ElementFactory::make("audioresample").build()?;
+  let sink = gst_app::AppSink::builder().build();
+  // The playback branch keeps time, the encoder takes audio as it comes
+  sink.set_property("sync", false);
+  sink.set_property("async", false);
+
+  let mut branch = vec![&queue, &valve, &convert, &resample];
+  branch.extend(encoder.iter());
+  branch.push(sink.upcast_ref());
+  bin.add_many(&branch)?;
+  gst::Element::link_many(&branch)?;
+  tee.link(&queue)?;
+
+  let (sender, _) = channel(256);
+  let headers = Arc::new(Mutex::new(StreamHeaders::default()));
+  let callback_sender = sender.clone();
+  let callback_headers = headers.clone();
+  let callback_valve = valve.clone();
+  sink.set_callbacks(
+    gst_app::AppSinkCallbacks::builder()
+      .new_sample(move |sink| {
+        let sample = sink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+        let buffer = sample.buffer().ok_or(gst::FlowError::Error)?;
+        let map = buffer.map_readable().map_err(|_| gst::FlowError::Error)?;
+        let bytes = Bytes::copy_from_slice(map.as_slice());
+        let mut headers = callback_headers.lock().unwrap();
+        if buffer.flags().contains(gst::BufferFlags::HEADER) {
+          // A new stream starts after every song change, replace the old headers
+          if headers.is_complete {
+            *headers = StreamHeaders::default();
+          }
+          headers.buffers.push(bytes.clone());
+        } else {
+          headers.is_complete = true;
+        }
+        // Nobody is listening anymore, stop encoding until someone tunes in
+        if callback_sender.send(bytes).is_err() {
+          callback_valve.set_property("drop", true);
+        }
+        Ok(gst::FlowSuccess::Ok)
+      })
+      .build(),
+  );
+  Ok(Encoder {
+    format,
+    sender,
+    headers,
+    valve,
+  })
+}
+
+// Wraps the fader volume in a bin that tees its output into the encoders
+fn create_audio_filter(
+  volume: &gst::Element,
+) -> Result<(gst::Element, Vec<Encoder>), gst::glib::BoolError> {
+  let bin = gst::Bin::new(None);
+  let tee = gst::ElementFactory::make("tee").build()?;
+  let output_queue = gst::ElementFactory::make("queue").build()?;
+  bin.add_many(&[volume, &tee, &output_queue])?;
+  gst::Element::link_many(&[volume, &tee, &output_queue])?;
+  bin.add_pad(&gst::GhostPad::with_target(
+    Some("sink"),
+    &volume.static_pad("sink").unwrap(),
+  )?)?;
+  bin.add_pad(&gst::GhostPad::with_target(
+    Some("src"),
+    &output_queue.static_pad("src").unwrap(),
+  )?)?;
+  let encoders = [BroadcastFormat::Mp3, BroadcastFormat::Opus]
+    .into_iter()
+    .filter_map(|format| match add_encoder(&bin, &tee, format) {
+      Ok(encoder) => Some(encoder),
+      Err(e) => {
+        println!("A broadcast encoder is unavailable: {}", e);
+        None
+      }
+    })
+    .collect();
+  Ok((bin.upcast(), encoders))
+}
+
+impl Broadcast {
+  // Returns the broadcast with the element to use as the playbin audio filter
+  pub fn new(volume: &gst::Element) -> (Self, gst::Element) {
+    let (audio_filter, encoders) = match create_audio_filter(volume) {
+      Ok(filter) => filter,
+      Err(e) => {
+        println!("Broadcasting is unavailable: {}", e);
+        (volume.clone(), Vec::new())
+      }
+    };
+    (Broadcast { encoders }, audio_filter)
+  }
+}
+
+fn get_stream_title(ctx: &Context) -> String {
+  let state = ctx.player_state.lock().unwrap();
+  let library = ctx.library.lock().unwrap();
+  let song = state
+    .current_song
+    .as_ref()
+    .and_then(|song_id| library.songs.get(song_id));
+  match song {
+    Some(song) => {
+      let album = library.albums.get(&song.album).unwrap();
+      let artist = library.artists.get(&album.artist).unwrap();
+      format!("{} - {}", artist.name, song.title)
+    }
+    None => String::new(),
+  }
+}
+
+// An ICY metadata block is its length in 16 byte units followed by the padded text
+fn create_metadata_block(title: &str) -> Bytes {
+  let mut text = format!("StreamTitle='{}';", title.replace('\'', "’")).into_bytes();
+  text.truncate(255 * 16);
+  let blocks = (text.len() + 15) / 16;
+  text.resize(blocks * 16, 0);
+  text.insert(0, blocks as u8);
+  Bytes::from(text)
+}
+
+async fn handle_stream(State(ctx): State<Context>, headers: HeaderMap) -> Response {
+  let format = ctx.config.lock().unwrap().broadcast.format.clone();
+  let encoder = match ctx
+    .broadcast
+    .encoders
+    .iter()
+    .find(|encoder| encoder.format == format)
+  {
+    Some(encoder) => encoder.clone(),
+    None => return StatusCode::SERVICE_UNAVAILABLE.into_response(),
+  };
+  let wants_metadata = headers
+    .get("icy-metadata")
+    .map(|value| value == "1")
+    .unwrap_or(false);
+  let mut receiver = encoder.sender.subscribe();
+  encoder.valve.set_property("drop", false);
+  let stream_headers = encoder.headers.lock().unwrap().buffers.clone();
+  let body = StreamBody::new(stream! {
+    let mut until_metadata = ICY_METAINT;
+    let mut last_title = None;
+    let mut chunks = stream_headers;
+    loop {
+      for mut chunk in chunks {
+        if !wants_metadata {
+          yield Ok::<Bytes, io::Error>(chunk);
+          continue;
+        }
+        while chunk.len() >= until_metadata {
+          yield Ok(chunk.split_to(until_metadata));
+          let title = get_stream_title(&ctx);
+          // Players keep showing the last title, so unchanged titles are sent empty
+          if last_title.as_ref() == Some(&title) {
+            yield Ok(Bytes::from_static(&[0]));
+          } else {
+            yield Ok(create_metadata_block(&title));
+            last_title = Some(title);
+          }
+          until_metadata = ICY_METAINT;
+        }
+        until_metadata -= chunk.len();
+        if !chunk.is_empty() {
+          yield Ok(chunk);
+        }
+      }
+      chunks = match receiver.recv().await {
+        Ok(chunk) => vec![chunk],
+        // Slow listeners skip ahead instead of falling behind
+        Err(RecvError::Lagged(_)) => Vec::new(),
+        Err(RecvError::Closed) => break,
+      };
+    }
+  });
+  let mut response_headers = vec![
+    (
+      header::CONTENT_TYPE,
+      match format {
+        BroadcastFormat::Mp3 => "audio/mpeg".to_string(),
+        BroadcastFormat::Opus => "audio/ogg".to_string(),
+      },
+    ),
+    (header::CACHE_CONTROL, "no-cache".to_string()),
+    (HeaderName::from_static("icy-name"), "Ronix".to_string()),
+  ];
+  if wants_metadata {
+    response_headers.push((
+      HeaderName::from_static("icy-metaint"),
+      ICY_METAINT.to_string(),
+    ));
+  }
+  let mut response = body.into_response();
+  for (name, value) in response_headers {
+    response.headers_mut().insert(name, value.parse().unwrap());
+  }
+  response
+}
+
+// Stops the running broadcast server and starts it again with the current config
+pub fn start(ctx: &Context) {
+  let mut broadcast_server = ctx.broadcast_server.lock().unwrap();
+  if let Some(server) = broadcast_server.take() {
+    server.abort();
+  }
+  let broadcast = ctx.config.lock().unwrap().broadcast.clone();
+  if !broadcast.enabled {
+    return;
+  }
+  let ctx = ctx.clone();
+  *broadcast_server = Some(tauri::async_runtime::spawn(async move {
+    let app = axum::Router::new()
+      .route("/", get(handle_stream))
+      .route("/stream", get(handle_stream))
+      .with_state(ctx);
+    let result = axum::Server::bind(&SocketAddr::from(([0, 0, 0, 0], broadcast.port)))
+      .serve(app.into_make_service())
+      .await;
+    if let Err(e) = result {
+      println!("Failed to serve broadcast: {}", e);
+    }
+  }));
+}
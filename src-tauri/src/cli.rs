@@ -0,0 +1,117 @@
+use crate::{
+  ipc::{IpcCommand, IpcResponse, SeekTarget},
+  RepeatMode,
+};
+use std::{fs, path::Path};
+
+const USAGE: &str = "Usage:
+  ronix                     Start the music player
+  ronix --daemon            Start the music player without a window
+  ronix FILE...             Play files in the running instance
+  ronix play                Resume playback
+  ronix pause               Pause playback
+  ronix toggle              Toggle playback
+  ronix next                Skip to the next song
+  ronix prev                Go back to the previous song
+  ronix seek [+|-]SECONDS   Seek to a position or relative to the current one
+  ronix volume PERCENT      Set the volume
+  ronix queue add FILE...   Add files to the end of the queue
+  ronix status              Show what is playing";
+
+fn parse_files(args: &[String]) -> Result<Vec<std::path::PathBuf>, String> {
+  args
+    .iter()
+    .map(|arg| fs::canonicalize(arg).map_err(|e| format!("{}: {}", arg, e)))
+    .collect()
+}
+
+pub enum CliAction {
+  Start { headless: bool },
+  Command(IpcCommand),
+}
+
+pub fn parse_args(args: &[String]) -> Result<CliAction, String> {
+  let args_str: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+  let command = match args_str.as_slice() {
+    [] => return Ok(CliAction::Start { headless: false }),
+    ["--daemon"] => return Ok(CliAction::Start { headless: true }),
+    ["play"] => IpcCommand::Play,
+    ["pause"] => IpcCommand::Pause,
+    ["toggle"] => IpcCommand::TogglePause,
+    ["next"] => IpcCommand::Next,
+    ["prev"] | ["previous"] => IpcCommand::Previous,
+    ["status"] => IpcCommand::Status,
+    ["seek", position] => {
+      let parse = |seconds: &str| {
+        seconds
+          .parse::<u32>()
+          .map_err(|_| format!("Invalid position: {}", position))
+      };
+      IpcCommand::Seek(match position.chars().next() {
+        Some('+') => SeekTarget::Forward(parse(&position[1..])?),
+        Some('-') => SeekTarget::Backward(parse(&position[1..])?),
+        _ => SeekTarget::Absolute(parse(position)?),
+      })
+    }
+    ["volume", volume] => IpcCommand::Volume(
+      volume
+        .trim_end_matches('%')
+        .parse::<f64>()
+        .map_err(|_| format!("Invalid volume: {}", volume))?
+        / 100.0,
+    ),
+    ["queue", "add", ..] => IpcCommand::QueueAdd(parse_files(&args[2..])?),
+    _ if args.iter().all(|arg| Path::new(arg).is_file()) => IpcCommand::Open(parse_files(args)?),
+    _ => return Err(USAGE.to_string()),
+  };
+  Ok(CliAction::Command(command))
+}
+
+fn format_time(seconds: u32) -> String {
+  format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+pub fn print_response(response: IpcResponse) -> i32 {
+  match response {
+    IpcResponse::Ok | IpcResponse::DaemonUrl(_) => 0,
+    IpcResponse::Error(e) => {
+      eprintln!("{}", e);
+      1
+    }
+    IpcResponse::Status(status) => {
+      match status.title {
+        Some(title) => {
+          println!(
+            "{}: {} - {} ({})",
+            if status.is_paused {
+              "Paused"
+            } else {
+              "Playing"
+            },
+            status.artist.unwrap_or_default(),
+            title,
+            status.album.unwrap_or_default()
+          );
+          println!(
+            "{} / {}",
+            format_time(status.position),
+            format_time(status.duration)
+          );
+        }
+        None => println!("Stopped"),
+      }
+      println!(
+        "Volume: {}%  Repeat: {}  Shuffle: {}  Queue: {} songs",
+        (status.volume * 100.0).round(),
+        match status.repeat_mode {
+          RepeatMode::None => "off",
+          RepeatMode::One => "one",
+          RepeatMode::All => "all",
+        },
+        if status.is_shuffled { "on" } else { "off" },
+        status.queue_length
+      );
+      0
+    }
+  }
+}
@@ -1,12 +1,20 @@
-use crate::{library, Context};
+use crate::{
+  broadcast, library, mpd, scrobble, server, subsonic,
+  themes::{self, SaveThemeInput, SaveThemeResult},
+  Context,
+};
+use async_stream::stream;
 use directories::{ProjectDirs, UserDirs};
+use nanoid::nanoid;
 use rspc::{Router, RouterBuilder, Type};
 use serde::{Deserialize, Serialize};
 use std::{
   fs::{create_dir_all, File},
   path::PathBuf,
+  time::Duration,
 };
 use tauri::api::dialog::blocking::FileDialogBuilder;
+use tokio::time::sleep;
 
 #[derive(Serialize, Deserialize, Clone, Type)]
 pub enum MainColor {
@@ -37,12 +45,199 @@ pub enum AccentColor {
   Rose,
 }
 
+#[derive(Serialize, Deserialize, Clone, Type)]
+pub struct FadeDurations {
+  pub pause_resume: u32,
+  pub skip: u32,
+  pub stop: u32,
+}
+
+impl Default for FadeDurations {
+  fn default() -> Self {
+    Self {
+      pause_resume: 150,
+      skip: 300,
+      stop: 300,
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone, Type, PartialEq)]
+pub struct RemoteConfig {
+  pub enabled: bool,
+  pub port: u16,
+  pub token: String,
+}
+
+impl Default for RemoteConfig {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      port: 7718,
+      token: nanoid!(),
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone, Type, PartialEq)]
+pub struct MpdConfig {
+  pub enabled: bool,
+  pub port: u16,
+  pub password: String,
+}
+
+impl Default for MpdConfig {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      port: 6600,
+      password: String::new(),
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone, Type, PartialEq)]
+pub struct SubsonicConfig {
+  pub enabled: bool,
+  pub port: u16,
+  pub username: String,
+  pub password: String,
+  pub transcoding: bool,
+}
+
+impl Default for SubsonicConfig {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      port: 4040,
+      username: String::new(),
+      password: String::new(),
+      transcoding: false,
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone, Type, PartialEq)]
+pub enum BroadcastFormat {
+  Mp3,
+  Opus,
+}
+
+#[derive(Serialize, Deserialize, Clone, Type, PartialEq)]
+pub struct BroadcastConfig {
+  pub enabled: bool,
+  pub port: u16,
+  pub format: BroadcastFormat,
+}
+
+impl Default for BroadcastConfig {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      port: 8000,
+      format: BroadcastFormat::Mp3,
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Clone, Type)]
+pub struct ScrobblingConfig {
+  pub listenbrainz_enabled: bool,
+  pub listenbrainz_url: String,
+  pub listenbrainz_token: String,
+  pub lastfm_enabled: bool,
+  pub lastfm_url: String,
+  pub lastfm_api_key: String,
+  pub lastfm_api_secret: String,
+  pub lastfm_session_key: String,
+}
+
+impl Default for ScrobblingConfig {
+  fn default() -> Self {
+    Self {
+      listenbrainz_enabled: false,
+      listenbrainz_url: "https://api.listenbrainz.org".to_string(),
+      listenbrainz_token: String::new(),
+      lastfm_enabled: false,
+      lastfm_url: "https://ws.audioscrobbler.com/2.0/".to_string(),
+      lastfm_api_key: String::new(),
+      lastfm_api_secret: String::new(),
+      lastfm_session_key: String::new(),
+    }
+  }
+}
+
+#[derive(Deserialize, Type)]
+struct LastFmLoginInput {
+  username: String,
+  password: String,
+}
+
 #[derive(Serialize, Deserialize, Clone, Type)]
 pub struct Config {
   pub music_folders: Vec<PathBuf>,
   pub dark_mode: bool,
   pub main_color: MainColor,
   pub accent_color: AccentColor,
+  #[serde(default)]
+  pub fade_durations: FadeDurations,
+  #[serde(default = "default_daemon_port")]
+  pub daemon_port: u16,
+  #[serde(default)]
+  pub remote: RemoteConfig,
+  #[serde(default)]
+  pub mpd: MpdConfig,
+  #[serde(default)]
+  pub subsonic: SubsonicConfig,
+  #[serde(default)]
+  pub broadcast: BroadcastConfig,
+  #[serde(default)]
+  pub scrobbling: ScrobblingConfig,
+  #[serde(default)]
+  pub write_rating_tags: bool,
+  #[serde(default = "default_search_transliteration")]
+  pub search_transliteration: bool,
+  // Skipped at the start of names when sorting
+  #[serde(default = "default_sort_articles")]
+  pub sort_articles: Vec<String>,
+  // Images beside the tracks with these names are used as cover art, earlier names first
+  #[serde(default = "default_cover_art_names")]
+  pub cover_art_names: Vec<String>,
+  // In megabytes, the least recently used covers are removed past it
+  #[serde(default = "default_cover_cache_size")]
+  pub cover_cache_size: u32,
+  // Takes the accent color from the playing song's cover instead
+  #[serde(default)]
+  pub dynamic_accent: bool,
+  // The id of a custom theme, which replaces the main and accent colors
+  #[serde(default)]
+  pub theme: Option<String>,
+  // Uses the desktop's light or dark preference over `dark_mode` when it has one
+  #[serde(default)]
+  pub follow_system_theme: bool,
+}
+
+fn default_daemon_port() -> u16 {
+  7717
+}
+
+fn default_search_transliteration() -> bool {
+  true
+}
+
+fn default_sort_articles() -> Vec<String> {
+  vec!["The".to_string(), "A".to_string(), "An".to_string()]
+}
+
+fn default_cover_cache_size() -> u32 {
+  256
+}
+
+fn default_cover_art_names() -> Vec<String> {
+  ["cover", "folder", "front", "album", "albumart"]
+    .iter()
+    .map(|name| name.to_string())
+    .collect()
 }
 
 impl Default for Config {
@@ -52,6 +247,21 @@ impl Default for Config {
       dark_mode: true,
       main_color: MainColor::Zinc,
       accent_color: AccentColor::Emerald,
+      fade_durations: FadeDurations::default(),
+      daemon_port: default_daemon_port(),
+      remote: RemoteConfig::default(),
+      mpd: MpdConfig::default(),
+      subsonic: SubsonicConfig::default(),
+      broadcast: BroadcastConfig::default(),
+      scrobbling: ScrobblingConfig::default(),
+      write_rating_tags: false,
+      search_transliteration: default_search_transliteration(),
+      sort_articles: default_sort_articles(),
+      cover_art_names: default_cover_art_names(),
+      cover_cache_size: default_cover_cache_size(),
+      dynamic_accent: false,
+      theme: None,
+      follow_system_theme: false,
     }
   }
 }
@@ -89,16 +299,146 @@ pub fn get_router() -> RouterBuilder<Context> {
       t(|_ctx, _input: ()| UserDirs::new().unwrap().audio_dir().unwrap().to_path_buf())
     })
     .query("pickFolder", |t| {
-      t(|_ctx, _input: ()| async { FileDialogBuilder::new().pick_folder() })
+      t(|ctx, _input: ()| async move {
+        // A headless daemon has no display to show the dialog on
+        match ctx.daemon_url {
+          Some(_) => None,
+          None => FileDialogBuilder::new().pick_folder(),
+        }
+      })
+    })
+    .query("getRemoteUrl", |t| {
+      t(|ctx, _input: ()| server::get_remote_url(&ctx.config.lock().unwrap().remote))
     })
     .mutation("set", |t| {
       t(|ctx, input: Config| {
         let mut config = ctx.config.lock().unwrap();
-        if input.music_folders != config.music_folders {
-          *ctx.library.lock().unwrap() = library::read_from_dirs(&input.music_folders)
+        if input.music_folders != config.music_folders
+          || input.cover_art_names != config.cover_art_names
+        {
+          let library = library::read_from_dirs(&input.music_folders, &input.cover_art_names);
+          ctx.library.lock().unwrap().replace(library);
         }
         write_config_file(&input);
+        let remote_changed = input.remote != config.remote;
+        let mpd_changed = input.mpd != config.mpd;
+        let subsonic_changed = input.subsonic != config.subsonic;
+        let broadcast_changed = input.broadcast != config.broadcast;
         *config = input;
+        drop(config);
+        if remote_changed {
+          server::start_remote(&ctx);
+        }
+        if mpd_changed {
+          mpd::start(&ctx);
+        }
+        if subsonic_changed {
+          subsonic::start(&ctx);
+        }
+        if broadcast_changed {
+          broadcast::start(&ctx);
+        }
+      })
+    })
+    .query("themes", |t| t(|_ctx, _input: ()| themes::get_themes()))
+    // Has the theme's id, or the reason it couldn't be saved
+    .mutation("saveTheme", |t| {
+      t(
+        |_ctx, input: SaveThemeInput| match themes::save_theme(input) {
+          Ok(id) => SaveThemeResult::Saved(id),
+          Err(e) => SaveThemeResult::Failed(e),
+        },
+      )
+    })
+    .mutation("deleteTheme", |t| {
+      t(|ctx, id: String| {
+        themes::delete_theme(&id);
+        let mut config = ctx.config.lock().unwrap();
+        if config.theme.as_ref() == Some(&id) {
+          config.theme = None;
+          write_config_file(&config);
+        }
+      })
+    })
+    .mutation("importTheme", |t| {
+      t(|ctx, _input: ()| async move {
+        // A headless daemon has no display to show the dialog on
+        if ctx.daemon_url.is_some() {
+          return "Themes can only be imported from the window".to_string();
+        }
+        let path = match FileDialogBuilder::new()
+          .add_filter("Ronix theme", &["json"])
+          .pick_file()
+        {
+          Some(path) => path,
+          None => return "Import cancelled".to_string(),
+        };
+        match themes::import_theme(&path) {
+          Ok(_) => "Theme imported".to_string(),
+          Err(e) => format!("Failed to read the theme: {}", e),
+        }
+      })
+    })
+    .mutation("exportTheme", |t| {
+      t(|ctx, id: String| async move {
+        if ctx.daemon_url.is_some() {
+          return "Themes can only be exported from the window".to_string();
+        }
+        let path = match FileDialogBuilder::new()
+          .set_file_name(&format!("{}.json", id))
+          .add_filter("Ronix theme", &["json"])
+          .save_file()
+        {
+          Some(path) => path,
+          None => return "Export cancelled".to_string(),
+        };
+        match themes::export_theme(&id, &path) {
+          Ok(_) => format!("Saved to {}", path.display()),
+          Err(e) => format!("Failed to save the theme: {}", e),
+        }
+      })
+    })
+    .subscription("systemDarkMode", |t| {
+      t(|ctx, _input: ()| {
+        stream! {
+          let mut old_dark_mode = *ctx.system_dark_mode.lock().unwrap();
+          yield old_dark_mode;
+          loop {
+            sleep(Duration::from_millis(500)).await;
+            let dark_mode = *ctx.system_dark_mode.lock().unwrap();
+            if dark_mode != old_dark_mode {
+              old_dark_mode = dark_mode;
+              yield dark_mode;
+            }
+          }
+        }
+      })
+    })
+    .mutation("resetRemoteToken", |t| {
+      t(|ctx, _input: ()| {
+        let mut config = ctx.config.lock().unwrap();
+        // Every paired device has to scan the new pairing link
+        config.remote.token = nanoid!();
+        write_config_file(&config);
+        drop(config);
+        server::start_remote(&ctx);
+      })
+    })
+    .query("scrobblingError", |t| {
+      t(|ctx, _input: ()| ctx.scrobbling_error.lock().unwrap().clone())
+    })
+    .mutation("lastFmLogin", |t| {
+      t(|ctx, input: LastFmLoginInput| async move {
+        let scrobbling = ctx.config.lock().unwrap().scrobbling.clone();
+        let session_key =
+          match scrobble::log_in_lastfm(&scrobbling, input.username, input.password).await {
+            Ok(session_key) => session_key,
+            Err(e) => return format!("Failed to log in to Last.fm: {}", e),
+          };
+        let mut config = ctx.config.lock().unwrap();
+        config.scrobbling.lastfm_session_key = session_key;
+        write_config_file(&config);
+        "Logged in to Last.fm".to_string()
       })
     })
 }
@@ -0,0 +1,325 @@
+use crate::{library::Library, Context};
+use directories::ProjectDirs;
+use filetime::{set_file_mtime, FileTime};
+use image::{imageops::FilterType, ImageFormat};
+use lofty::{MimeType, Picture, PictureType, Probe, TaggedFileExt};
+use nanoid::nanoid;
+use std::{
+  error::Error,
+  fs::{self, create_dir_all},
+  path::{Path, PathBuf},
+  time::{Duration, SystemTime},
+};
+use tauri::http::{Request, Response, ResponseBuilder};
+use tokio::time::interval;
+use url::Url;
+
+const COVER_ART_EXTENSIONS: [&str; 5] = ["jpg", "jpeg", "png", "webp", "bmp"];
+// Every cover use touches the file, so recently used ones, like those being served, are kept
+const MIN_EVICTION_AGE: Duration = Duration::from_secs(60 * 60);
+const EVICTION_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Clone, Copy)]
+pub enum CoverSize {
+  // For lists and the player bar
+  Small,
+  // For the album page
+  Large,
+  Original,
+}
+
+// What's needed to find an album's cover, so the library isn't locked while resizing
+pub struct CoverSource {
+  cover_art: PathBuf,
+  song_paths: Vec<PathBuf>,
+  cover_art_names: Vec<String>,
+}
+
+impl CoverSize {
+  pub fn parse(size: Option<&str>) -> CoverSize {
+    match size {
+      Some("small") => CoverSize::Small,
+      Some("large") => CoverSize::Large,
+      _ => CoverSize::Original,
+    }
+  }
+
+  fn get_pixels(&self) -> Option<u32> {
+    match self {
+      CoverSize::Small => Some(256),
+      CoverSize::Large => Some(1024),
+      CoverSize::Original => None,
+    }
+  }
+}
+
+pub fn get_covers_dir() -> PathBuf {
+  let covers_dir = ProjectDirs::from("dev", "ronanru", "ronix")
+    .unwrap()
+    .cache_dir()
+    .join("covers");
+  create_dir_all(&covers_dir).unwrap();
+  covers_dir
+}
+
+// Identical images are only written once, named by their hash
+fn save_cover_art(data: &[u8], extension: &str, covers_dir: &Path) -> Option<String> {
+  let path = covers_dir
+    .join(format!("{:x}", md5::compute(data)))
+    .with_extension(extension);
+  if !path.exists() {
+    fs::write(&path, data).ok()?;
+  }
+  Some(path.to_str()?.to_string())
+}
+
+fn save_picture(picture: &Picture, covers_dir: &Path) -> Option<String> {
+  let extension = match picture.mime_type() {
+    MimeType::Bmp => "bmp",
+    MimeType::Jpeg => "jpg",
+    MimeType::Png => "png",
+    MimeType::Tiff => "tiff",
+    MimeType::Unknown(t) => t.split('/').last().unwrap(),
+    _ => "jpg",
+  };
+  save_cover_art(picture.data(), extension, covers_dir)
+}
+
+// Names are matched without case or extension, like Cover.JPG for "cover"
+fn find_sidecar_cover_art(folder: &Path, names: &[String]) -> Option<PathBuf> {
+  fs::read_dir(folder)
+    .ok()?
+    .filter_map(|entry| {
+      let path = entry.ok()?.path();
+      let extension = path.extension()?.to_str()?.to_lowercase();
+      if !COVER_ART_EXTENSIONS.contains(&extension.as_str()) {
+        return None;
+      }
+      let stem = path.file_stem()?.to_str()?;
+      let priority = names
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(stem))?;
+      Some((priority, path))
+    })
+    .min()
+    .map(|(_, path)| path)
+}
+
+// Embedded front covers win over images beside the tracks, which win over other embedded pictures
+pub fn read_cover_art(
+  path: &Path,
+  pictures: &[Picture],
+  cover_art_names: &[String],
+  covers_dir: &Path,
+) -> Option<String> {
+  if let Some(picture) = pictures
+    .iter()
+    .find(|picture| picture.pic_type() == PictureType::CoverFront)
+  {
+    return save_picture(picture, covers_dir);
+  }
+  path
+    .parent()
+    .and_then(|folder| find_sidecar_cover_art(folder, cover_art_names))
+    .and_then(|sidecar| {
+      let data = fs::read(&sidecar).ok()?;
+      let extension = sidecar.extension()?.to_str()?.to_lowercase();
+      save_cover_art(&data, &extension, covers_dir)
+    })
+    .or_else(|| save_picture(pictures.first()?, covers_dir))
+}
+
+pub fn get_cover_source(library: &Library, album_id: &str) -> Option<CoverSource> {
+  let album = library.albums.get(album_id)?;
+  Some(CoverSource {
+    cover_art: PathBuf::from(album.cover_art.as_ref()?),
+    song_paths: library
+      .songs
+      .values()
+      .filter(|song| song.album == album_id)
+      .map(|song| song.path.clone())
+      .collect(),
+    cover_art_names: library.cover_art_names.clone(),
+  })
+}
+
+// Covers evicted from the cache are read again from the album's songs
+fn get_original(source: &CoverSource) -> Option<PathBuf> {
+  if source.cover_art.exists() {
+    return Some(source.cover_art.clone());
+  }
+  let covers_dir = get_covers_dir();
+  source
+    .song_paths
+    .iter()
+    .find_map(|path| {
+      let tagged_file = Probe::open(path).ok()?.read().ok()?;
+      let pictures = tagged_file
+        .primary_tag()
+        .or_else(|| tagged_file.first_tag())
+        .map_or(&[][..], |tag| tag.pictures());
+      read_cover_art(path, pictures, &source.cover_art_names, &covers_dir)
+    })
+    .map(PathBuf::from)
+}
+
+// Thumbnails are kept beside the original as <hash>-<pixels>.jpg
+fn get_thumbnail_path(original: &Path, pixels: u32) -> Option<PathBuf> {
+  Some(original.with_file_name(format!(
+    "{}-{}.jpg",
+    original.file_stem()?.to_str()?,
+    pixels
+  )))
+}
+
+pub fn get_cover(source: &CoverSource, size: CoverSize) -> Option<PathBuf> {
+  let original = get_original(source)?;
+  let path = match size.get_pixels() {
+    None => original,
+    Some(pixels) => {
+      let thumbnail = get_thumbnail_path(&original, pixels)?;
+      if thumbnail.exists() {
+        thumbnail
+      } else {
+        match image::open(&original) {
+          // Covers that are already small enough are used as they are
+          Ok(image) if image.width() > pixels || image.height() > pixels => {
+            // Written under another name first, so a half written thumbnail is never read
+            let temporary = thumbnail.with_extension(format!("{}.tmp", nanoid!()));
+            image
+              .resize(pixels, pixels, FilterType::Lanczos3)
+              .to_rgb8()
+              .save_with_format(&temporary, ImageFormat::Jpeg)
+              .ok()?;
+            fs::rename(&temporary, &thumbnail).ok()?;
+            thumbnail
+          }
+          _ => original,
+        }
+      }
+    }
+  };
+  // The modification time is when the cover was last used, which the eviction goes by
+  let _ = set_file_mtime(&path, FileTime::now());
+  Some(path)
+}
+
+pub fn get_original_cover(library: &Library, album_id: &str) -> Option<PathBuf> {
+  get_cover(&get_cover_source(library, album_id)?, CoverSize::Original)
+}
+
+// Removes the least recently used covers until the cache fits in `max_size` bytes
+pub fn evict(max_size: u64) {
+  let entries = match fs::read_dir(get_covers_dir()) {
+    Ok(entries) => entries,
+    Err(_) => return,
+  };
+  let min_age = SystemTime::now() - MIN_EVICTION_AGE;
+  let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+    .filter_map(|entry| {
+      let entry = entry.ok()?;
+      let metadata = entry
+        .metadata()
+        .ok()
+        .filter(|metadata| metadata.is_file())?;
+      Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+    })
+    .collect();
+  let mut total_size: u64 = files.iter().map(|(_, size, _)| size).sum();
+  files.sort();
+  for (modified, size, path) in files {
+    if total_size <= max_size || modified > min_age {
+      break;
+    }
+    if fs::remove_file(&path).is_ok() {
+      total_size -= size;
+    }
+  }
+}
+
+pub fn get_mime_type(path: &Path) -> &'static str {
+  match path
+    .extension()
+    .and_then(|extension| extension.to_str())
+    .map(|extension| extension.to_lowercase())
+    .as_deref()
+  {
+    Some("png") => "image/png",
+    Some("webp") => "image/webp",
+    Some("bmp") => "image/bmp",
+    Some("tiff") => "image/tiff",
+    _ => "image/jpeg",
+  }
+}
+
+// Decodes and resizes images, so async code calls it with spawn_blocking
+pub fn read_cover(
+  ctx: &Context,
+  album_id: &str,
+  size: CoverSize,
+) -> Option<(Vec<u8>, &'static str)> {
+  let source = get_cover_source(&ctx.library.lock().unwrap(), album_id)?;
+  let path = get_cover(&source, size)?;
+  let data = fs::read(&path).ok()?;
+  Some((data, get_mime_type(&path)))
+}
+
+// Keeps the cache under the size from the config, checked now and then instead of on every write
+pub fn start(ctx: Context) {
+  tauri::async_runtime::spawn(async move {
+    let mut ticker = interval(EVICTION_INTERVAL);
+    loop {
+      ticker.tick().await;
+      let max_size = ctx.config.lock().unwrap().cover_cache_size as u64 * 1024 * 1024;
+      let _ = tokio::task::spawn_blocking(move || evict(max_size)).await;
+    }
+  });
+}
+
+// The thumbnail if it was made already, without decoding anything
+fn find_cover(source: &CoverSource, size: CoverSize) -> Option<PathBuf> {
+  let path = match size.get_pixels() {
+    None => source.cover_art.clone(),
+    Some(pixels) => get_thumbnail_path(&source.cover_art, pixels)?,
+  };
+  if !path.exists() {
+    return None;
+  }
+  let _ = set_file_mtime(&path, FileTime::now());
+  Some(path)
+}
+
+// Serves cover://localhost/<album id>?size=small, or https://cover.localhost/... on Windows.
+// It runs on the webview's thread, so missing thumbnails are made in the background and
+// the original is served until then
+pub fn handle_protocol(ctx: &Context, request: &Request) -> Result<Response, Box<dyn Error>> {
+  let url = Url::parse(request.uri())?;
+  let album_id = url.path().trim_start_matches('/');
+  let size = url
+    .query_pairs()
+    .find(|(key, _)| key == "size")
+    .map(|(_, value)| value.to_string());
+  let size = CoverSize::parse(size.as_deref());
+  let source = match get_cover_source(&ctx.library.lock().unwrap(), album_id) {
+    Some(source) => source,
+    None => return ResponseBuilder::new().status(404).body(Vec::new()),
+  };
+  if let Some(path) = find_cover(&source, size) {
+    return ResponseBuilder::new()
+      .mimetype(get_mime_type(&path))
+      .header("Cache-Control", "max-age=86400")
+      .body(fs::read(&path)?);
+  }
+  let original = find_cover(&source, CoverSize::Original);
+  tauri::async_runtime::spawn_blocking(move || get_cover(&source, size));
+  match original {
+    Some(path) => ResponseBuilder::new()
+      .mimetype(get_mime_type(&path))
+      .header("Cache-Control", "no-store")
+      .body(fs::read(&path)?),
+    None => ResponseBuilder::new()
+      .status(404)
+      .header("Cache-Control", "no-store")
+      .body(Vec::new()),
+  }
+}
@@ -21,6 +21,8 @@ pub fn get_router() -> RouterBuilder<Context> {
           "--audio-format",
           "mp3",
           "--add-metadata",
+          // The input can't be read as an option, like --exec
+          "--",
           &input,
         ])
         .current_dir(&folders[0])
@@ -35,7 +37,9 @@ pub fn get_router() -> RouterBuilder<Context> {
       if !sacad.map(|s| s.success()).unwrap_or(false) {
         return "Failed to download cover art with sacad_r";
       }
-      *ctx.library.lock().unwrap() = library::read_from_dirs(&folders);
+      let cover_art_names = ctx.config.lock().unwrap().cover_art_names.clone();
+      let library = library::read_from_dirs(&folders, &cover_art_names);
+      ctx.library.lock().unwrap().replace(library);
       "Download successful"
     })
   })
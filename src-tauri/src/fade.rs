@@ -0,0 +1,194 @@
+use crate::config::Config;
+use gst::prelude::*;
+use std::{
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    mpsc::{channel, Sender},
+    Arc, Mutex,
+  },
+  thread,
+  time::Duration,
+};
+
+const FADE_STEP_MS: u32 = 10;
+
+pub enum FadeKind {
+  PauseResume,
+  Skip,
+  Stop,
+}
+
+enum FadeCommand {
+  In(u32, Box<dyn FnOnce() + Send>),
+  Out(u32, Box<dyn FnOnce() + Send>),
+  // One command, so the fade in doesn't count as a newer fade cutting the fade out short
+  Through(u32, Box<dyn FnOnce() + Send>),
+}
+
+// Fades run on a dedicated `volume` element used as the playbin audio filter,
+// so they never touch the volume set by the user through the player.
+#[derive(Clone)]
+pub struct Fader {
+  pub volume: gst::Element,
+  sender: Sender<FadeCommand>,
+  pending: Arc<AtomicUsize>,
+  config: Arc<Mutex<Config>>,
+}
+
+impl Fader {
+  pub fn new(config: Arc<Mutex<Config>>) -> Self {
+    let volume = gst::ElementFactory::make("volume").build().unwrap();
+    let volume_clone = volume.clone();
+
+    let (sender, receiver) = channel::<FadeCommand>();
+    let pending = Arc::new(AtomicUsize::new(0));
+    let pending_clone = pending.clone();
+    thread::spawn(move || {
+      for command in receiver {
+        pending_clone.fetch_sub(1, Ordering::SeqCst);
+        run_command(&volume_clone, command, &pending_clone);
+      }
+    });
+
+    Fader {
+      volume,
+      sender,
+      pending,
+      config,
+    }
+  }
+
+  fn duration(&self, kind: FadeKind) -> u32 {
+    let durations = &self.config.lock().unwrap().fade_durations;
+    match kind {
+      FadeKind::PauseResume => durations.pause_resume,
+      FadeKind::Skip => durations.skip,
+      FadeKind::Stop => durations.stop,
+    }
+  }
+
+  fn send(&self, command: FadeCommand) {
+    self.pending.fetch_add(1, Ordering::SeqCst);
+    self.sender.send(command).unwrap();
+  }
+
+  pub fn fade_in(&self, kind: FadeKind, action: impl FnOnce() + Send + 'static) {
+    self.send(FadeCommand::In(self.duration(kind), Box::new(action)));
+  }
+
+  pub fn fade_out(&self, kind: FadeKind, action: impl FnOnce() + Send + 'static) {
+    self.send(FadeCommand::Out(self.duration(kind), Box::new(action)));
+  }
+
+  // Fades out, runs the action at silence and fades back in
+  pub fn fade_through(&self, kind: FadeKind, action: impl FnOnce() + Send + 'static) {
+    self.send(FadeCommand::Through(self.duration(kind), Box::new(action)));
+  }
+}
+
+// Lets the fades run without GStreamer in tests
+trait Volume {
+  fn get(&self) -> f64;
+  fn set(&self, volume: f64);
+}
+
+impl Volume for gst::Element {
+  fn get(&self) -> f64 {
+    self.property("volume")
+  }
+
+  fn set(&self, volume: f64) {
+    self.set_property("volume", volume);
+  }
+}
+
+fn run_command(volume: &impl Volume, command: FadeCommand, pending: &AtomicUsize) {
+  match command {
+    FadeCommand::In(duration, action) => {
+      volume.set(0.0);
+      action();
+      ramp(volume, 1.0, duration, pending);
+    }
+    FadeCommand::Out(duration, action) => {
+      ramp(volume, 0.0, duration, pending);
+      action();
+    }
+    FadeCommand::Through(duration, action) => {
+      ramp(volume, 0.0, duration, pending);
+      action();
+      ramp(volume, 1.0, duration, pending);
+    }
+  }
+}
+
+fn ramp(volume: &impl Volume, target: f64, duration: u32, pending: &AtomicUsize) {
+  let start = volume.get();
+  let steps = duration / FADE_STEP_MS;
+  for step in 1..=steps {
+    // A newer fade is waiting and ramps on from here. Jumping to the target instead would
+    // blip to full volume between two fade outs
+    if pending.load(Ordering::SeqCst) > 0 {
+      return;
+    }
+    volume.set(start + (target - start) * step as f64 / steps as f64);
+    thread::sleep(Duration::from_millis(FADE_STEP_MS as u64));
+  }
+  volume.set(target);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::cell::RefCell;
+
+  #[derive(Default)]
+  struct RecordedVolume {
+    values: RefCell<Vec<f64>>,
+  }
+
+  impl Volume for RecordedVolume {
+    fn get(&self) -> f64 {
+      self.values.borrow().last().copied().unwrap_or(1.0)
+    }
+
+    fn set(&self, volume: f64) {
+      self.values.borrow_mut().push(volume);
+    }
+  }
+
+  #[test]
+  fn ramps_to_the_target_in_steps() {
+    let volume = RecordedVolume::default();
+    volume.set(0.0);
+    ramp(&volume, 1.0, 40, &AtomicUsize::new(0));
+    assert_eq!(*volume.values.borrow(), [0.0, 0.25, 0.5, 0.75, 1.0, 1.0]);
+  }
+
+  #[test]
+  fn pending_fades_keep_the_volume() {
+    let volume = RecordedVolume::default();
+    volume.set(0.5);
+    ramp(&volume, 1.0, 40, &AtomicUsize::new(1));
+    assert_eq!(volume.get(), 0.5);
+  }
+
+  #[test]
+  fn fading_through_before_another_fade_never_gets_louder() {
+    let volume = RecordedVolume::default();
+    volume.set(0.5);
+    let is_run = Arc::new(Mutex::new(false));
+    let is_run_clone = is_run.clone();
+    let command = FadeCommand::Through(40, Box::new(move || *is_run_clone.lock().unwrap() = true));
+    run_command(&volume, command, &AtomicUsize::new(1));
+    assert!(*is_run.lock().unwrap());
+    assert!(volume.values.borrow().iter().all(|value| *value <= 0.5));
+  }
+
+  #[test]
+  fn fades_in_from_silence_after_the_action() {
+    let volume = RecordedVolume::default();
+    let command = FadeCommand::In(20, Box::new(|| {}));
+    run_command(&volume, command, &AtomicUsize::new(0));
+    assert_eq!(*volume.values.borrow(), [0.0, 0.5, 1.0, 1.0]);
+  }
+}
@@ -0,0 +1,347 @@
+use crate::{
+  get_current_time,
+  library::{Library, LibraryGuard},
+  player::get_position,
+  Context, PlayerScope,
+};
+use directories::ProjectDirs;
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::{HashMap, HashSet},
+  fs::{create_dir_all, File, OpenOptions},
+  io::{BufRead, BufReader, BufWriter, Write},
+  path::PathBuf,
+  sync::Arc,
+  time::Duration,
+};
+use tokio::time::{interval, MissedTickBehavior};
+
+// A song counts as played after half of it or this many seconds, like scrobblers do
+const MAX_LISTEN_TIME: u32 = 240;
+
+#[derive(Serialize, Deserialize, Clone, Type, PartialEq)]
+pub enum HistoryEventKind {
+  Play,
+  Skip,
+}
+
+// Album and artist ids change on every scan, so scopes are saved by name
+#[derive(Serialize, Deserialize, Clone, Type)]
+pub enum HistoryScope {
+  Library,
+  Album(String),
+  Artist(String),
+  Playlist(String),
+  Favorites,
+  Rating(u8),
+  Genre(String),
+  Year(u32),
+  Decade(u32),
+  Folder(String),
+  Subsonic,
+  // Imported from another service's export
+  Imported(String),
+}
+
+// Saved with song paths, like playlists, so the history survives rescans
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HistoryEvent {
+  pub path: PathBuf,
+  pub kind: HistoryEventKind,
+  pub timestamp: u32,
+  pub listened: u32,
+  pub scope: HistoryScope,
+}
+
+#[derive(Deserialize, Type)]
+pub struct HistoryInput {
+  offset: u32,
+  limit: u32,
+  from: Option<u32>,
+  to: Option<u32>,
+}
+
+#[derive(Serialize, Type)]
+pub struct HistoryEntry {
+  // None when the song is no longer in the library
+  song_id: Option<String>,
+  path: PathBuf,
+  kind: HistoryEventKind,
+  timestamp: u32,
+  listened: u32,
+  scope: HistoryScope,
+}
+
+#[derive(Serialize, Type)]
+pub struct HistoryPage {
+  entries: Vec<HistoryEntry>,
+  total: u32,
+}
+
+struct Play {
+  song_id: String,
+  scope: HistoryScope,
+  started_at: u32,
+  position: u32,
+  listened: u32,
+}
+
+fn get_history_file_path() -> PathBuf {
+  ProjectDirs::from("dev", "ronanru", "ronix")
+    .unwrap()
+    .data_dir()
+    .join("history.jsonl")
+}
+
+pub fn is_listened(duration: u32, listened: u32) -> bool {
+  listened >= (duration / 2).min(MAX_LISTEN_TIME)
+}
+
+fn read_history() -> Vec<HistoryEvent> {
+  match File::open(get_history_file_path()) {
+    Ok(file) => BufReader::new(file)
+      .lines()
+      .flatten()
+      .filter_map(|line| serde_json::from_str(&line).ok())
+      .collect(),
+    Err(_) => Vec::new(),
+  }
+}
+
+fn count_event(library: &mut Library, song_id: &str, event: &HistoryEvent) {
+  let song = match library.songs.get_mut(song_id) {
+    Some(song) => song,
+    None => return,
+  };
+  if event.kind == HistoryEventKind::Skip {
+    song.skip_count += 1;
+    return;
+  }
+  song.play_count += 1;
+  song.last_played = song.last_played.max(Some(event.timestamp));
+  let album = library.albums.get_mut(&song.album).unwrap();
+  album.play_count += 1;
+  album.last_played = album.last_played.max(Some(event.timestamp));
+  let artist = library.artists.get_mut(&album.artist).unwrap();
+  artist.play_count += 1;
+  artist.last_played = artist.last_played.max(Some(event.timestamp));
+}
+
+pub fn count_history(library: &mut Library) {
+  let song_ids: HashMap<PathBuf, String> = library
+    .songs
+    .iter()
+    .map(|(id, song)| (song.path.clone(), id.clone()))
+    .collect();
+  let history = read_history();
+  for event in history.iter() {
+    if let Some(song_id) = song_ids.get(&event.path) {
+      count_event(library, song_id, event);
+    }
+  }
+  library.history = Arc::new(history);
+}
+
+pub fn record(
+  library: &mut LibraryGuard,
+  song_id: &str,
+  kind: HistoryEventKind,
+  timestamp: u32,
+  listened: u32,
+  scope: HistoryScope,
+) {
+  let path = match library.songs.get(song_id) {
+    Some(song) => song.path.clone(),
+    None => return,
+  };
+  let event = HistoryEvent {
+    path,
+    kind,
+    timestamp,
+    listened,
+    scope,
+  };
+  count_event(library, song_id, &event);
+  let history_file_path = get_history_file_path();
+  create_dir_all(history_file_path.parent().unwrap()).unwrap();
+  let mut history_file = OpenOptions::new()
+    .create(true)
+    .append(true)
+    .open(history_file_path)
+    .unwrap();
+  writeln!(history_file, "{}", serde_json::to_string(&event).unwrap()).unwrap();
+  Arc::make_mut(&mut library.history).push(event);
+  library.mark_songs_changed([song_id.to_string()]);
+}
+
+// Merges imported events into the history, skipping the ones it already has
+pub fn import_events(library: &mut LibraryGuard, events: Vec<HistoryEvent>) -> u32 {
+  let mut history = library.history.to_vec();
+  let mut known_events: HashSet<(PathBuf, u32)> = history
+    .iter()
+    .map(|event| (event.path.clone(), event.timestamp))
+    .collect();
+  let song_ids: HashMap<PathBuf, String> = library
+    .songs
+    .iter()
+    .map(|(id, song)| (song.path.clone(), id.clone()))
+    .collect();
+  let mut imported = 0;
+  for event in events {
+    if !known_events.insert((event.path.clone(), event.timestamp)) {
+      continue;
+    }
+    if let Some(song_id) = song_ids.get(&event.path) {
+      count_event(library, song_id, &event);
+      library.mark_songs_changed([song_id.clone()]);
+    }
+    history.push(event);
+    imported += 1;
+  }
+  // Imported events are older, the log is rewritten to stay in chronological order
+  history.sort_by_key(|event| event.timestamp);
+  let history_file_path = get_history_file_path();
+  create_dir_all(history_file_path.parent().unwrap()).unwrap();
+  let mut history_file = BufWriter::new(File::create(history_file_path).unwrap());
+  for event in history.iter() {
+    writeln!(history_file, "{}", serde_json::to_string(event).unwrap()).unwrap();
+  }
+  library.history = Arc::new(history);
+  imported
+}
+
+// Newest first, `from` and `to` are unix timestamps
+pub fn get_history(library: &Library, input: HistoryInput) -> HistoryPage {
+  let song_ids: HashMap<&PathBuf, &String> = library
+    .songs
+    .iter()
+    .map(|(id, song)| (&song.path, id))
+    .collect();
+  let events: Vec<&HistoryEvent> = library
+    .history
+    .iter()
+    .rev()
+    .filter(|event| input.from.map_or(true, |from| event.timestamp >= from))
+    .filter(|event| input.to.map_or(true, |to| event.timestamp < to))
+    .collect();
+  HistoryPage {
+    total: events.len() as u32,
+    entries: events
+      .into_iter()
+      .skip(input.offset as usize)
+      .take(input.limit as usize)
+      .map(|event| HistoryEntry {
+        song_id: song_ids.get(&event.path).map(|id| id.to_string()),
+        path: event.path.clone(),
+        kind: event.kind.clone(),
+        timestamp: event.timestamp,
+        listened: event.listened,
+        scope: event.scope.clone(),
+      })
+      .collect(),
+  }
+}
+
+fn get_history_scope(scope: &PlayerScope, library: &Library) -> HistoryScope {
+  match scope {
+    PlayerScope::Library => HistoryScope::Library,
+    PlayerScope::Album(album_id) => library
+      .albums
+      .get(album_id)
+      .map(|album| HistoryScope::Album(album.name.clone()))
+      .unwrap_or(HistoryScope::Library),
+    PlayerScope::Artist(artist_id) => library
+      .artists
+      .get(artist_id)
+      .map(|artist| HistoryScope::Artist(artist.name.clone()))
+      .unwrap_or(HistoryScope::Library),
+    PlayerScope::Playlist(playlist_id) => library
+      .playlists
+      .get(playlist_id)
+      .map(|playlist| HistoryScope::Playlist(playlist.name.clone()))
+      .unwrap_or(HistoryScope::Library),
+    PlayerScope::Favorites => HistoryScope::Favorites,
+    PlayerScope::Rating(min_rating) => HistoryScope::Rating(*min_rating),
+    PlayerScope::Genre(genre) => HistoryScope::Genre(genre.clone()),
+    PlayerScope::Year(year) => HistoryScope::Year(*year),
+    PlayerScope::Decade(decade) => HistoryScope::Decade(*decade),
+    PlayerScope::Folder(folder) => HistoryScope::Folder(folder.clone()),
+  }
+}
+
+// Songs left before they count as played are recorded as skips
+fn finish_play(ctx: &Context, play: Play) {
+  if play.listened == 0 {
+    return;
+  }
+  let mut library = ctx.library.lock().unwrap();
+  let duration = match library.songs.get(&play.song_id) {
+    Some(song) => song.duration,
+    None => return,
+  };
+  let kind = if is_listened(duration, play.listened) {
+    HistoryEventKind::Play
+  } else {
+    HistoryEventKind::Skip
+  };
+  record(
+    &mut library,
+    &play.song_id,
+    kind,
+    play.started_at,
+    play.listened,
+    play.scope,
+  );
+}
+
+// Watches the player and records every song once another one starts
+pub fn start(ctx: Context) {
+  tauri::async_runtime::spawn(async move {
+    let mut play: Option<Play> = None;
+    let mut last_check = get_current_time();
+    let mut ticker = interval(Duration::from_secs(1));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+      ticker.tick().await;
+      let now = get_current_time();
+      let (song_id, position, is_paused, scope) = {
+        let state = ctx.player_state.lock().unwrap();
+        let library = ctx.library.lock().unwrap();
+        (
+          state.current_song.clone(),
+          get_position(&state),
+          state.paused_at.is_some(),
+          get_history_scope(&state.scope, &library),
+        )
+      };
+      let is_new_play = match (&play, &song_id) {
+        // Repeating a song shows up as its position going back to the start
+        (Some(play), Some(song_id)) => {
+          &play.song_id != song_id || (position < play.position && position <= 1)
+        }
+        (None, Some(_)) => true,
+        (_, None) => true,
+      };
+      if is_new_play {
+        if let Some(play) = play.take() {
+          finish_play(&ctx, play);
+        }
+        play = song_id.map(|song_id| Play {
+          song_id,
+          scope,
+          started_at: now - position,
+          position,
+          listened: 0,
+        });
+      }
+      if let Some(play) = play.as_mut() {
+        if !is_paused {
+          play.listened += now.saturating_sub(last_check);
+        }
+        play.position = position;
+      }
+      last_check = now;
+    }
+  });
+}
@@ -0,0 +1,978 @@
+use crate::{
+  history::{import_events, is_listened, HistoryEvent, HistoryEventKind, HistoryScope},
+  library::{Library, LibraryGuard},
+  playlist::create_playlist,
+  query::get_fuse,
+  search::{fold, DocumentKind, SearchIndex},
+  Context,
+};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use directories::{BaseDirs, UserDirs};
+use fuse_rust::Fuse;
+use roxmltree::{Document, Node};
+use rspc::{Router, RouterBuilder, Type};
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+  collections::HashMap,
+  ffi::OsStr,
+  fs::{self, File},
+  path::{Path, PathBuf},
+};
+use tauri::api::dialog::blocking::FileDialogBuilder;
+use url::Url;
+
+// How far apart in seconds durations can be when the export has them
+const DURATION_TOLERANCE: u32 = 10;
+// Spotify counts a stream after 30 seconds
+const SPOTIFY_MIN_PLAYED: u32 = 30;
+
+#[derive(Deserialize, Type)]
+enum HistorySource {
+  Spotify,
+  LastFm,
+  ListenBrainz,
+}
+
+#[derive(Serialize, Type)]
+struct UnmatchedEntry {
+  artist: String,
+  album: Option<String>,
+  title: String,
+  count: u32,
+}
+
+#[derive(Serialize, Type, Default)]
+struct ImportResult {
+  message: String,
+  imported: u32,
+  // Entries that were already in the history
+  duplicates: u32,
+  unmatched: Vec<UnmatchedEntry>,
+}
+
+#[derive(Deserialize, Type)]
+enum PlaylistSource {
+  Exportify,
+  Rhythmbox,
+  Clementine,
+  Strawberry,
+  ITunes,
+}
+
+#[derive(Serialize, Type)]
+struct ImportedPlaylistSummary {
+  id: String,
+  name: String,
+  songs: u32,
+  missing: u32,
+}
+
+#[derive(Serialize, Type)]
+struct MissingTrack {
+  playlist: String,
+  artist: String,
+  album: Option<String>,
+  title: String,
+  // A yt-dlp search that can be passed to the downloader
+  download_query: String,
+}
+
+#[derive(Serialize, Type, Default)]
+struct PlaylistImportResult {
+  message: String,
+  playlists: Vec<ImportedPlaylistSummary>,
+  missing: Vec<MissingTrack>,
+}
+
+pub struct TrackQuery {
+  pub artist: String,
+  pub album: Option<String>,
+  pub title: String,
+  pub duration: Option<u32>,
+}
+
+struct ImportedListen {
+  track: TrackQuery,
+  timestamp: u32,
+  // None when the export only has scrobbles, which are full plays
+  listened: Option<u32>,
+  is_skipped: bool,
+}
+
+// Tracks have a file, tags, or both depending on the player
+struct PlaylistTrack {
+  path: Option<PathBuf>,
+  query: Option<TrackQuery>,
+}
+
+struct ImportedPlaylist {
+  name: String,
+  tracks: Vec<PlaylistTrack>,
+}
+
+// The name, the matched songs and how many tracks couldn't be found
+type MatchedPlaylist = (String, Vec<String>, u32);
+
+struct IndexedSong<'a> {
+  id: &'a String,
+  title: String,
+  album: String,
+  duration: u32,
+}
+
+// Features and remaster notes are dropped, and only letters and digits are compared
+fn normalize(text: &str) -> String {
+  let text = fold(text).replace('&', " and ");
+  let text = text.split(" - ").next().unwrap_or_default();
+  let mut normalized = String::new();
+  let mut depth = 0;
+  for c in text.chars() {
+    match c {
+      '(' | '[' => depth += 1,
+      ')' | ']' => depth = (depth - 1).max(0),
+      _ if depth > 0 => {}
+      c if c.is_alphanumeric() => normalized.push(c),
+      _ if !normalized.ends_with(' ') => normalized.push(' '),
+      _ => {}
+    }
+  }
+  let normalized = normalized.trim();
+  normalized
+    .strip_prefix("the ")
+    .unwrap_or(normalized)
+    .to_string()
+}
+
+// Only the main artist is compared, services disagree on how to list the others
+fn normalize_artist(artist: &str) -> String {
+  let artist = fold(artist);
+  let main_artist = [",", ";", " feat", " ft.", " & ", " x ", " and "]
+    .iter()
+    .filter_map(|separator| artist.find(separator))
+    .min()
+    .map(|end| &artist[..end])
+    .unwrap_or(&artist);
+  normalize(main_artist)
+}
+
+// Fuse finds the pattern anywhere in the text, so it's run both ways to compare whole names.
+// 1 is the same name, names under the fuse threshold are None
+fn get_similarity(fuse: &Fuse, a: &str, b: &str) -> Option<f64> {
+  if a.is_empty() || b.is_empty() {
+    return None;
+  }
+  let a_in_b = fuse.search_text_in_string(a, b)?.score;
+  let b_in_a = fuse.search_text_in_string(b, a)?.score;
+  Some(1.0 - a_in_b.max(b_in_a))
+}
+
+pub struct SongMatcher<'a> {
+  fuse: Fuse,
+  search_index: &'a SearchIndex,
+  artists: HashMap<&'a String, String>,
+  songs_by_artist: HashMap<&'a String, Vec<IndexedSong<'a>>>,
+  // By normalized artist name, the library's artists that are similar enough
+  artist_cache: HashMap<String, Vec<(&'a String, f64)>>,
+  cache: HashMap<(String, String, String), Option<String>>,
+}
+
+impl<'a> SongMatcher<'a> {
+  pub fn new(library: &'a Library) -> Self {
+    let mut songs_by_artist: HashMap<&String, Vec<IndexedSong>> = HashMap::new();
+    for (id, song) in library.songs.iter() {
+      let album = library.albums.get(&song.album).unwrap();
+      songs_by_artist
+        .entry(&album.artist)
+        .or_default()
+        .push(IndexedSong {
+          id,
+          title: normalize(&song.title),
+          album: normalize(&album.name),
+          duration: song.duration,
+        });
+    }
+    SongMatcher {
+      fuse: get_fuse(),
+      search_index: &library.search_index,
+      artists: library
+        .artists
+        .iter()
+        .map(|(id, artist)| (id, normalize_artist(&artist.name)))
+        .collect(),
+      songs_by_artist,
+      artist_cache: HashMap::new(),
+      cache: HashMap::new(),
+    }
+  }
+
+  // Only artists sharing enough trigrams with the name are compared with fuse
+  fn find_artists(&mut self, artist: &str) -> Vec<(&'a String, f64)> {
+    if let Some(artists) = self.artist_cache.get(artist) {
+      return artists.clone();
+    }
+    let search_index = self.search_index;
+    let artists: Vec<(&'a String, f64)> = search_index
+      .search(artist, false)
+      .into_iter()
+      .filter(|hit| hit.kind == DocumentKind::Artist)
+      .filter_map(|hit| {
+        let artist_name = self.artists.get(hit.id)?;
+        Some((hit.id, get_similarity(&self.fuse, artist, artist_name)?))
+      })
+      .collect();
+    self
+      .artist_cache
+      .insert(artist.to_string(), artists.clone());
+    artists
+  }
+
+  // Returns the song that is most similar, if any is similar enough
+  pub fn find(&mut self, track: &TrackQuery) -> Option<String> {
+    let artist = normalize_artist(&track.artist);
+    let title = normalize(&track.title);
+    let album = track.album.as_deref().map(normalize).unwrap_or_default();
+    let key = (artist, title, album);
+    if let Some(song_id) = self.cache.get(&key) {
+      return song_id.clone();
+    }
+    let (artist, title, album) = &key;
+    let mut best_match: Option<(f64, &String)> = None;
+    for (artist_id, artist_similarity) in self.find_artists(artist) {
+      for song in self.songs_by_artist.get(artist_id).into_iter().flatten() {
+        let title_similarity = match get_similarity(&self.fuse, title, &song.title) {
+          Some(similarity) => similarity,
+          None => continue,
+        };
+        if let Some(duration) = track.duration {
+          if song.duration.abs_diff(duration) > DURATION_TOLERANCE {
+            continue;
+          }
+        }
+        // The album only breaks ties between versions of a song
+        let album_similarity = get_similarity(&self.fuse, album, &song.album).unwrap_or(0.0);
+        let score = title_similarity * 2.0 + artist_similarity + album_similarity * 0.5;
+        if best_match.map_or(true, |(best_score, _)| score > best_score) {
+          best_match = Some((score, song.id));
+        }
+      }
+    }
+    let song_id = best_match.map(|(_, song_id)| song_id.clone());
+    self.cache.insert(key, song_id.clone());
+    song_id
+  }
+}
+
+fn get_str(value: &Value, pointer: &str) -> Option<String> {
+  value
+    .pointer(pointer)
+    .and_then(Value::as_str)
+    .filter(|text| !text.is_empty())
+    .map(|text| text.to_string())
+}
+
+fn parse_utc(text: &str, format: &str) -> Option<u32> {
+  NaiveDateTime::parse_from_str(text, format)
+    .ok()
+    .map(|time| Utc.from_utc_datetime(&time).timestamp() as u32)
+}
+
+// Both the extended streaming history and the older account data export
+fn read_spotify(path: &Path) -> Result<Vec<ImportedListen>, String> {
+  let entries: Vec<Value> = serde_json::from_reader(File::open(path).map_err(|e| e.to_string())?)
+    .map_err(|e| e.to_string())?;
+  Ok(
+    entries
+      .iter()
+      .filter_map(|entry| {
+        let (artist, album, title, end_time) = match entry.get("ts") {
+          Some(_) => (
+            get_str(entry, "/master_metadata_album_artist_name")?,
+            get_str(entry, "/master_metadata_album_album_name"),
+            get_str(entry, "/master_metadata_track_name")?,
+            DateTime::parse_from_rfc3339(&get_str(entry, "/ts")?)
+              .ok()?
+              .timestamp() as u32,
+          ),
+          None => (
+            get_str(entry, "/artistName")?,
+            None,
+            get_str(entry, "/trackName")?,
+            parse_utc(&get_str(entry, "/endTime")?, "%Y-%m-%d %H:%M")?,
+          ),
+        };
+        let played = entry
+          .get("ms_played")
+          .or_else(|| entry.get("msPlayed"))
+          .and_then(Value::as_u64)
+          .unwrap_or(0) as u32
+          / 1000;
+        Some(ImportedListen {
+          track: TrackQuery {
+            artist,
+            album,
+            title,
+            duration: None,
+          },
+          // Spotify saves when a song stopped playing
+          timestamp: end_time.saturating_sub(played),
+          listened: Some(played),
+          is_skipped: entry
+            .get("skipped")
+            .and_then(Value::as_bool)
+            .unwrap_or(false)
+            || played < SPOTIFY_MIN_PLAYED,
+        })
+      })
+      .collect(),
+  )
+}
+
+fn read_lastfm_track(track: &Value) -> Option<ImportedListen> {
+  // Tracks that were playing while exporting don't have a date yet
+  let timestamp = get_str(track, "/date/uts")?.parse().ok()?;
+  Some(ImportedListen {
+    track: TrackQuery {
+      artist: get_str(track, "/artist/#text").or_else(|| get_str(track, "/artist/name"))?,
+      album: get_str(track, "/album/#text"),
+      title: get_str(track, "/name")?,
+      duration: None,
+    },
+    timestamp,
+    listened: None,
+    is_skipped: false,
+  })
+}
+
+// CSV exports have artist, album, title and date columns without a header,
+// JSON exports are the pages returned by user.getRecentTracks
+fn read_lastfm(path: &Path) -> Result<Vec<ImportedListen>, String> {
+  if path
+    .extension()
+    .map_or(false, |extension| extension == "csv")
+  {
+    let mut reader = csv::ReaderBuilder::new()
+      .has_headers(false)
+      .flexible(true)
+      .from_path(path)
+      .map_err(|e| e.to_string())?;
+    return Ok(
+      reader
+        .records()
+        .flatten()
+        .filter_map(|record| {
+          Some(ImportedListen {
+            track: TrackQuery {
+              artist: record.get(0)?.to_string(),
+              album: record
+                .get(1)
+                .filter(|album| !album.is_empty())
+                .map(|album| album.to_string()),
+              title: record.get(2)?.to_string(),
+              duration: None,
+            },
+            timestamp: parse_utc(record.get(3)?, "%d %b %Y %H:%M")?,
+            listened: None,
+            is_skipped: false,
+          })
+        })
+        .collect(),
+    );
+  }
+  let export: Value = serde_json::from_reader(File::open(path).map_err(|e| e.to_string())?)
+    .map_err(|e| e.to_string())?;
+  let pages = match export {
+    Value::Array(pages) => pages,
+    page => vec![page],
+  };
+  Ok(
+    pages
+      .iter()
+      .flat_map(|page| {
+        match page
+          .pointer("/recenttracks/track")
+          .or_else(|| page.get("track"))
+        {
+          Some(Value::Array(tracks)) => tracks.iter().collect(),
+          // Some exporters flatten the pages into a list of tracks
+          _ => vec![page],
+        }
+      })
+      .filter_map(read_lastfm_track)
+      .collect(),
+  )
+}
+
+fn read_listenbrainz_listen(listen: &Value) -> Option<ImportedListen> {
+  Some(ImportedListen {
+    track: TrackQuery {
+      artist: get_str(listen, "/track_metadata/artist_name")?,
+      album: get_str(listen, "/track_metadata/release_name"),
+      title: get_str(listen, "/track_metadata/track_name")?,
+      duration: listen
+        .pointer("/track_metadata/additional_info/duration_ms")
+        .and_then(Value::as_u64)
+        .map(|duration| (duration / 1000) as u32),
+    },
+    timestamp: listen.get("listened_at")?.as_u64()? as u32,
+    listened: None,
+    is_skipped: false,
+  })
+}
+
+// The user export is a JSON array, full dumps have one listen per line
+fn read_listenbrainz(path: &Path) -> Result<Vec<ImportedListen>, String> {
+  let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+  let listens: Vec<Value> = match serde_json::from_str(&contents) {
+    Ok(Value::Array(listens)) => listens,
+    _ => contents
+      .lines()
+      .filter_map(|line| serde_json::from_str(line).ok())
+      .collect(),
+  };
+  Ok(
+    listens
+      .iter()
+      .filter_map(read_listenbrainz_listen)
+      .collect(),
+  )
+}
+
+// Runs on a copy of the library, so it isn't locked while comparing names
+fn match_history(
+  library: &Library,
+  source: &HistorySource,
+  paths: &[PathBuf],
+) -> Result<(Vec<HistoryEvent>, Vec<UnmatchedEntry>), String> {
+  let mut listens = Vec::new();
+  for path in paths {
+    listens.extend(match source {
+      HistorySource::Spotify => read_spotify(path),
+      HistorySource::LastFm => read_lastfm(path),
+      HistorySource::ListenBrainz => read_listenbrainz(path),
+    }?);
+  }
+  let source_name = match source {
+    HistorySource::Spotify => "Spotify",
+    HistorySource::LastFm => "Last.fm",
+    HistorySource::ListenBrainz => "ListenBrainz",
+  };
+  let mut events = Vec::new();
+  let mut unmatched: HashMap<(String, Option<String>, String), u32> = HashMap::new();
+  let mut matcher = SongMatcher::new(library);
+  for listen in listens {
+    let song = match matcher.find(&listen.track) {
+      Some(song_id) => library.songs.get(&song_id).unwrap(),
+      None => {
+        let track = listen.track;
+        *unmatched
+          .entry((track.artist, track.album, track.title))
+          .or_default() += 1;
+        continue;
+      }
+    };
+    let listened = listen.listened.unwrap_or(song.duration);
+    events.push(HistoryEvent {
+      path: song.path.clone(),
+      kind: if !listen.is_skipped && is_listened(song.duration, listened) {
+        HistoryEventKind::Play
+      } else {
+        HistoryEventKind::Skip
+      },
+      timestamp: listen.timestamp,
+      listened,
+      scope: HistoryScope::Imported(source_name.to_string()),
+    });
+  }
+  let mut unmatched: Vec<UnmatchedEntry> = unmatched
+    .into_iter()
+    .map(|((artist, album, title), count)| UnmatchedEntry {
+      artist,
+      album,
+      title,
+      count,
+    })
+    .collect();
+  unmatched.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.artist.cmp(&b.artist)));
+  Ok((events, unmatched))
+}
+
+fn import_history(
+  library: &mut LibraryGuard,
+  events: Vec<HistoryEvent>,
+  unmatched: Vec<UnmatchedEntry>,
+) -> ImportResult {
+  let matched = events.len() as u32;
+  let imported = import_events(library, events);
+  ImportResult {
+    message: format!(
+      "Imported {} plays, {} could not be found in the library",
+      imported,
+      unmatched.iter().map(|entry| entry.count).sum::<u32>()
+    ),
+    imported,
+    duplicates: matched - imported,
+    unmatched,
+  }
+}
+
+fn file_url_to_path(location: &str) -> Option<PathBuf> {
+  Url::parse(location).ok()?.to_file_path().ok()
+}
+
+// Exportify names the files after the playlists
+fn read_exportify(path: &Path) -> Result<ImportedPlaylist, String> {
+  let mut reader = csv::Reader::from_path(path).map_err(|e| e.to_string())?;
+  let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+  let column = |names: &[&str]| headers.iter().position(|header| names.contains(&header));
+  let (title_column, artist_column) = match (
+    column(&["Track Name"]),
+    column(&["Artist Name(s)", "Artist Name"]),
+  ) {
+    (Some(title_column), Some(artist_column)) => (title_column, artist_column),
+    _ => return Err("Not an Exportify playlist".to_string()),
+  };
+  let album_column = column(&["Album Name"]);
+  let duration_column = column(&["Duration (ms)", "Track Duration (ms)"]);
+  let tracks = reader
+    .records()
+    .flatten()
+    .map(|record| PlaylistTrack {
+      path: None,
+      query: Some(TrackQuery {
+        artist: record.get(artist_column).unwrap_or_default().to_string(),
+        album: album_column
+          .and_then(|column| record.get(column))
+          .filter(|album| !album.is_empty())
+          .map(|album| album.to_string()),
+        title: record.get(title_column).unwrap_or_default().to_string(),
+        duration: duration_column
+          .and_then(|column| record.get(column))
+          .and_then(|duration| duration.parse::<u32>().ok())
+          .map(|duration| duration / 1000),
+      }),
+    })
+    .collect();
+  Ok(ImportedPlaylist {
+    name: path
+      .file_stem()
+      .map(|name| name.to_string_lossy().replace('_', " "))
+      .unwrap_or_default(),
+    tracks,
+  })
+}
+
+// Automatic playlists and the play queue are skipped, only static playlists have songs
+fn read_rhythmbox(path: &Path) -> Result<Vec<ImportedPlaylist>, String> {
+  let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+  let document = Document::parse(&contents).map_err(|e| e.to_string())?;
+  Ok(
+    document
+      .root_element()
+      .children()
+      .filter(|node| node.has_tag_name("playlist") && node.attribute("type") == Some("static"))
+      .map(|node| ImportedPlaylist {
+        name: node.attribute("name").unwrap_or("Rhythmbox").to_string(),
+        tracks: node
+          .children()
+          .filter(|child| child.has_tag_name("location"))
+          .filter_map(|child| child.text())
+          .map(|location| PlaylistTrack {
+            path: file_url_to_path(location),
+            query: None,
+          })
+          .collect(),
+      })
+      .collect(),
+  )
+}
+
+// Both databases have the tags of library songs in their songs table, which Strawberry calls
+// collection_songs, and the ones of other files in the playlist items
+fn read_clementine(path: &Path, is_strawberry: bool) -> Result<Vec<ImportedPlaylist>, String> {
+  let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+    .map_err(|e| e.to_string())?;
+  let (songs_table, song_id_column, song_url_column) = if is_strawberry {
+    ("collection_songs", "collection_id", "url")
+  } else {
+    ("songs", "library_id", "filename")
+  };
+  let mut statement = connection
+    .prepare(&format!(
+      "SELECT playlist_items.playlist, playlists.name,
+        COALESCE(songs.{url}, playlist_items.url),
+        COALESCE(songs.artist, playlist_items.artist),
+        COALESCE(songs.album, playlist_items.album),
+        COALESCE(songs.title, playlist_items.title),
+        COALESCE(songs.length, playlist_items.length)
+      FROM playlist_items
+      JOIN playlists ON playlists.ROWID = playlist_items.playlist
+      LEFT JOIN {table} AS songs ON songs.ROWID = playlist_items.{id}
+      ORDER BY playlist_items.playlist, playlist_items.ROWID",
+      table = songs_table,
+      url = song_url_column,
+      id = song_id_column,
+    ))
+    .map_err(|e| e.to_string())?;
+  let mut rows = statement.query([]).map_err(|e| e.to_string())?;
+  let mut playlists: Vec<(i64, ImportedPlaylist)> = Vec::new();
+  while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+    let text = |index: usize| {
+      row
+        .get::<_, Option<String>>(index)
+        .ok()
+        .flatten()
+        .filter(|text| !text.is_empty())
+    };
+    let playlist_id: i64 = row.get(0).map_err(|e| e.to_string())?;
+    let track = PlaylistTrack {
+      path: text(2).and_then(|location| file_url_to_path(&location)),
+      query: text(5).map(|title| TrackQuery {
+        artist: text(3).unwrap_or_default(),
+        album: text(4),
+        title,
+        // Lengths are saved in nanoseconds
+        duration: row
+          .get::<_, Option<i64>>(6)
+          .ok()
+          .flatten()
+          .filter(|length| *length > 0)
+          .map(|length| (length / 1_000_000_000) as u32),
+      }),
+    };
+    match playlists.last_mut() {
+      Some((id, playlist)) if *id == playlist_id => playlist.tracks.push(track),
+      _ => playlists.push((
+        playlist_id,
+        ImportedPlaylist {
+          name: text(1).unwrap_or_else(|| "Playlist".to_string()),
+          tracks: vec![track],
+        },
+      )),
+    }
+  }
+  Ok(
+    playlists
+      .into_iter()
+      .map(|(_, playlist)| playlist)
+      .collect(),
+  )
+}
+
+// Property lists are converted to JSON so they can be read like the other exports
+fn read_plist_value(node: Node) -> Value {
+  match node.tag_name().name() {
+    "dict" => {
+      let mut object = Map::new();
+      let mut key = None;
+      for child in node.children().filter(|child| child.is_element()) {
+        if child.has_tag_name("key") {
+          key = child.text().map(|key| key.to_string());
+        } else if let Some(key) = key.take() {
+          object.insert(key, read_plist_value(child));
+        }
+      }
+      Value::Object(object)
+    }
+    "array" => Value::Array(
+      node
+        .children()
+        .filter(|child| child.is_element())
+        .map(read_plist_value)
+        .collect(),
+    ),
+    "integer" => node
+      .text()
+      .and_then(|number| number.parse::<i64>().ok())
+      .map(Value::from)
+      .unwrap_or(Value::Null),
+    "true" => Value::Bool(true),
+    "false" => Value::Bool(false),
+    _ => node.text().map(Value::from).unwrap_or(Value::Null),
+  }
+}
+
+// The library itself, folders and the built-in playlists like Music or Podcasts are skipped
+fn read_itunes(path: &Path) -> Result<Vec<ImportedPlaylist>, String> {
+  let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+  let document = Document::parse(&contents).map_err(|e| e.to_string())?;
+  let library = document
+    .root_element()
+    .children()
+    .find(|node| node.is_element())
+    .map(read_plist_value)
+    .ok_or_else(|| "Not an iTunes library".to_string())?;
+  let tracks = &library["Tracks"];
+  Ok(
+    library["Playlists"]
+      .as_array()
+      .into_iter()
+      .flatten()
+      .filter(|playlist| {
+        !playlist["Master"].as_bool().unwrap_or(false)
+          && !playlist["Folder"].as_bool().unwrap_or(false)
+          && playlist["Visible"].as_bool().unwrap_or(true)
+          && playlist.get("Distinguished Kind").is_none()
+      })
+      .map(|playlist| ImportedPlaylist {
+        name: get_str(playlist, "/Name").unwrap_or_else(|| "iTunes".to_string()),
+        tracks: playlist["Playlist Items"]
+          .as_array()
+          .into_iter()
+          .flatten()
+          .filter_map(|item| item["Track ID"].as_i64())
+          .filter_map(|track_id| tracks.get(track_id.to_string()))
+          .map(|track| PlaylistTrack {
+            path: track["Location"].as_str().and_then(file_url_to_path),
+            query: get_str(track, "/Name").map(|title| TrackQuery {
+              artist: get_str(track, "/Artist").unwrap_or_default(),
+              album: get_str(track, "/Album"),
+              title,
+              duration: track["Total Time"]
+                .as_u64()
+                .map(|duration| (duration / 1000) as u32),
+            }),
+          })
+          .collect(),
+      })
+      .collect(),
+  )
+}
+
+fn read_playlists(
+  source: &PlaylistSource,
+  paths: &[PathBuf],
+) -> Result<Vec<ImportedPlaylist>, String> {
+  let mut playlists = Vec::new();
+  for path in paths {
+    match source {
+      PlaylistSource::Exportify => playlists.push(read_exportify(path)?),
+      PlaylistSource::Rhythmbox => playlists.extend(read_rhythmbox(path)?),
+      PlaylistSource::Clementine => playlists.extend(read_clementine(path, false)?),
+      PlaylistSource::Strawberry => playlists.extend(read_clementine(path, true)?),
+      PlaylistSource::ITunes => playlists.extend(read_itunes(path)?),
+    }
+  }
+  Ok(playlists)
+}
+
+// Songs are found by path first, then by file name in case the music was moved,
+// and then by their tags. Runs on a copy of the library like the history
+fn match_playlists(
+  library: &Library,
+  playlists: Vec<ImportedPlaylist>,
+) -> (Vec<MatchedPlaylist>, Vec<MissingTrack>) {
+  let mut matched_playlists = Vec::new();
+  let mut missing = Vec::new();
+  let song_ids: HashMap<&PathBuf, &String> = library
+    .songs
+    .iter()
+    .map(|(id, song)| (&song.path, id))
+    .collect();
+  // File names shared by several songs can't be used to find them
+  let mut song_ids_by_name: HashMap<&OsStr, Option<&String>> = HashMap::new();
+  for (id, song) in library.songs.iter() {
+    if let Some(name) = song.path.file_name() {
+      song_ids_by_name
+        .entry(name)
+        .and_modify(|song_id| *song_id = None)
+        .or_insert(Some(id));
+    }
+  }
+  let mut matcher = SongMatcher::new(library);
+  for playlist in playlists {
+    let mut songs = Vec::new();
+    let mut missing_count = 0;
+    for track in playlist.tracks {
+      let song_id = track
+        .path
+        .as_ref()
+        .and_then(|path| {
+          song_ids.get(path).or_else(|| {
+            path
+              .file_name()
+              .and_then(|name| song_ids_by_name.get(name))
+              .and_then(|song_id| song_id.as_ref())
+          })
+        })
+        .map(|song_id| song_id.to_string())
+        .or_else(|| track.query.as_ref().and_then(|query| matcher.find(query)));
+      if let Some(song_id) = song_id {
+        songs.push(song_id);
+        continue;
+      }
+      missing_count += 1;
+      let (artist, album, title) = match track.query {
+        Some(query) => (query.artist, query.album, query.title),
+        None => (
+          String::new(),
+          None,
+          track
+            .path
+            .as_ref()
+            .and_then(|path| path.file_stem())
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        ),
+      };
+      missing.push(MissingTrack {
+        playlist: playlist.name.clone(),
+        download_query: if artist.is_empty() {
+          format!("ytsearch1:{}", title)
+        } else {
+          format!("ytsearch1:{} - {}", artist, title)
+        },
+        artist,
+        album,
+        title,
+      });
+    }
+    matched_playlists.push((playlist.name, songs, missing_count));
+  }
+  (matched_playlists, missing)
+}
+
+fn import_playlists(
+  library: &mut LibraryGuard,
+  matched_playlists: Vec<MatchedPlaylist>,
+  missing: Vec<MissingTrack>,
+) -> PlaylistImportResult {
+  let mut imported_playlists = Vec::new();
+  for (name, mut songs, missing_count) in matched_playlists {
+    // Songs removed by a scan while matching are left out
+    songs.retain(|song_id| library.songs.contains_key(song_id));
+    // Existing playlists are never replaced
+    let mut unique_name = name.clone();
+    let mut copy = 1;
+    while library
+      .playlists
+      .values()
+      .any(|playlist| playlist.name == unique_name)
+    {
+      copy += 1;
+      unique_name = format!("{} ({})", name, copy);
+    }
+    let song_count = songs.len() as u32;
+    imported_playlists.push(ImportedPlaylistSummary {
+      id: create_playlist(library, unique_name.clone(), songs),
+      name: unique_name,
+      songs: song_count,
+      missing: missing_count,
+    });
+  }
+  library.mark_changed();
+  PlaylistImportResult {
+    message: format!(
+      "Imported {} playlists, {} songs could not be found in the library",
+      imported_playlists.len(),
+      missing.len()
+    ),
+    playlists: imported_playlists,
+    missing,
+  }
+}
+
+// Where the players keep their playlists, if they are installed
+fn get_default_directory(source: &PlaylistSource) -> Option<PathBuf> {
+  let base_dirs = BaseDirs::new()?;
+  let directory = match source {
+    PlaylistSource::Exportify => UserDirs::new()?.download_dir()?.to_path_buf(),
+    PlaylistSource::Rhythmbox => base_dirs.data_dir().join("rhythmbox"),
+    PlaylistSource::Clementine => base_dirs.config_dir().join("Clementine"),
+    PlaylistSource::Strawberry => base_dirs.data_dir().join("strawberry").join("strawberry"),
+    PlaylistSource::ITunes => UserDirs::new()?.audio_dir()?.join("iTunes"),
+  };
+  Some(directory).filter(|directory| directory.is_dir())
+}
+
+pub fn get_router() -> RouterBuilder<Context> {
+  Router::<Context>::new()
+    .mutation("playlists", |t| {
+      t(|ctx, input: PlaylistSource| async move {
+        if ctx.daemon_url.is_some() {
+          return PlaylistImportResult {
+            message: "Playlists can only be imported from the window".to_string(),
+            ..Default::default()
+          };
+        }
+        let mut dialog = FileDialogBuilder::new();
+        dialog = match input {
+          PlaylistSource::Exportify => dialog.add_filter("Exportify playlists", &["csv"]),
+          PlaylistSource::Rhythmbox => dialog.add_filter("Rhythmbox playlists", &["xml"]),
+          PlaylistSource::Clementine => dialog.add_filter("Clementine database", &["db"]),
+          PlaylistSource::Strawberry => dialog.add_filter("Strawberry database", &["db"]),
+          PlaylistSource::ITunes => dialog.add_filter("iTunes library", &["xml"]),
+        };
+        if let Some(directory) = get_default_directory(&input) {
+          dialog = dialog.set_directory(directory);
+        }
+        let paths = match dialog.pick_files() {
+          Some(paths) => paths,
+          None => {
+            return PlaylistImportResult {
+              message: "Import cancelled".to_string(),
+              ..Default::default()
+            }
+          }
+        };
+        let library = ctx.library.lock().unwrap().clone();
+        let matched = tokio::task::spawn_blocking(move || {
+          read_playlists(&input, &paths).map(|playlists| match_playlists(&library, playlists))
+        })
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+        match matched {
+          Ok((matched_playlists, missing)) => {
+            import_playlists(&mut ctx.library.lock().unwrap(), matched_playlists, missing)
+          }
+          Err(e) => PlaylistImportResult {
+            message: format!("Failed to read the playlists: {}", e),
+            ..Default::default()
+          },
+        }
+      })
+    })
+    .mutation("history", |t| {
+      t(|ctx, input: HistorySource| async move {
+        // A headless daemon has no display to show the dialog on
+        if ctx.daemon_url.is_some() {
+          return ImportResult {
+            message: "History can only be imported from the window".to_string(),
+            ..Default::default()
+          };
+        }
+        let mut dialog = FileDialogBuilder::new();
+        dialog = match input {
+          HistorySource::Spotify => dialog.add_filter("Spotify streaming history", &["json"]),
+          HistorySource::LastFm => dialog.add_filter("Last.fm export", &["csv", "json"]),
+          HistorySource::ListenBrainz => {
+            dialog.add_filter("ListenBrainz export", &["json", "jsonl"])
+          }
+        };
+        // Spotify splits the history into several files
+        let paths = match dialog.pick_files() {
+          Some(paths) => paths,
+          None => {
+            return ImportResult {
+              message: "Import cancelled".to_string(),
+              ..Default::default()
+            }
+          }
+        };
+        let library = ctx.library.lock().unwrap().clone();
+        let matched = tokio::task::spawn_blocking(move || match_history(&library, &input, &paths))
+          .await
+          .unwrap_or_else(|e| Err(e.to_string()));
+        match matched {
+          Ok((events, unmatched)) => {
+            import_history(&mut ctx.library.lock().unwrap(), events, unmatched)
+          }
+          Err(e) => ImportResult {
+            message: format!("Failed to read the export: {}", e),
+            ..Default::default()
+          },
+        }
+      })
+    })
+}
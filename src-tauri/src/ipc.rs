@@ -0,0 +1,184 @@
+use crate::{
+  library::add_external_song,
+  player::{self, get_position},
+  Context, RepeatMode,
+};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::{
+  fs::{self, create_dir_all},
+  io::{self, BufRead, BufReader, Write},
+  os::unix::net::{UnixListener, UnixStream},
+  path::PathBuf,
+  thread,
+};
+
+#[derive(Serialize, Deserialize)]
+pub enum SeekTarget {
+  Absolute(u32),
+  Forward(u32),
+  Backward(u32),
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum IpcCommand {
+  Play,
+  Pause,
+  TogglePause,
+  Next,
+  Previous,
+  Seek(SeekTarget),
+  Volume(f64),
+  QueueAdd(Vec<PathBuf>),
+  Open(Vec<PathBuf>),
+  Status,
+  GetDaemonUrl,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Status {
+  pub title: Option<String>,
+  pub album: Option<String>,
+  pub artist: Option<String>,
+  pub position: u32,
+  pub duration: u32,
+  pub is_paused: bool,
+  pub volume: f64,
+  pub repeat_mode: RepeatMode,
+  pub is_shuffled: bool,
+  pub queue_length: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum IpcResponse {
+  Ok,
+  Status(Status),
+  DaemonUrl(Option<String>),
+  Error(String),
+}
+
+pub fn get_socket_path() -> PathBuf {
+  let dirs = ProjectDirs::from("dev", "ronanru", "ronix").unwrap();
+  dirs
+    .runtime_dir()
+    .unwrap_or(dirs.cache_dir())
+    .join("ronix.sock")
+}
+
+pub fn send(command: &IpcCommand) -> io::Result<IpcResponse> {
+  let mut stream = UnixStream::connect(get_socket_path())?;
+  serde_json::to_writer(&stream, command)?;
+  stream.write_all(b"\n")?;
+  let mut line = String::new();
+  BufReader::new(&stream).read_line(&mut line)?;
+  Ok(serde_json::from_str(&line)?)
+}
+
+pub fn handle_command(ctx: &Context, command: IpcCommand) -> IpcResponse {
+  let mut state = ctx.player_state.lock().unwrap();
+  let mut library = ctx.library.lock().unwrap();
+  match command {
+    IpcCommand::Play => player::play(&mut state, &ctx.player, &ctx.fader),
+    IpcCommand::Pause => player::pause(&mut state, &ctx.player, &ctx.fader),
+    IpcCommand::TogglePause => {
+      if state.current_song.is_none() {
+        return IpcResponse::Error("Nothing is playing".to_string());
+      }
+      player::toggle_pause(&mut state, &ctx.player, &ctx.fader)
+    }
+    IpcCommand::Next => player::next_song(&mut state, &library, &ctx.player, Some(&ctx.fader)),
+    IpcCommand::Previous => player::previous_song(&mut state, &ctx.player, &ctx.fader, &library),
+    IpcCommand::Seek(target) => {
+      if state.current_song.is_none() {
+        return IpcResponse::Error("Nothing is playing".to_string());
+      }
+      let position = get_position(&state);
+      let seek_to = match target {
+        SeekTarget::Absolute(seconds) => seconds,
+        SeekTarget::Forward(seconds) => position.saturating_add(seconds),
+        SeekTarget::Backward(seconds) => position.saturating_sub(seconds),
+      };
+      player::seek(&mut state, &ctx.player, seek_to)
+    }
+    IpcCommand::Volume(volume) => ctx.player.set_volume(volume.clamp(0.0, 1.0)),
+    IpcCommand::QueueAdd(paths) | IpcCommand::Open(paths) if paths.is_empty() => {
+      return IpcResponse::Error("No files given".to_string());
+    }
+    IpcCommand::QueueAdd(paths) => {
+      let mut song_ids = Vec::new();
+      for path in paths.iter() {
+        match add_external_song(&mut library, path) {
+          Some(song_id) => song_ids.push(song_id),
+          None => return IpcResponse::Error(format!("Could not read {}", path.display())),
+        }
+      }
+      player::queue_songs(&mut state, song_ids)
+    }
+    IpcCommand::Open(paths) => {
+      let mut song_ids = Vec::new();
+      for path in paths.iter() {
+        match add_external_song(&mut library, path) {
+          Some(song_id) => song_ids.push(song_id),
+          None => return IpcResponse::Error(format!("Could not read {}", path.display())),
+        }
+      }
+      player::play_songs(&song_ids, &library, &ctx.player, &ctx.fader, &mut state)
+    }
+    IpcCommand::GetDaemonUrl => return IpcResponse::DaemonUrl(ctx.daemon_url.clone()),
+    IpcCommand::Status => {
+      let song = state
+        .current_song
+        .as_ref()
+        .and_then(|song_id| library.songs.get(song_id));
+      let album = song.and_then(|song| library.albums.get(&song.album));
+      let artist = album.and_then(|album| library.artists.get(&album.artist));
+      return IpcResponse::Status(Status {
+        title: song.map(|song| song.title.clone()),
+        album: album.map(|album| album.name.clone()),
+        artist: artist.map(|artist| artist.name.clone()),
+        position: song.map(|_| get_position(&state)).unwrap_or(0),
+        duration: song.map(|song| song.duration).unwrap_or(0),
+        is_paused: state.paused_at.is_some(),
+        volume: state.volume,
+        repeat_mode: state.repeat_mode.clone(),
+        is_shuffled: state.is_shuffled,
+        queue_length: (state.next_songs.len() + state.automatic_next_songs.len()) as u32,
+      });
+    }
+  }
+  IpcResponse::Ok
+}
+
+fn handle_connection(ctx: &Context, mut stream: UnixStream) -> io::Result<()> {
+  let mut line = String::new();
+  BufReader::new(&stream).read_line(&mut line)?;
+  let response = match serde_json::from_str::<IpcCommand>(&line) {
+    Ok(command) => handle_command(ctx, command),
+    Err(e) => IpcResponse::Error(e.to_string()),
+  };
+  serde_json::to_writer(&stream, &response)?;
+  stream.write_all(b"\n")
+}
+
+pub fn remove_socket() {
+  let _ = fs::remove_file(get_socket_path());
+}
+
+pub fn start(ctx: Context) -> io::Result<()> {
+  let socket_path = get_socket_path();
+  create_dir_all(socket_path.parent().unwrap())?;
+  // Only reached when no running instance answered, so the socket is stale
+  remove_socket();
+  let listener = UnixListener::bind(&socket_path)?;
+  thread::spawn(move || {
+    for stream in listener.incoming().flatten() {
+      let ctx = ctx.clone();
+      thread::spawn(move || {
+        if let Err(e) = handle_connection(&ctx, stream) {
+          println!("Failed to handle control connection: {}", e);
+        }
+      });
+    }
+  });
+  Ok(())
+}
@@ -1,150 +1,388 @@
-use crate::{Context, PlayerScope};
-use fuse_rust::Fuse;
-use lofty::{Accessor, AudioFile, MimeType, Probe, TagExt, TaggedFileExt};
+use crate::{
+  covers::{get_covers_dir, read_cover_art},
+  history::{self, count_history, HistoryEvent, HistoryInput},
+  paging::{self, IdsInput, LibraryChanges, PageInput},
+  playlist::{
+    read_playlists, refresh_smart_playlists, update_smart_playlists, write_playlists, Playlist,
+  },
+  query::{self, Condition, Field, Filter},
+  ratings::{self, get_shuffle_weight, read_rating_tag, read_saved_ratings},
+  scopes::ScopeIndex,
+  search::{DocumentKind, SearchIndex},
+  sort::{self, SortInput},
+  Context, PlayerScope,
+};
+use async_stream::stream;
+use lofty::{Accessor, AudioFile, ItemKey, Probe, TagExt, TaggedFileExt};
 use nanoid::nanoid;
 use rand::prelude::*;
 use rspc::{Router, RouterBuilder, Type};
 use serde::{Deserialize, Serialize};
 use std::{
-  collections::HashMap,
-  fs::{self, create_dir_all, File},
+  collections::{HashMap, HashSet},
+  fs,
   hash::Hash,
-  io::Write,
-  path::PathBuf,
+  ops::{Deref, DerefMut},
+  path::{Path, PathBuf},
   process::Command,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, LockResult, Mutex, MutexGuard, PoisonError,
+  },
+  time::{Duration, UNIX_EPOCH},
 };
+use tokio::time::sleep;
 use walkdir::WalkDir;
 
-#[derive(Serialize, Clone, Type, Hash)]
+#[derive(Serialize, Clone, Type, Hash, PartialEq)]
 pub struct Artist {
   pub name: String,
+  // From the ARTISTSORT tag
+  pub sort_name: Option<String>,
+  pub play_count: u32,
+  pub last_played: Option<u32>,
 }
 
-#[derive(Serialize, Clone, Type, Hash)]
+#[derive(Serialize, Clone, Type, Hash, PartialEq)]
 pub struct Album {
   pub name: String,
+  // From the ALBUMSORT tag
+  pub sort_name: Option<String>,
   pub cover_art: Option<String>,
   pub artist: String,
+  pub play_count: u32,
+  pub last_played: Option<u32>,
 }
 
-#[derive(Serialize, Clone, Type, Hash)]
+#[derive(Serialize, Clone, Type, Hash, PartialEq)]
 pub struct Song {
   pub title: String,
+  // From the TITLESORT tag
+  pub sort_title: Option<String>,
   pub path: PathBuf,
   pub duration: u32,
   pub album: String,
+  pub genre: Option<String>,
+  pub year: Option<u32>,
+  // When the file was created, or last modified where that isn't known
+  pub added_at: u32,
+  pub play_count: u32,
+  pub skip_count: u32,
+  pub last_played: Option<u32>,
+  pub rating: u8,
+  pub is_favorite: bool,
 }
 
-#[derive(Serialize, Clone, Type)]
+#[derive(Serialize, Clone, Type, Default)]
 pub struct Library {
   pub artists: HashMap<String, Artist>,
   pub albums: HashMap<String, Album>,
   pub songs: HashMap<String, Song>,
+  pub playlists: HashMap<String, Playlist>,
+  // Shared with the clones sent to the frontend until it changes
+  #[serde(skip)]
+  pub search_index: Arc<SearchIndex>,
+  #[serde(skip)]
+  pub scope_index: Arc<ScopeIndex>,
+  // Read from history.jsonl once, then appended to with every play
+  #[serde(skip)]
+  pub history: Arc<Vec<HistoryEvent>>,
+  // Kept for the songs opened after the scan
+  #[serde(skip)]
+  pub cover_art_names: Vec<String>,
+}
+
+// The library's mutex, which counts its changes so subscribers only compare it when it changed
+pub struct LibraryLock {
+  library: Mutex<Library>,
+  revision: AtomicU64,
+}
+
+pub struct LibraryGuard<'a> {
+  library: MutexGuard<'a, Library>,
+  revision: &'a AtomicU64,
+  is_changed: bool,
+  changed_songs: Vec<String>,
 }
 
-pub fn read_from_dirs(dirs: &Vec<PathBuf>) -> Library {
-  let mut artists: HashMap<String, Artist> = HashMap::new();
-  let mut albums: HashMap<String, Album> = HashMap::new();
-  let mut songs: HashMap<String, Song> = HashMap::new();
+impl LibraryLock {
+  pub fn new(library: Library) -> Self {
+    LibraryLock {
+      library: Mutex::new(library),
+      revision: AtomicU64::new(0),
+    }
+  }
+
+  pub fn lock(&self) -> LockResult<LibraryGuard<'_>> {
+    let guard = |library| LibraryGuard {
+      library,
+      revision: &self.revision,
+      is_changed: false,
+      changed_songs: Vec::new(),
+    };
+    match self.library.lock() {
+      Ok(library) => Ok(guard(library)),
+      Err(e) => Err(PoisonError::new(guard(e.into_inner()))),
+    }
+  }
+
+  pub fn revision(&self) -> u64 {
+    self.revision.load(Ordering::SeqCst)
+  }
+}
+
+// Changes have to be marked, so lookups and failed mutations don't wake up subscribers
+impl LibraryGuard<'_> {
+  pub fn mark_changed(&mut self) {
+    self.is_changed = true;
+  }
+
+  // Ratings and play counts may move these songs in or out of smart playlists
+  pub fn mark_songs_changed(&mut self, song_ids: impl IntoIterator<Item = String>) {
+    self.changed_songs.extend(song_ids);
+  }
+
+  pub fn replace(&mut self, library: Library) {
+    *self.library = library;
+    self.is_changed = true;
+  }
+}
+
+impl Deref for LibraryGuard<'_> {
+  type Target = Library;
+
+  fn deref(&self) -> &Library {
+    &self.library
+  }
+}
+
+impl DerefMut for LibraryGuard<'_> {
+  fn deref_mut(&mut self) -> &mut Library {
+    &mut self.library
+  }
+}
+
+impl Drop for LibraryGuard<'_> {
+  fn drop(&mut self) {
+    if !self.changed_songs.is_empty() {
+      self.changed_songs.sort();
+      self.changed_songs.dedup();
+      update_smart_playlists(&mut self.library, &self.changed_songs);
+      self.is_changed = true;
+    }
+    if self.is_changed {
+      self.revision.fetch_add(1, Ordering::SeqCst);
+    }
+  }
+}
+
+fn read_song(path: &Path, library: &mut Library, covers_dir: &Path) -> Option<String> {
+  let tagged_file = Probe::open(path).ok().map(|p| p.read().ok()).flatten()?;
+  let tags = match tagged_file.primary_tag() {
+    Some(primary_tag) => Some(primary_tag),
+    None => tagged_file.first_tag(),
+  }?;
+  let artist_name = tags
+    .artist()
+    .as_deref()
+    .unwrap_or("Unknown Artist")
+    .to_string();
+  let album_name = tags
+    .album()
+    .as_deref()
+    .unwrap_or("Unknown Album")
+    .to_string();
+  let title = tags.title().as_deref().unwrap_or("Unknown").to_string();
+  // Genres written as several tag items are kept together, like genres separated in one item
+  let genres: Vec<&str> = tags.get_strings(&ItemKey::Genre).collect();
+  let genre = match genres.len() {
+    0 => tags.genre().map(|genre| genre.to_string()),
+    _ => Some(genres.join("; ")),
+  };
+  let year = tags.year();
+  let added_at = fs::metadata(path)
+    .and_then(|metadata| metadata.created().or_else(|_| metadata.modified()))
+    .ok()
+    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+    .map_or(0, |time| time.as_secs() as u32);
+  let get_tag = |key: ItemKey| tags.get_string(&key).map(|value| value.to_string());
+  let artist_sort_name = get_tag(ItemKey::TrackArtistSortOrder);
+  let album_sort_name = get_tag(ItemKey::AlbumTitleSortOrder);
+  let sort_title = get_tag(ItemKey::TrackTitleSortOrder);
+  let duration = tagged_file.properties().duration().as_secs();
+  let rating = read_rating_tag(tags);
+  let artist_id = match library.artists.iter().find(|a| a.1.name == artist_name) {
+    Some(artist) => artist.0.clone(),
+    None => {
+      let id = nanoid!();
+      library.artists.insert(
+        id.clone(),
+        Artist {
+          name: artist_name.to_string(),
+          sort_name: artist_sort_name.clone(),
+          play_count: 0,
+          last_played: None,
+        },
+      );
+      Arc::make_mut(&mut library.search_index).add(
+        DocumentKind::Artist,
+        &id,
+        &artist_name,
+        artist_sort_name.as_deref(),
+      );
+      id
+    }
+  };
+  let album = match library
+    .albums
+    .iter()
+    .find(|a| a.1.artist == artist_id && a.1.name == album_name)
+  {
+    Some(album) => album.0.clone(),
+    None => {
+      let id = nanoid!();
+      let cover_art = read_cover_art(path, tags.pictures(), &library.cover_art_names, covers_dir);
+      Arc::make_mut(&mut library.search_index).add(
+        DocumentKind::Album,
+        &id,
+        &album_name,
+        album_sort_name.as_deref(),
+      );
+      library.albums.insert(
+        id.clone(),
+        Album {
+          name: album_name,
+          sort_name: album_sort_name,
+          cover_art,
+          artist: artist_id,
+          play_count: 0,
+          last_played: None,
+        },
+      );
+      id
+    }
+  };
+  let id = nanoid!();
+  Arc::make_mut(&mut library.search_index).add(
+    DocumentKind::Song,
+    &id,
+    &title,
+    sort_title.as_deref(),
+  );
+  let song = Song {
+    title,
+    sort_title,
+    path: path.to_path_buf(),
+    duration: duration as u32,
+    album,
+    genre,
+    year,
+    added_at,
+    play_count: 0,
+    skip_count: 0,
+    last_played: None,
+    rating,
+    is_favorite: false,
+  };
+  Arc::make_mut(&mut library.scope_index).add(&id, &song);
+  library.songs.insert(id.clone(), song);
+  Some(id)
+}
 
-  let temp_dir = std::env::temp_dir().join("ronix-covers");
-  create_dir_all(&temp_dir).unwrap();
+pub fn read_from_dirs(dirs: &Vec<PathBuf>, cover_art_names: &[String]) -> Library {
+  let mut library = Library {
+    artists: HashMap::new(),
+    albums: HashMap::new(),
+    songs: HashMap::new(),
+    playlists: HashMap::new(),
+    search_index: Arc::new(SearchIndex::default()),
+    scope_index: Arc::new(ScopeIndex::default()),
+    history: Arc::new(Vec::new()),
+    cover_art_names: cover_art_names.to_vec(),
+  };
+
+  let covers_dir = get_covers_dir();
 
   for dir in dirs.iter() {
     for file_res in WalkDir::new(dir) {
       if let Ok(file) = file_res {
         if file.file_type().is_file() {
-          if let Some(tagged_file) = Probe::open(file.path())
-            .ok()
-            .map(|p| p.read().ok())
-            .flatten()
-          {
-            if let Some(tags) = match tagged_file.primary_tag() {
-              Some(primary_tag) => Some(primary_tag),
-              None => tagged_file.first_tag(),
-            } {
-              let artist_name = tags
-                .artist()
-                .as_deref()
-                .unwrap_or("Unknown Artist")
-                .to_string();
-              let album_name = tags
-                .album()
-                .as_deref()
-                .unwrap_or("Unknown Album")
-                .to_string();
-              let title = tags.title().as_deref().unwrap_or("Unknown").to_string();
-              let path = file.path().to_path_buf();
-              let duration = tagged_file.properties().duration().as_secs();
-              let artist_id = match artists.iter().find(|a| a.1.name == artist_name) {
-                Some(artist) => artist.0.clone(),
-                None => {
-                  let id = nanoid!();
-                  artists.insert(
-                    id.clone(),
-                    Artist {
-                      name: artist_name.to_string(),
-                    },
-                  );
-                  id
-                }
-              };
-              let album = match albums
-                .iter()
-                .find(|a| a.1.artist == artist_id && a.1.name == album_name)
-              {
-                Some(album) => album.0.clone(),
-                None => {
-                  let id = nanoid!();
-                  let cover_art = if tags.picture_count() > 0 {
-                    let path =
-                      temp_dir
-                        .join(&id)
-                        .with_extension(match tags.pictures()[0].mime_type() {
-                          MimeType::Bmp => "bmp",
-                          MimeType::Jpeg => "jpg",
-                          MimeType::Png => "png",
-                          MimeType::Tiff => "tiff",
-                          MimeType::Unknown(t) => t.split('/').last().unwrap(),
-                          _ => "jpg",
-                        });
-                    let mut file = File::create(&path).unwrap();
-                    file.write_all(tags.pictures()[0].data()).unwrap();
-                    fs::write(&path, tags.pictures()[0].data()).unwrap();
-                    Some(path.to_str().unwrap().to_string())
-                  } else {
-                    None
-                  };
-                  albums.insert(
-                    id.clone(),
-                    Album {
-                      name: album_name,
-                      cover_art,
-                      artist: artist_id,
-                    },
-                  );
-                  id
-                }
-              };
-              songs.insert(
-                nanoid!(),
-                Song {
-                  title,
-                  path,
-                  duration: duration as u32,
-                  album,
-                },
-              );
-            }
-          }
+          read_song(file.path(), &mut library, &covers_dir);
         }
       }
     }
   }
-  Library {
-    artists,
-    albums,
-    songs,
+  library.playlists = read_playlists(&library.songs);
+  read_saved_ratings(&mut library);
+  count_history(&mut library);
+  refresh_smart_playlists(&mut library);
+  library
+}
+
+pub fn find_song_by_path(library: &Library, path: &Path) -> Option<String> {
+  library
+    .songs
+    .iter()
+    .find(|(_, song)| song.path == path)
+    .map(|(song_id, _)| song_id.clone())
+}
+
+// Files opened from outside the music folders are kept until the next scan
+pub fn add_external_song(library: &mut LibraryGuard, path: &Path) -> Option<String> {
+  if let Some(song_id) = find_song_by_path(library, path) {
+    return Some(song_id);
+  }
+  let song_id = read_song(path, library, &get_covers_dir())?;
+  library.mark_songs_changed([song_id.clone()]);
+  Some(song_id)
+}
+
+// Files opened from outside the music folders are read again after a scan that replaced them
+pub fn keep_external_songs(library: &mut Library, paths: &[PathBuf]) {
+  let covers_dir = get_covers_dir();
+  let mut song_ids = Vec::new();
+  for path in paths {
+    if find_song_by_path(library, path).is_none() {
+      song_ids.extend(read_song(path, library, &covers_dir));
+    }
+  }
+  update_smart_playlists(library, &song_ids);
+}
+
+// Genres, years and folders come from the scope index. Only playlists are in order
+pub fn get_scope_songs<'a>(library: &'a Library, scope: &PlayerScope) -> Vec<&'a String> {
+  if let Some(song_ids) = library.scope_index.get_songs(scope) {
+    return song_ids
+      .into_iter()
+      .filter(|id| library.songs.contains_key(*id))
+      .collect();
+  }
+  match scope {
+    PlayerScope::Playlist(playlist_id) => library
+      .playlists
+      .get(playlist_id)
+      .map(|playlist| {
+        playlist
+          .songs
+          .iter()
+          .filter(|id| library.songs.contains_key(*id))
+          .collect()
+      })
+      .unwrap_or_default(),
+    _ => library
+      .songs
+      .iter()
+      .filter(|(_, song)| match scope {
+        PlayerScope::Album(album_id) => song.album.as_str() == album_id,
+        PlayerScope::Artist(artist_id) => {
+          library.albums.get(&song.album).unwrap().artist.as_str() == artist_id
+        }
+        PlayerScope::Favorites => song.is_favorite,
+        PlayerScope::Rating(min_rating) => song.rating >= *min_rating,
+        _ => true,
+      })
+      .map(|(id, _)| id)
+      .collect(),
   }
 }
 
@@ -154,38 +392,53 @@ pub fn get_automatic_next_songs(
   is_shuffled: bool,
   scope: &PlayerScope,
 ) -> Vec<String> {
-  let mut songs_vec: Vec<(String, &String)> = library
-    .songs
-    .iter()
-    .filter(|(_, song)| match scope {
-      PlayerScope::Library => true,
-      PlayerScope::Album(album_id) => song.album.as_str() == album_id,
-      PlayerScope::Artist(artist_id) => {
-        library.albums.get(&song.album).unwrap().artist.as_str() == artist_id
-      }
-    })
-    .map(|(id, song)| (id.clone(), &song.title))
+  let mut songs_vec: Vec<(String, &String)> = get_scope_songs(library, scope)
+    .into_iter()
+    .map(|id| (id.clone(), &library.songs.get(id).unwrap().title))
     .collect();
+  if matches!(scope, PlayerScope::Playlist(_)) {
+    songs_vec.reverse();
+  }
   if is_shuffled {
+    // Weighted shuffle: higher rated songs get bigger keys, and the queue is played from the back
     let rng = &mut thread_rng();
-    songs_vec.shuffle(rng);
-  } else {
+    let mut keyed_songs: Vec<(f64, (String, &String))> = songs_vec
+      .into_iter()
+      .map(|(id, title)| {
+        let song = library.songs.get(&id).unwrap();
+        let weight = get_shuffle_weight(song.rating, song.is_favorite);
+        (rng.gen::<f64>().powf(1.0 / weight), (id, title))
+      })
+      .collect();
+    keyed_songs.sort_by(|a, b| a.0.total_cmp(&b.0));
+    songs_vec = keyed_songs.into_iter().map(|(_, song)| song).collect();
+  } else if !matches!(scope, PlayerScope::Playlist(_)) {
     songs_vec.sort_by(|a, b| b.1.cmp(a.1));
   }
-  let current_song_index = songs_vec
-    .iter()
-    .position(|(id, _)| id == current_song_id)
-    .unwrap();
-  songs_vec.rotate_left(current_song_index);
-  songs_vec.remove(0);
+  // The current song can be missing when it was unfavorited or removed from the playlist
+  if let Some(current_song_index) = songs_vec.iter().position(|(id, _)| id == current_song_id) {
+    songs_vec.remove(current_song_index);
+    // Shuffled songs are already in random order, others continue after the current one
+    if !is_shuffled {
+      songs_vec.rotate_left(current_song_index);
+    }
+  }
   songs_vec.iter().map(|(id, _)| id).cloned().collect()
 }
 
+#[derive(Serialize, Type)]
+struct SearchHit {
+  id: String,
+  score: f64,
+}
+
+// Each list is sorted by relevance
 #[derive(Serialize, Type)]
 struct SearchResults {
-  artists: Option<Vec<String>>,
-  albums: Option<Vec<String>>,
-  songs: Vec<String>,
+  artists: Option<Vec<SearchHit>>,
+  albums: Option<Vec<SearchHit>>,
+  songs: Vec<SearchHit>,
+  total_songs: u32,
 }
 
 #[derive(Deserialize, Type)]
@@ -206,91 +459,306 @@ enum SearchMode {
 struct SearchInput {
   query: String,
   mode: SearchMode,
+  #[serde(default)]
+  min_rating: Option<u8>,
+  #[serde(default)]
+  favorites_only: Option<bool>,
+  // Applied to each kind of result
+  #[serde(default)]
+  offset: Option<u32>,
+  #[serde(default)]
+  limit: Option<u32>,
 }
 
-pub fn get_router() -> RouterBuilder<Context> {
+#[derive(Deserialize, Type)]
+struct SetRatingInput {
+  song_id: String,
+  rating: u8,
+}
+
+#[derive(Deserialize, Type)]
+struct SetFavoriteInput {
+  song_id: String,
+  is_favorite: bool,
+}
+
+// Ties, like all results of a query made only of filters, are sorted by name
+fn rank<'a>(
+  scores: impl Iterator<Item = (&'a String, f64)>,
+  get_name: impl Fn(&String) -> &'a str,
+) -> Vec<SearchHit> {
+  let mut hits: Vec<(&String, f64, &str)> = scores
+    .map(|(id, score)| (id, score, get_name(id)))
+    .collect();
+  hits.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.2.cmp(b.2)));
+  hits
+    .into_iter()
+    .map(|(id, score, _)| SearchHit {
+      id: id.clone(),
+      score,
+    })
+    .collect()
+}
+
+fn page(hits: Vec<SearchHit>, input: &SearchInput) -> Vec<SearchHit> {
+  hits
+    .into_iter()
+    .skip(input.offset.unwrap_or(0) as usize)
+    .take(input.limit.map_or(usize::MAX, |limit| limit as usize))
+    .collect()
+}
+
+fn search(library: &Library, input: SearchInput, is_transliterating: bool) -> SearchResults {
+  let fuse = query::get_fuse();
+  let mut query = query::parse(&input.query);
+  // The older options are the same as rating:>= and is:favorite
+  if let Some(min_rating) = input.min_rating.filter(|min_rating| *min_rating > 0) {
+    query.add_filter(Filter::Field(
+      Field::Rating,
+      Condition::Range {
+        min: Some(min_rating as u32),
+        max: None,
+      },
+    ));
+  }
+  if input.favorites_only.unwrap_or(false) {
+    query.add_filter(Filter::Favorite);
+  }
+  let has_filters = query.has_filters();
+  let mut artist_scores: HashMap<&String, f64> = HashMap::new();
+  let mut album_scores: HashMap<&String, f64> = HashMap::new();
+  let mut song_scores: HashMap<&String, f64> = HashMap::new();
+  if query.text.is_empty() {
+    // Queries made only of filters match everything before filtering
+    artist_scores.extend(library.artists.keys().map(|id| (id, 1.0)));
+    album_scores.extend(library.albums.keys().map(|id| (id, 1.0)));
+    song_scores.extend(library.songs.keys().map(|id| (id, 1.0)));
+  } else {
+    for hit in library.search_index.search(&query.text, is_transliterating) {
+      match hit.kind {
+        DocumentKind::Artist => artist_scores.insert(hit.id, hit.score),
+        DocumentKind::Album => album_scores.insert(hit.id, hit.score),
+        DocumentKind::Song => song_scores.insert(hit.id, hit.score),
+      };
+    }
+  }
+  let get_song_title = |id: &String| library.songs.get(id).map_or("", |song| song.title.as_str());
+  match input.mode {
+    SearchMode::Library => {
+      // With filters, albums and artists are only shown if one of their songs matches
+      let mut matching_songs = HashSet::new();
+      let mut matching_albums = HashSet::new();
+      let mut matching_artists = HashSet::new();
+      if has_filters {
+        for (song_id, song) in library.songs.iter() {
+          if query.filter.matches(library, &fuse, song) {
+            matching_songs.insert(song_id);
+            matching_albums.insert(&song.album);
+            matching_artists.insert(&library.albums.get(&song.album).unwrap().artist);
+          }
+        }
+      }
+      let songs = rank(
+        song_scores
+          .into_iter()
+          .filter(|(id, _)| !has_filters || matching_songs.contains(id)),
+        get_song_title,
+      );
+      SearchResults {
+        artists: Some(page(
+          rank(
+            artist_scores
+              .into_iter()
+              .filter(|(id, _)| !has_filters || matching_artists.contains(id)),
+            |id| {
+              library
+                .artists
+                .get(id)
+                .map_or("", |artist| artist.name.as_str())
+            },
+          ),
+          &input,
+        )),
+        albums: Some(page(
+          rank(
+            album_scores
+              .into_iter()
+              .filter(|(id, _)| !has_filters || matching_albums.contains(id)),
+            |id| {
+              library
+                .albums
+                .get(id)
+                .map_or("", |album| album.name.as_str())
+            },
+          ),
+          &input,
+        )),
+        total_songs: songs.len() as u32,
+        songs: page(songs, &input),
+      }
+    }
+    SearchMode::Songs => {
+      // Songs also match through their album and artist
+      let songs = rank(
+        library.songs.iter().filter_map(|(song_id, song)| {
+          let album = library.albums.get(&song.album).unwrap();
+          let score = [
+            song_scores.get(song_id),
+            album_scores.get(&song.album),
+            artist_scores.get(&album.artist),
+          ]
+          .into_iter()
+          .flatten()
+          .copied()
+          .reduce(f64::max)?;
+          if has_filters && !query.filter.matches(library, &fuse, song) {
+            return None;
+          }
+          Some((song_id, score))
+        }),
+        get_song_title,
+      );
+      SearchResults {
+        artists: None,
+        albums: None,
+        total_songs: songs.len() as u32,
+        songs: page(songs, &input),
+      }
+    }
+  }
+}
+
+// Only queries, so it can also be served to the remote control
+pub fn get_read_router() -> RouterBuilder<Context> {
   Router::<Context>::new()
     .query("get", |t| {
       t(|ctx, _input: ()| ctx.library.lock().unwrap().clone())
     })
-    .query("search", |t| {
-      t(|ctx, input: SearchInput| {
-        let library = ctx.library.lock().unwrap();
-        let fuse = Fuse {
-          location: 0,
-          distance: 100,
-          threshold: 0.3,
-          max_pattern_length: 32,
-          is_case_sensitive: false,
-          tokenize: false,
-        };
-        let artists: Vec<String> = fuse
-          .search_text_in_iterable(
-            &input.query,
-            library.artists.iter().map(|(_, artist)| &artist.name),
-          )
-          .iter()
-          .map(|a| library.artists.iter().nth(a.index).unwrap().0.to_string())
-          .collect();
-        let albums: Vec<String> = fuse
-          .search_text_in_iterable(
-            &input.query,
-            library.albums.iter().map(|(_, album)| &album.name),
-          )
-          .iter()
-          .map(|a| library.albums.iter().nth(a.index).unwrap().0.to_string())
-          .collect();
-        let mut songs: Vec<String> = fuse
-          .search_text_in_iterable(
-            &input.query,
-            library.songs.iter().map(|(_, song)| &song.title),
-          )
-          .iter()
-          .map(|a| library.songs.iter().nth(a.index).unwrap().0.to_string())
-          .collect();
-        match input.mode {
-          SearchMode::Library => SearchResults {
-            artists: Some(artists),
-            albums: Some(albums),
-            songs: songs,
-          },
-          SearchMode::Songs => {
-            songs.extend(
-              library
-                .songs
-                .iter()
-                .filter_map(|(song_id, song)| {
-                  if songs.contains(song_id) {
-                    return Some(song_id);
-                  }
-                  if albums.contains(&song.album) {
-                    return Some(song_id);
-                  }
-                  let album = library.albums.get(&song.album).unwrap();
-                  if artists.contains(&album.artist) {
-                    return Some(song_id);
-                  }
-                  return None;
-                })
-                .cloned()
-                .collect::<Vec<String>>(),
-            );
-            SearchResults {
-              artists: None,
-              albums: None,
-              songs,
+    .query("artists", |t| {
+      t(|ctx, input: PageInput| paging::get_page(&ctx.library.lock().unwrap().artists, input))
+    })
+    .query("albums", |t| {
+      t(|ctx, input: PageInput| paging::get_page(&ctx.library.lock().unwrap().albums, input))
+    })
+    .query("songs", |t| {
+      t(|ctx, input: PageInput| paging::get_page(&ctx.library.lock().unwrap().songs, input))
+    })
+    .query("playlists", |t| {
+      t(|ctx, _: ()| ctx.library.lock().unwrap().playlists.clone())
+    })
+    .query("byIds", |t| {
+      t(|ctx, input: IdsInput| paging::get_by_ids(&ctx.library.lock().unwrap(), input))
+    })
+    .query("albumSongs", |t| {
+      t(|ctx, input: String| paging::get_album_songs(&ctx.library.lock().unwrap(), &input))
+    })
+    .query("artistSongs", |t| {
+      t(|ctx, input: String| paging::get_artist_songs(&ctx.library.lock().unwrap(), &input))
+    })
+    .subscription("changes", |t| {
+      t(|ctx, _: ()| {
+        stream! {
+          let (mut snapshot, mut revision) = {
+            let library = ctx.library.lock().unwrap();
+            (paging::get_snapshot(&library), ctx.library.revision())
+          };
+          // The first, empty changes tell the subscriber it can load the pages now
+          yield LibraryChanges::default();
+          loop {
+            sleep(Duration::from_millis(500)).await;
+            if ctx.library.revision() == revision {
+              continue;
+            }
+            let changes = {
+              let library = ctx.library.lock().unwrap();
+              revision = ctx.library.revision();
+              paging::get_changes(&library, &mut snapshot)
+            };
+            if let Some(changes) = changes {
+              yield changes;
             }
           }
         }
       })
     })
+    .query("genres", |t| {
+      t(|ctx, _: ()| ctx.library.lock().unwrap().scope_index.get_genres())
+    })
+    .query("years", |t| {
+      t(|ctx, _: ()| ctx.library.lock().unwrap().scope_index.get_years())
+    })
+    .query("folder", |t| {
+      t(|ctx, input: Option<String>| {
+        let music_folders = ctx.config.lock().unwrap().music_folders.clone();
+        ctx
+          .library
+          .lock()
+          .unwrap()
+          .scope_index
+          .get_folder(input.as_deref().map(Path::new), &music_folders)
+      })
+    })
+    .query("scopeSongs", |t| {
+      t(|ctx, input: PlayerScope| {
+        get_scope_songs(&ctx.library.lock().unwrap(), &input)
+          .into_iter()
+          .cloned()
+          .collect::<Vec<String>>()
+      })
+    })
+    .query("search", |t| {
+      t(|ctx, input: SearchInput| {
+        let is_transliterating = ctx.config.lock().unwrap().search_transliteration;
+        search(&ctx.library.lock().unwrap(), input, is_transliterating)
+      })
+    })
+    .query("sorted", |t| {
+      t(|ctx, input: SortInput| {
+        let articles = ctx.config.lock().unwrap().sort_articles.clone();
+        sort::get_sorted(&ctx.library.lock().unwrap(), input, &articles)
+      })
+    })
+    .query("history", |t| {
+      t(|ctx, input: HistoryInput| history::get_history(&ctx.library.lock().unwrap(), input))
+    })
+}
+
+pub fn get_router() -> RouterBuilder<Context> {
+  get_read_router()
+    .mutation("setRating", |t| {
+      t(|ctx, input: SetRatingInput| {
+        let write_tags = ctx.config.lock().unwrap().write_rating_tags;
+        ratings::set_rating(
+          &mut ctx.library.lock().unwrap(),
+          &input.song_id,
+          input.rating,
+          write_tags,
+        )
+      })
+    })
+    .mutation("setFavorite", |t| {
+      t(|ctx, input: SetFavoriteInput| {
+        ratings::set_favorite(
+          &mut ctx.library.lock().unwrap(),
+          &input.song_id,
+          input.is_favorite,
+        )
+      })
+    })
     .mutation("deleteSong", |t| {
       t(|ctx, input: String| {
         let mut library = ctx.library.lock().unwrap();
         match library.songs.get(&input) {
           Some(song) => match fs::remove_file(&song.path) {
             Ok(_) => {
-              library.songs.remove(&input);
+              let song = library.songs.remove(&input).unwrap();
+              Arc::make_mut(&mut library.scope_index).remove(&input, &song);
+              Arc::make_mut(&mut library.search_index).remove(DocumentKind::Song, &input);
+              for playlist in library.playlists.values_mut() {
+                playlist.songs.retain(|song_id| song_id != &input);
+              }
+              write_playlists(&library);
+              library.mark_changed();
               "Successfully deleted".to_string()
             }
             Err(e) => e.to_string(),
@@ -332,7 +800,11 @@ pub fn get_router() -> RouterBuilder<Context> {
                     if !sacad.map(|s| s.success()).unwrap_or(false) {
                       return "Failed to download cover art with sacad_r";
                     }
-                    *library = read_from_dirs(&ctx.config.lock().unwrap().music_folders);
+                    let config = ctx.config.lock().unwrap();
+                    library.replace(read_from_dirs(
+                      &config.music_folders,
+                      &config.cover_art_names,
+                    ));
                     "Successfully edited"
                   }
                   None => "Could not edit song",
@@ -347,7 +819,9 @@ pub fn get_router() -> RouterBuilder<Context> {
     })
     .mutation("refresh", |t| {
       t(|ctx, _: ()| {
-        *ctx.library.lock().unwrap() = read_from_dirs(&ctx.config.lock().unwrap().music_folders);
+        let config = ctx.config.lock().unwrap();
+        let library = read_from_dirs(&config.music_folders, &config.cover_art_names);
+        ctx.library.lock().unwrap().replace(library);
       })
     })
 }
@@ -1,22 +1,52 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
+mod broadcast;
+mod cli;
 mod config;
+mod covers;
 mod download;
+mod fade;
+mod history;
+mod import;
+mod ipc;
 mod library;
+mod mpd;
+mod mpris;
+mod paging;
+mod palette;
 mod player;
+mod playlist;
+mod query;
+mod ratings;
+mod scopes;
+mod scrobble;
+mod search;
+mod server;
+mod sort;
+mod stats;
+mod subsonic;
+mod themes;
+use broadcast::Broadcast;
+use cli::CliAction;
 use config::{get_config_file, Config};
+use fade::Fader;
+use gst::prelude::*;
 use gst_player::{Player, PlayerSignalDispatcher, PlayerVideoRenderer};
-use library::{read_from_dirs, Library};
-use player::{next_song, previous_song, seek, toggle_pause};
+use ipc::{IpcCommand, IpcResponse};
+use library::{read_from_dirs, Library, LibraryLock};
+use palette::CachedPalette;
+use player::{next_song, seek};
 use rspc::{Config as RspcConfig, Router, Type};
 use serde::{Deserialize, Serialize};
-use souvlaki::{MediaControlEvent, MediaControls, PlatformConfig};
 use std::{
+  collections::HashMap,
+  path::PathBuf,
   sync::{Arc, Mutex},
   time::{SystemTime, UNIX_EPOCH},
 };
+use tauri::{async_runtime::JoinHandle, WindowBuilder, WindowUrl};
 
-#[derive(Serialize, Clone, Type, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Type, PartialEq)]
 pub enum RepeatMode {
   None,
   One,
@@ -28,15 +58,40 @@ pub enum PlayerScope {
   Library,
   Album(String),
   Artist(String),
+  Playlist(String),
+  Favorites,
+  // Songs rated at least this many stars
+  Rating(u8),
+  Genre(String),
+  Year(u32),
+  // The first year of the decade, like 1990
+  Decade(u32),
+  // Including the subfolders
+  Folder(String),
 }
 
 #[derive(Clone)]
 pub struct Context {
-  pub library: Arc<Mutex<Library>>,
+  pub library: Arc<LibraryLock>,
   pub config: Arc<Mutex<Config>>,
   pub player: Arc<Player>,
+  pub fader: Fader,
+  pub broadcast: Broadcast,
   pub player_state: Arc<Mutex<PlayerState>>,
-  pub os_controls: Arc<Mutex<MediaControls>>,
+  // Set when running headless, the window attaches to the daemon through it
+  pub daemon_url: Option<String>,
+  // Required by the daemon's local server, also part of `daemon_url`
+  pub daemon_token: Option<String>,
+  pub remote_server: Arc<Mutex<Option<JoinHandle<()>>>>,
+  pub mpd_server: Arc<Mutex<Option<JoinHandle<()>>>>,
+  pub subsonic_server: Arc<Mutex<Option<JoinHandle<()>>>>,
+  pub broadcast_server: Arc<Mutex<Option<JoinHandle<()>>>>,
+  // By cover art path
+  pub palettes: Arc<Mutex<HashMap<PathBuf, CachedPalette>>>,
+  // From the desktop portal, None without a preference or a portal
+  pub system_dark_mode: Arc<Mutex<Option<bool>>>,
+  // Shown in the settings when a scrobbling service refuses its credentials
+  pub scrobbling_error: Arc<Mutex<Option<String>>>,
 }
 
 pub struct PlayerState {
@@ -49,6 +104,8 @@ pub struct PlayerState {
   pub scope: PlayerScope,
   pub song_started_at: u32,
   pub paused_at: Option<u32>,
+  // Stopped songs are also paused, but start over when resumed
+  pub is_stopped: bool,
   pub volume: f64,
 }
 
@@ -64,6 +121,7 @@ impl Default for PlayerState {
       scope: PlayerScope::Library,
       song_started_at: get_current_time(),
       paused_at: Some(get_current_time()),
+      is_stopped: false,
       volume: 0.5,
     }
   }
@@ -76,80 +134,50 @@ pub fn get_current_time() -> u32 {
     .as_secs() as u32
 }
 
-#[tokio::main]
-async fn main() {
-  gst::init().unwrap();
-
-  let router = Router::<Context>::new()
-    .merge("config.", config::get_router())
-    .merge("player.", player::get_router())
-    .merge("library.", library::get_router())
-    .merge("download.", download::get_router())
-    .config(RspcConfig::new().export_ts_bindings("../src/gen/tauri-types.ts"))
-    .build();
-
+fn create_context(headless: bool) -> Context {
   let config: Config = serde_json::from_reader(&get_config_file()).unwrap_or_default();
-  let library = Arc::new(Mutex::new(read_from_dirs(&config.music_folders)));
+  let daemon_token = headless.then(server::create_daemon_token);
+  let daemon_url = daemon_token.as_ref().map(|token| {
+    format!(
+      "ws://127.0.0.1:{}/rspc/ws?token={}",
+      config.daemon_port, token
+    )
+  });
+  let library = read_from_dirs(&config.music_folders, &config.cover_art_names);
+  build_context(config, library, daemon_url, daemon_token)
+}
+
+// Tests build their context from a library made in code
+fn build_context(
+  config: Config,
+  library: Library,
+  daemon_url: Option<String>,
+  daemon_token: Option<String>,
+) -> Context {
+  let config = Arc::new(Mutex::new(config));
+  let library = Arc::new(LibraryLock::new(library));
   let player = Arc::new(Player::new(
     None::<PlayerVideoRenderer>,
     None::<PlayerSignalDispatcher>,
   ));
   player.set_volume(0.5);
+  let fader = Fader::new(config.clone());
+  // The broadcast taps the audio after the fades, but before the user's volume
+  let (broadcast, audio_filter) = Broadcast::new(&fader.volume);
+  player
+    .pipeline()
+    .set_property("audio-filter", &audio_filter);
 
   let player_state = Arc::new(Mutex::new(PlayerState::default()));
 
-  let os_controls = Arc::new(Mutex::new(
-    MediaControls::new(PlatformConfig {
-      dbus_name: "dev.ronanru.ronix",
-      display_name: "Ronix",
-      hwnd: None,
-    })
-    .unwrap(),
-  ));
-
-  let player_state_clone = player_state.clone();
-  let player_clone = player.clone();
-  let library_clone = library.clone();
-  let os_controls_clone = os_controls.clone();
-  os_controls
-    .lock()
-    .unwrap()
-    .attach(move |event: MediaControlEvent| match event {
-      MediaControlEvent::Toggle => toggle_pause(
-        &mut player_state_clone.lock().unwrap(),
-        &player_clone,
-        &mut os_controls_clone.lock().unwrap(),
-      ),
-      MediaControlEvent::Next => next_song(
-        &mut player_state_clone.lock().unwrap(),
-        &library_clone.lock().unwrap(),
-        &player_clone,
-        &mut os_controls_clone.lock().unwrap(),
-      ),
-      MediaControlEvent::Previous => previous_song(
-        &mut player_state_clone.lock().unwrap(),
-        &player_clone,
-        &library_clone.lock().unwrap(),
-        &mut os_controls_clone.lock().unwrap(),
-      ),
-      _ => println!("Unhandled MPRIS event: {:?}", event),
-    })
-    .unwrap();
-
   let player_state_clone = player_state.clone();
   let library_clone = library.clone();
-  let os_controls_clone = os_controls.clone();
   player.connect_end_of_stream(move |p| {
     let mut state = player_state_clone.lock().unwrap();
     if state.repeat_mode == RepeatMode::One {
       seek(&mut state, &p, 0);
     } else {
-      next_song(
-        &mut state,
-        &library_clone.lock().unwrap(),
-        &p,
-        &mut os_controls_clone.lock().unwrap(),
-      );
+      next_song(&mut state, &library_clone.lock().unwrap(), &p, None);
     }
   });
 
@@ -159,19 +187,138 @@ async fn main() {
     state.volume = p.volume();
   });
 
-  let context = Context {
+  Context {
     library,
-    config: Arc::new(Mutex::new(config)),
+    config,
     player: player,
+    fader,
+    broadcast,
     player_state,
-    os_controls,
-  };
+    daemon_url,
+    daemon_token,
+    remote_server: Arc::new(Mutex::new(None)),
+    mpd_server: Arc::new(Mutex::new(None)),
+    subsonic_server: Arc::new(Mutex::new(None)),
+    broadcast_server: Arc::new(Mutex::new(None)),
+    palettes: Arc::new(Mutex::new(HashMap::new())),
+    system_dark_mode: Arc::new(Mutex::new(None)),
+    scrobbling_error: Arc::new(Mutex::new(None)),
+  }
+}
 
-  tauri::Builder::default()
-    .plugin(rspc::integrations::tauri::plugin(
-      router.arced(),
-      move || context.clone(),
-    ))
+async fn start_services(context: &Context, initial_command: Option<IpcCommand>) {
+  if let Err(e) = mpris::start(context.clone()).await {
+    println!("Failed to start MPRIS server: {}", e);
+  }
+  if let Err(e) = ipc::start(context.clone()) {
+    println!("Failed to start control socket: {}", e);
+  }
+  server::start_remote(context);
+  mpd::start(context);
+  subsonic::start(context);
+  broadcast::start(context);
+  history::start(context.clone());
+  covers::start(context.clone());
+  scrobble::start(context.clone());
+  themes::start(context.clone());
+  if let Some(command) = initial_command {
+    ipc::handle_command(context, command);
+  }
+}
+
+async fn run_daemon(router: Arc<Router<Context>>) {
+  let context = create_context(true);
+  let port = context.config.lock().unwrap().daemon_port;
+  let token = context.daemon_token.clone().unwrap();
+  start_services(&context, None).await;
+  tokio::spawn(server::serve_local(router, context, port, token));
+  tokio::signal::ctrl_c().await.unwrap();
+  ipc::remove_socket();
+  server::remove_daemon_token();
+}
+
+// Without a context the window attaches to the daemon running at `daemon_url`
+fn run_window(router: Arc<Router<Context>>, context: Option<Context>, daemon_url: Option<String>) {
+  let mut builder = tauri::Builder::default();
+  if let Some(context) = context {
+    let cover_context = context.clone();
+    builder = builder
+      .plugin(rspc::integrations::tauri::plugin(router, move || {
+        context.clone()
+      }))
+      .register_uri_scheme_protocol("cover", move |_, request| {
+        covers::handle_protocol(&cover_context, request)
+      });
+  }
+  builder
+    .setup(move |app| {
+      WindowBuilder::new(app, "main", WindowUrl::App("index.html".into()))
+        .title("Ronix Music Player")
+        .inner_size(800.0, 1000.0)
+        .initialization_script(&format!(
+          "window.__RONIX_DAEMON_URL__ = {};",
+          serde_json::to_string(&daemon_url).unwrap()
+        ))
+        .build()?;
+      Ok(())
+    })
     .run(tauri::generate_context!())
     .expect("error while running tauri application");
 }
+
+#[tokio::main]
+async fn main() {
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  let action = match cli::parse_args(&args) {
+    Ok(action) => action,
+    Err(usage) => {
+      eprintln!("{}", usage);
+      std::process::exit(2);
+    }
+  };
+  let (headless, initial_command) = match action {
+    CliAction::Command(command) => match ipc::send(&command) {
+      Ok(response) => std::process::exit(cli::print_response(response)),
+      Err(_) if matches!(command, IpcCommand::Open(_)) => (false, Some(command)),
+      Err(_) => {
+        eprintln!("Ronix is not running");
+        std::process::exit(1);
+      }
+    },
+    CliAction::Start { headless } => (headless, None),
+  };
+
+  let router = Router::<Context>::new()
+    .merge("config.", config::get_router())
+    .merge("player.", player::get_router())
+    .merge("library.", library::get_router())
+    .merge("download.", download::get_router())
+    .merge("playlist.", playlist::get_router())
+    .merge("stats.", stats::get_router())
+    .merge("import.", import::get_router())
+    .config(RspcConfig::new().export_ts_bindings("../src/gen/tauri-types.ts"))
+    .build()
+    .arced();
+
+  if initial_command.is_none() {
+    match ipc::send(&IpcCommand::GetDaemonUrl) {
+      Ok(IpcResponse::DaemonUrl(Some(daemon_url))) if !headless => {
+        return run_window(router, None, Some(daemon_url));
+      }
+      Ok(_) => {
+        eprintln!("Ronix is already running");
+        std::process::exit(0);
+      }
+      Err(_) => {}
+    }
+  }
+
+  gst::init().unwrap();
+
+  if headless {
+    return run_daemon(router).await;
+  }
+  let context = create_context(false);
+  start_services(&context, initial_command).await;
+  run_window(router, Some(context), None);
+}
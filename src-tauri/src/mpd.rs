@@ -0,0 +1,1438 @@
+use crate::{
+  covers::get_original_cover,
+  get_current_time,
+  library::{add_external_song, keep_external_songs, read_from_dirs, Library, LibraryGuard},
+  mpris::file_uri_to_path,
+  player::{self, get_position},
+  playlist::{create_playlist, write_playlists},
+  Context, PlayerState, RepeatMode,
+};
+use std::{
+  collections::{hash_map::DefaultHasher, BTreeSet, HashMap},
+  fmt::Display,
+  fs,
+  hash::{Hash, Hasher},
+  io,
+  iter::Peekable,
+  mem,
+  path::{Path, PathBuf},
+  str::{Chars, FromStr},
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+use tokio::{
+  io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+  net::{tcp::OwnedReadHalf, TcpListener, TcpStream},
+  time::sleep,
+};
+
+const GREETING: &[u8] = b"OK MPD 0.23.5\n";
+const BINARY_CHUNK_SIZE: usize = 8192;
+// Like MPD, clients sending longer lines or command lists are disconnected
+const MAX_LINE_LENGTH: usize = 4096;
+const MAX_COMMAND_LIST_SIZE: usize = 2048 * 1024;
+// Each level of parentheses or negation is a recursive call
+const MAX_FILTER_DEPTH: u32 = 64;
+
+const ACK_ERROR_ARG: u32 = 2;
+const ACK_ERROR_PASSWORD: u32 = 3;
+const ACK_ERROR_PERMISSION: u32 = 4;
+const ACK_ERROR_UNKNOWN: u32 = 5;
+const ACK_ERROR_NO_EXIST: u32 = 50;
+
+const COMMANDS: &[&str] = &[
+  "add",
+  "addid",
+  "albumart",
+  "clear",
+  "close",
+  "command_list_begin",
+  "command_list_end",
+  "command_list_ok_begin",
+  "commands",
+  "consume",
+  "count",
+  "currentsong",
+  "decoders",
+  "delete",
+  "deleteid",
+  "find",
+  "findadd",
+  "getvol",
+  "idle",
+  "list",
+  "listall",
+  "listallinfo",
+  "listplaylist",
+  "listplaylistinfo",
+  "listplaylists",
+  "load",
+  "lsinfo",
+  "next",
+  "noidle",
+  "notcommands",
+  "outputs",
+  "password",
+  "pause",
+  "ping",
+  "play",
+  "playid",
+  "playlistadd",
+  "playlistclear",
+  "playlistdelete",
+  "playlistid",
+  "playlistinfo",
+  "plchanges",
+  "plchangesposid",
+  "previous",
+  "random",
+  "readpicture",
+  "rename",
+  "repeat",
+  "replay_gain_status",
+  "rescan",
+  "rm",
+  "save",
+  "search",
+  "searchadd",
+  "seek",
+  "seekcur",
+  "seekid",
+  "setvol",
+  "single",
+  "stats",
+  "status",
+  "stop",
+  "tagtypes",
+  "update",
+  "urlhandlers",
+  "volume",
+];
+
+const TAG_TYPES: &[&str] = &["Artist", "AlbumArtist", "Album", "Title"];
+
+const SUBSYSTEMS: &[&str] = &[
+  "database",
+  "stored_playlist",
+  "playlist",
+  "player",
+  "mixer",
+  "options",
+];
+
+struct Ack {
+  code: u32,
+  message: String,
+}
+
+impl Ack {
+  fn new(code: u32, message: impl Into<String>) -> Self {
+    Ack {
+      code,
+      message: message.into(),
+    }
+  }
+}
+
+// MPD clients expect numeric song ids, so every song id gets a number on first use
+#[derive(Default)]
+struct SongNumbers {
+  numbers: HashMap<String, u32>,
+  song_ids: Vec<String>,
+}
+
+impl SongNumbers {
+  fn get(&mut self, song_id: &str) -> u32 {
+    if let Some(number) = self.numbers.get(song_id) {
+      return *number;
+    }
+    self.song_ids.push(song_id.to_string());
+    let number = self.song_ids.len() as u32;
+    self.numbers.insert(song_id.to_string(), number);
+    number
+  }
+
+  fn find(&self, number: u32) -> Option<&String> {
+    self.song_ids.get((number as usize).checked_sub(1)?)
+  }
+
+  // Songs keep their numbers after a rescan gives them new ids
+  fn remap(&mut self, new_ids: &HashMap<String, String>) {
+    self.numbers.clear();
+    for (i, song_id) in self.song_ids.iter_mut().enumerate() {
+      if let Some(new_id) = new_ids.get(song_id) {
+        *song_id = new_id.clone();
+      }
+      self.numbers.entry(song_id.clone()).or_insert(i as u32 + 1);
+    }
+  }
+}
+
+struct Server {
+  song_numbers: Mutex<SongNumbers>,
+  started_at: u32,
+}
+
+enum QueueEntry {
+  Current,
+  Next(usize),
+  Automatic(usize),
+}
+
+// The MPD queue is the current song followed by everything that plays after it
+fn get_queue(state: &PlayerState) -> Vec<String> {
+  state
+    .current_song
+    .iter()
+    .chain(state.next_songs.iter().rev())
+    .chain(state.automatic_next_songs.iter().rev())
+    .cloned()
+    .collect()
+}
+
+fn get_queue_entry(state: &PlayerState, position: usize) -> Option<QueueEntry> {
+  let position = match state.current_song {
+    Some(_) if position == 0 => return Some(QueueEntry::Current),
+    Some(_) => position - 1,
+    None => position,
+  };
+  let next_songs_len = state.next_songs.len();
+  if position < next_songs_len {
+    return Some(QueueEntry::Next(next_songs_len - 1 - position));
+  }
+  let position = position - next_songs_len;
+  let automatic_next_songs_len = state.automatic_next_songs.len();
+  (position < automatic_next_songs_len)
+    .then(|| QueueEntry::Automatic(automatic_next_songs_len - 1 - position))
+}
+
+fn get_song_uri(path: &Path, music_folders: &[PathBuf]) -> String {
+  music_folders
+    .iter()
+    .find_map(|folder| path.strip_prefix(folder).ok())
+    .unwrap_or(path)
+    .to_string_lossy()
+    .to_string()
+}
+
+struct SongTags<'a> {
+  uri: String,
+  title: &'a str,
+  artist: &'a str,
+  album: &'a str,
+}
+
+fn get_song_tags<'a>(
+  song_id: &str,
+  library: &'a Library,
+  music_folders: &[PathBuf],
+) -> Option<SongTags<'a>> {
+  let song = library.songs.get(song_id)?;
+  let album = library.albums.get(&song.album)?;
+  let artist = library.artists.get(&album.artist)?;
+  Some(SongTags {
+    uri: get_song_uri(&song.path, music_folders),
+    title: &song.title,
+    artist: &artist.name,
+    album: &album.name,
+  })
+}
+
+fn push_pair(out: &mut String, key: &str, value: impl Display) {
+  out.push_str(&format!("{}: {}\n", key, value));
+}
+
+fn push_song(out: &mut String, song_id: &str, library: &Library, music_folders: &[PathBuf]) {
+  if let Some(tags) = get_song_tags(song_id, library, music_folders) {
+    let duration = library.songs.get(song_id).unwrap().duration;
+    push_pair(out, "file", tags.uri);
+    push_pair(out, "Title", tags.title);
+    push_pair(out, "Artist", tags.artist);
+    push_pair(out, "AlbumArtist", tags.artist);
+    push_pair(out, "Album", tags.album);
+    push_pair(out, "Time", duration);
+    push_pair(out, "duration", duration);
+  }
+}
+
+fn push_queue_song(
+  out: &mut String,
+  position: usize,
+  song_id: &str,
+  library: &Library,
+  music_folders: &[PathBuf],
+  song_numbers: &mut SongNumbers,
+) {
+  push_song(out, song_id, library, music_folders);
+  push_pair(out, "Pos", position);
+  push_pair(out, "Id", song_numbers.get(song_id));
+}
+
+fn hash_value(value: impl Hash) -> u64 {
+  let mut hasher = DefaultHasher::new();
+  value.hash(&mut hasher);
+  hasher.finish()
+}
+
+// Idle compares a hash of every subsystem against the one the client last saw
+fn get_subsystem_hashes(ctx: &Context) -> HashMap<&'static str, u64> {
+  let state = ctx.player_state.lock().unwrap();
+  let library = ctx.library.lock().unwrap();
+  let mut playlists: Vec<(&String, &String, &Vec<String>)> = library
+    .playlists
+    .iter()
+    .map(|(id, playlist)| (id, &playlist.name, &playlist.songs))
+    .collect();
+  playlists.sort();
+  HashMap::from([
+    (
+      "database",
+      hash_value((library.songs.len(), library.albums.len())),
+    ),
+    ("stored_playlist", hash_value(playlists)),
+    ("playlist", hash_value(get_queue(&state))),
+    (
+      "player",
+      hash_value((&state.current_song, state.song_started_at, state.paused_at)),
+    ),
+    ("mixer", hash_value(state.volume.to_bits())),
+    (
+      "options",
+      hash_value((state.repeat_mode.clone() as u8, state.is_shuffled)),
+    ),
+  ])
+}
+
+fn parse_args(line: &str) -> Result<Vec<String>, Ack> {
+  let mut args = Vec::new();
+  let mut chars = line.chars().peekable();
+  loop {
+    skip_whitespace(&mut chars);
+    let arg = match chars.peek() {
+      Some('"') => parse_quoted(&mut chars)?,
+      Some(_) => {
+        let mut arg = String::new();
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+          arg.push(c);
+        }
+        arg
+      }
+      None => return Ok(args),
+    };
+    args.push(arg);
+  }
+}
+
+fn parse_quoted(chars: &mut Peekable<Chars>) -> Result<String, Ack> {
+  let quote = chars.next();
+  let mut value = String::new();
+  loop {
+    match chars.next() {
+      Some('\\') => value.extend(chars.next()),
+      Some(c) if Some(c) == quote => return Ok(value),
+      Some(c) => value.push(c),
+      None => return Err(Ack::new(ACK_ERROR_ARG, "Missing closing quote")),
+    }
+  }
+}
+
+#[derive(Clone, Copy)]
+enum FilterOperator {
+  Equals,
+  NotEquals,
+  Contains,
+  StartsWith,
+}
+
+enum Filter {
+  Tag(String, FilterOperator, String),
+  Not(Box<Filter>),
+  And(Vec<Filter>),
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+  while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+fn read_word(chars: &mut Peekable<Chars>) -> String {
+  let mut word = String::new();
+  while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != ')') {
+    word.push(c);
+  }
+  word
+}
+
+fn parse_filter_expression(chars: &mut Peekable<Chars>, depth: u32) -> Result<Filter, Ack> {
+  let invalid = || Ack::new(ACK_ERROR_ARG, "Invalid filter expression");
+  if depth >= MAX_FILTER_DEPTH {
+    return Err(Ack::new(
+      ACK_ERROR_ARG,
+      "Filter expression is nested too deeply",
+    ));
+  }
+  skip_whitespace(chars);
+  chars.next_if_eq(&'(').ok_or_else(invalid)?;
+  skip_whitespace(chars);
+  let filter = match chars.peek() {
+    Some('(') => {
+      let mut filters = vec![parse_filter_expression(chars, depth + 1)?];
+      loop {
+        skip_whitespace(chars);
+        if chars.peek() == Some(&')') {
+          break;
+        }
+        if read_word(chars) != "AND" {
+          return Err(invalid());
+        }
+        filters.push(parse_filter_expression(chars, depth + 1)?);
+      }
+      Filter::And(filters)
+    }
+    Some('!') => {
+      chars.next();
+      Filter::Not(Box::new(parse_filter_expression(chars, depth + 1)?))
+    }
+    _ => {
+      let tag = read_word(chars);
+      skip_whitespace(chars);
+      let operator = match tag.as_str() {
+        "base" => FilterOperator::StartsWith,
+        _ => match read_word(chars).as_str() {
+          "==" => FilterOperator::Equals,
+          "!=" => FilterOperator::NotEquals,
+          "contains" => FilterOperator::Contains,
+          "starts_with" => FilterOperator::StartsWith,
+          _ => return Err(Ack::new(ACK_ERROR_ARG, "Unsupported filter operator")),
+        },
+      };
+      skip_whitespace(chars);
+      if !matches!(chars.peek(), Some('"') | Some('\'')) {
+        return Err(invalid());
+      }
+      Filter::Tag(tag.to_lowercase(), operator, parse_quoted(chars)?)
+    }
+  };
+  skip_whitespace(chars);
+  chars.next_if_eq(&')').ok_or_else(invalid)?;
+  Ok(filter)
+}
+
+// Accepts both filter expressions and the older "TAG VALUE ..." pairs
+fn parse_filter(args: &[String], operator: FilterOperator) -> Result<Filter, Ack> {
+  let mut filters = Vec::new();
+  let mut args = args.iter();
+  while let Some(arg) = args.next() {
+    if arg.starts_with('(') {
+      filters.push(parse_filter_expression(&mut arg.chars().peekable(), 0)?);
+      continue;
+    }
+    let value = args
+      .next()
+      .ok_or_else(|| Ack::new(ACK_ERROR_ARG, "Missing filter value"))?;
+    match arg.as_str() {
+      "sort" | "window" | "group" => {}
+      _ => filters.push(Filter::Tag(arg.to_lowercase(), operator, value.clone())),
+    }
+  }
+  Ok(Filter::And(filters))
+}
+
+fn get_tag_values<'a>(tags: &'a SongTags, tag: &str) -> Option<Vec<&'a str>> {
+  Some(match tag {
+    "artist" | "albumartist" => vec![tags.artist],
+    "album" => vec![tags.album],
+    "title" => vec![tags.title],
+    "file" | "base" => vec![tags.uri.as_str()],
+    "any" => vec![tags.artist, tags.album, tags.title, tags.uri.as_str()],
+    _ => return None,
+  })
+}
+
+fn matches_filter(filter: &Filter, tags: &SongTags, ignore_case: bool) -> bool {
+  match filter {
+    Filter::And(filters) => filters
+      .iter()
+      .all(|filter| matches_filter(filter, tags, ignore_case)),
+    Filter::Not(filter) => !matches_filter(filter, tags, ignore_case),
+    Filter::Tag(tag, FilterOperator::NotEquals, expected) => !matches_filter(
+      &Filter::Tag(tag.clone(), FilterOperator::Equals, expected.clone()),
+      tags,
+      ignore_case,
+    ),
+    Filter::Tag(tag, operator, expected) => {
+      let values = match get_tag_values(tags, tag) {
+        Some(values) => values,
+        // Tags Ronix doesn't read are always empty
+        None => vec![""],
+      };
+      let expected = match ignore_case {
+        true => expected.to_lowercase(),
+        false => expected.to_string(),
+      };
+      values.into_iter().any(|value| {
+        let value = match ignore_case {
+          true => value.to_lowercase(),
+          false => value.to_string(),
+        };
+        match operator {
+          FilterOperator::Equals | FilterOperator::NotEquals => value == expected,
+          FilterOperator::Contains => value.contains(&expected),
+          FilterOperator::StartsWith => value.starts_with(&expected),
+        }
+      })
+    }
+  }
+}
+
+fn find_songs(
+  library: &Library,
+  music_folders: &[PathBuf],
+  filter: &Filter,
+  ignore_case: bool,
+) -> Vec<String> {
+  let mut songs: Vec<(String, String)> = library
+    .songs
+    .keys()
+    .filter_map(|song_id| {
+      let tags = get_song_tags(song_id, library, music_folders)?;
+      matches_filter(filter, &tags, ignore_case).then(|| (tags.uri, song_id.clone()))
+    })
+    .collect();
+  songs.sort();
+  songs.into_iter().map(|(_, song_id)| song_id).collect()
+}
+
+// Symlinks and `..` are resolved first, so they can't lead out of the music folders
+fn is_in_music_folders(path: &Path, music_folders: &[PathBuf]) -> bool {
+  let path = match path.canonicalize() {
+    Ok(path) => path,
+    Err(_) => return false,
+  };
+  music_folders.iter().any(|folder| {
+    folder
+      .canonicalize()
+      .map_or(false, |folder| path.starts_with(folder))
+  })
+}
+
+// A uri is either a song or a directory of songs
+fn find_songs_by_uri(library: &Library, music_folders: &[PathBuf], uri: &str) -> Vec<String> {
+  let directory = format!("{}/", uri.trim_end_matches('/'));
+  let mut songs: Vec<(String, String)> = library
+    .songs
+    .iter()
+    .map(|(song_id, song)| (get_song_uri(&song.path, music_folders), song_id.clone()))
+    .filter(|(song_uri, _)| uri.is_empty() || song_uri == uri || song_uri.starts_with(&directory))
+    .collect();
+  songs.sort();
+  songs.into_iter().map(|(_, song_id)| song_id).collect()
+}
+
+// Files in the music folders that aren't read yet get added to the library
+fn get_songs_by_uri(
+  library: &mut LibraryGuard,
+  music_folders: &[PathBuf],
+  uri: &str,
+) -> Result<Vec<String>, Ack> {
+  let song_ids = find_songs_by_uri(library, music_folders, uri);
+  if !song_ids.is_empty() {
+    return Ok(song_ids);
+  }
+  let path = file_uri_to_path(uri).unwrap_or(PathBuf::from(uri));
+  // Clients can't make Ronix read files from anywhere else on the machine
+  if path.is_absolute() && is_in_music_folders(&path, music_folders) {
+    if let Some(song_id) = add_external_song(library, &path) {
+      return Ok(vec![song_id]);
+    }
+  }
+  Err(Ack::new(ACK_ERROR_NO_EXIST, "No such song"))
+}
+
+fn get_arg(args: &[String], i: usize) -> Result<&str, Ack> {
+  args
+    .get(i)
+    .map(|arg| arg.as_str())
+    .ok_or_else(|| Ack::new(ACK_ERROR_ARG, "Too few arguments"))
+}
+
+fn parse_number<T: FromStr>(arg: &str) -> Result<T, Ack> {
+  arg
+    .parse()
+    .map_err(|_| Ack::new(ACK_ERROR_ARG, format!("Invalid number: {}", arg)))
+}
+
+fn parse_bool(arg: &str) -> Result<bool, Ack> {
+  match arg {
+    "0" => Ok(false),
+    "1" => Ok(true),
+    _ => Err(Ack::new(ACK_ERROR_ARG, format!("Invalid boolean: {}", arg))),
+  }
+}
+
+fn get_next_position(position: usize) -> Result<usize, Ack> {
+  position
+    .checked_add(1)
+    .ok_or_else(|| Ack::new(ACK_ERROR_ARG, "Bad song index"))
+}
+
+// Either "POS" or "START:END", the end being optional
+fn parse_range(arg: &str) -> Result<(usize, Option<usize>), Ack> {
+  match arg.split_once(':') {
+    Some((start, "")) => Ok((parse_number(start)?, None)),
+    Some((start, end)) => Ok((parse_number(start)?, Some(parse_number(end)?))),
+    None => {
+      let position = parse_number(arg)?;
+      Ok((position, Some(get_next_position(position)?)))
+    }
+  }
+}
+
+fn parse_seconds(arg: &str) -> Result<u32, Ack> {
+  Ok(parse_number::<f64>(arg)?.max(0.0) as u32)
+}
+
+fn find_queue_position(
+  state: &PlayerState,
+  song_numbers: &SongNumbers,
+  number: u32,
+) -> Result<usize, Ack> {
+  let song_id = song_numbers
+    .find(number)
+    .ok_or_else(|| Ack::new(ACK_ERROR_NO_EXIST, "No such song"))?;
+  get_queue(state)
+    .iter()
+    .position(|id| id == song_id)
+    .ok_or_else(|| Ack::new(ACK_ERROR_NO_EXIST, "No such song"))
+}
+
+fn play_position(
+  ctx: &Context,
+  state: &mut PlayerState,
+  library: &Library,
+  position: usize,
+) -> Result<(), Ack> {
+  // Everything queued before the chosen song gets skipped
+  let song_id = match get_queue_entry(state, position) {
+    Some(QueueEntry::Current) => {
+      player::seek(state, &ctx.player, 0);
+      player::play(state, &ctx.player, &ctx.fader);
+      return Ok(());
+    }
+    Some(QueueEntry::Next(i)) => {
+      let song_id = state.next_songs[i].clone();
+      state.next_songs.truncate(i);
+      song_id
+    }
+    Some(QueueEntry::Automatic(i)) => {
+      let song_id = state.automatic_next_songs[i].clone();
+      state.next_songs.clear();
+      state.automatic_next_songs.truncate(i);
+      song_id
+    }
+    None => return Err(Ack::new(ACK_ERROR_ARG, "Bad song index")),
+  };
+  player::play_song(
+    &song_id,
+    library,
+    &ctx.player,
+    Some(&ctx.fader),
+    state,
+    true,
+  );
+  Ok(())
+}
+
+fn remove_position(
+  ctx: &Context,
+  state: &mut PlayerState,
+  library: &Library,
+  position: usize,
+) -> Result<(), Ack> {
+  match get_queue_entry(state, position) {
+    Some(QueueEntry::Current) => player::next_song(state, library, &ctx.player, Some(&ctx.fader)),
+    Some(QueueEntry::Next(i)) => {
+      state.next_songs.remove(i);
+    }
+    Some(QueueEntry::Automatic(i)) => {
+      state.automatic_next_songs.remove(i);
+    }
+    None => return Err(Ack::new(ACK_ERROR_ARG, "Bad song index")),
+  }
+  Ok(())
+}
+
+// The queues are played from the back, so inserting after an index plays before it
+fn insert_at_position(state: &mut PlayerState, position: usize, song_id: String) {
+  match get_queue_entry(state, position) {
+    Some(QueueEntry::Next(i)) => state.next_songs.insert(i + 1, song_id),
+    Some(QueueEntry::Automatic(i)) => state.automatic_next_songs.insert(i + 1, song_id),
+    Some(QueueEntry::Current) | None if position <= 1 => state.next_songs.push(song_id),
+    _ => state.automatic_next_songs.insert(0, song_id),
+  }
+}
+
+fn seek_position(
+  ctx: &Context,
+  state: &mut PlayerState,
+  library: &Library,
+  position: usize,
+  seek_to: u32,
+) -> Result<(), Ack> {
+  if !matches!(get_queue_entry(state, position), Some(QueueEntry::Current)) {
+    play_position(ctx, state, library, position)?;
+  }
+  player::seek(state, &ctx.player, seek_to);
+  Ok(())
+}
+
+fn find_playlist(library: &Library, name: &str) -> Result<String, Ack> {
+  library
+    .playlists
+    .iter()
+    .find(|(_, playlist)| playlist.name == name)
+    .map(|(id, _)| id.clone())
+    .ok_or_else(|| Ack::new(ACK_ERROR_NO_EXIST, "No such playlist"))
+}
+
+fn read_cover(
+  library: &Library,
+  music_folders: &[PathBuf],
+  uri: &str,
+  offset: usize,
+) -> Result<Vec<u8>, Ack> {
+  let song = library
+    .songs
+    .values()
+    .find(|song| get_song_uri(&song.path, music_folders) == uri)
+    .ok_or_else(|| Ack::new(ACK_ERROR_NO_EXIST, "No such song"))?;
+  let cover = get_original_cover(library, &song.album)
+    .and_then(|cover_art| fs::read(cover_art).ok())
+    .ok_or_else(|| Ack::new(ACK_ERROR_NO_EXIST, "No file exists"))?;
+  let chunk = cover.get(offset..).unwrap_or_default();
+  let chunk = &chunk[..chunk.len().min(BINARY_CHUNK_SIZE)];
+  let mut out = format!("size: {}\nbinary: {}\n", cover.len(), chunk.len()).into_bytes();
+  out.extend_from_slice(chunk);
+  out.push(b'\n');
+  Ok(out)
+}
+
+// Scanning takes a while, so it runs without holding the locks. Song ids change on every scan,
+// so the queue is moved over to the new ids by path
+fn start_update(
+  ctx: &Context,
+  server: &Arc<Server>,
+  music_folders: Vec<PathBuf>,
+  cover_art_names: Vec<String>,
+) {
+  let ctx = ctx.clone();
+  let server = server.clone();
+  tauri::async_runtime::spawn(async move {
+    let queued_paths: Vec<PathBuf> = {
+      let state = ctx.player_state.lock().unwrap();
+      let library = ctx.library.lock().unwrap();
+      get_queue(&state)
+        .iter()
+        .chain(state.previous_songs.iter())
+        .filter_map(|song_id| library.songs.get(song_id))
+        .map(|song| song.path.clone())
+        .collect()
+    };
+    let new_library = tokio::task::spawn_blocking(move || {
+      let mut new_library = read_from_dirs(&music_folders, &cover_art_names);
+      keep_external_songs(&mut new_library, &queued_paths);
+      new_library
+    })
+    .await;
+    let new_library = match new_library {
+      Ok(new_library) => new_library,
+      Err(e) => {
+        println!("Failed to update the library: {}", e);
+        return;
+      }
+    };
+    let mut state = ctx.player_state.lock().unwrap();
+    let mut library = ctx.library.lock().unwrap();
+    let mut song_numbers = server.song_numbers.lock().unwrap();
+    let new_ids_by_path: HashMap<&PathBuf, &String> = new_library
+      .songs
+      .iter()
+      .map(|(song_id, song)| (&song.path, song_id))
+      .collect();
+    let new_ids: HashMap<String, String> = library
+      .songs
+      .iter()
+      .filter_map(|(song_id, song)| {
+        let new_id = new_ids_by_path.get(&song.path)?;
+        Some((song_id.clone(), new_id.to_string()))
+      })
+      .collect();
+    let remap = |song_ids: &[String]| -> Vec<String> {
+      song_ids
+        .iter()
+        .filter_map(|song_id| new_ids.get(song_id).cloned())
+        .collect()
+    };
+    state.current_song = state
+      .current_song
+      .as_ref()
+      .and_then(|song_id| new_ids.get(song_id).cloned());
+    state.previous_songs = remap(&state.previous_songs);
+    state.next_songs = remap(&state.next_songs);
+    state.automatic_next_songs = remap(&state.automatic_next_songs);
+    song_numbers.remap(&new_ids);
+    library.replace(new_library);
+  });
+}
+
+fn run_command(ctx: &Context, server: &Arc<Server>, args: &[String]) -> Result<Vec<u8>, Ack> {
+  let (music_folders, cover_art_names) = {
+    let config = ctx.config.lock().unwrap();
+    (config.music_folders.clone(), config.cover_art_names.clone())
+  };
+  let mut state = ctx.player_state.lock().unwrap();
+  let mut library = ctx.library.lock().unwrap();
+  let mut song_numbers = server.song_numbers.lock().unwrap();
+  let mut out = String::new();
+  match args[0].as_str() {
+    "ping" | "clearerror" | "binarylimit" => {}
+    "tagtypes" => {
+      if args.len() == 1 {
+        for tag_type in TAG_TYPES {
+          push_pair(&mut out, "tagtype", tag_type);
+        }
+      }
+    }
+    "commands" => {
+      for command in COMMANDS {
+        push_pair(&mut out, "command", command);
+      }
+    }
+    "notcommands" | "decoders" | "channels" | "readmessages" | "listmounts" | "listneighbors" => {}
+    "urlhandlers" => push_pair(&mut out, "handler", "file://"),
+    "outputs" => {
+      push_pair(&mut out, "outputid", 0);
+      push_pair(&mut out, "outputname", "Ronix");
+      push_pair(&mut out, "plugin", "gstreamer");
+      push_pair(&mut out, "outputenabled", 1);
+    }
+    "replay_gain_status" => push_pair(&mut out, "replay_gain_mode", "off"),
+    "status" => {
+      let queue = get_queue(&state);
+      push_pair(&mut out, "volume", (state.volume * 100.0).round());
+      push_pair(
+        &mut out,
+        "repeat",
+        (state.repeat_mode != RepeatMode::None) as u8,
+      );
+      push_pair(&mut out, "random", state.is_shuffled as u8);
+      push_pair(
+        &mut out,
+        "single",
+        (state.repeat_mode == RepeatMode::One) as u8,
+      );
+      push_pair(&mut out, "consume", 0);
+      push_pair(&mut out, "playlist", hash_value(&queue) as u32);
+      push_pair(&mut out, "playlistlength", queue.len());
+      push_pair(&mut out, "mixrampdb", "0.000000");
+      let song = state
+        .current_song
+        .as_ref()
+        .and_then(|song_id| library.songs.get(song_id));
+      push_pair(
+        &mut out,
+        "state",
+        match (song, state.paused_at) {
+          (None, _) => "stop",
+          (Some(_), Some(_)) if state.is_stopped => "stop",
+          (Some(_), Some(_)) => "pause",
+          (Some(_), None) => "play",
+        },
+      );
+      if let Some(song) = song {
+        let position = get_position(&state);
+        push_pair(&mut out, "song", 0);
+        push_pair(&mut out, "songid", song_numbers.get(&queue[0]));
+        push_pair(&mut out, "time", format!("{}:{}", position, song.duration));
+        push_pair(&mut out, "elapsed", format!("{}.000", position));
+        push_pair(&mut out, "duration", format!("{}.000", song.duration));
+        push_pair(&mut out, "bitrate", 0);
+        if let Some(next_song) = queue.get(1) {
+          push_pair(&mut out, "nextsong", 1);
+          push_pair(&mut out, "nextsongid", song_numbers.get(next_song));
+        }
+      }
+    }
+    "currentsong" => {
+      if let Some(song_id) = &state.current_song {
+        push_queue_song(
+          &mut out,
+          0,
+          song_id,
+          &library,
+          &music_folders,
+          &mut song_numbers,
+        );
+      }
+    }
+    "stats" => {
+      push_pair(&mut out, "artists", library.artists.len());
+      push_pair(&mut out, "albums", library.albums.len());
+      push_pair(&mut out, "songs", library.songs.len());
+      push_pair(&mut out, "uptime", get_current_time() - server.started_at);
+      push_pair(
+        &mut out,
+        "db_playtime",
+        library
+          .songs
+          .values()
+          .map(|song| song.duration as u64)
+          .sum::<u64>(),
+      );
+      push_pair(&mut out, "playtime", 0);
+    }
+    "play" => match args.get(1) {
+      Some(position) => play_position(ctx, &mut state, &library, parse_number(position)?)?,
+      None if state.current_song.is_none() => play_position(ctx, &mut state, &library, 0)?,
+      None => player::play(&mut state, &ctx.player, &ctx.fader),
+    },
+    "playid" => match args.get(1) {
+      Some(number) => {
+        let position = find_queue_position(&state, &song_numbers, parse_number(number)?)?;
+        play_position(ctx, &mut state, &library, position)?
+      }
+      None => player::play(&mut state, &ctx.player, &ctx.fader),
+    },
+    "pause" => match args.get(1) {
+      Some(pause) if parse_bool(pause)? => player::pause(&mut state, &ctx.player, &ctx.fader),
+      Some(_) => player::play(&mut state, &ctx.player, &ctx.fader),
+      None if state.current_song.is_some() => {
+        player::toggle_pause(&mut state, &ctx.player, &ctx.fader)
+      }
+      None => {}
+    },
+    "stop" => player::stop(&mut state, &ctx.player, &ctx.fader),
+    "next" => player::next_song(&mut state, &library, &ctx.player, Some(&ctx.fader)),
+    "previous" => player::previous_song(&mut state, &ctx.player, &ctx.fader, &library),
+    "seekcur" => {
+      if state.current_song.is_none() {
+        return Err(Ack::new(ACK_ERROR_ARG, "Not playing"));
+      }
+      let time = get_arg(args, 1)?;
+      let position = get_position(&state);
+      let seek_to = match time.chars().next() {
+        Some('+') => position.saturating_add(parse_seconds(&time[1..])?),
+        Some('-') => position.saturating_sub(parse_seconds(&time[1..])?),
+        _ => parse_seconds(time)?,
+      };
+      player::seek(&mut state, &ctx.player, seek_to);
+    }
+    "seek" => {
+      let position = parse_number(get_arg(args, 1)?)?;
+      let seek_to = parse_seconds(get_arg(args, 2)?)?;
+      seek_position(ctx, &mut state, &library, position, seek_to)?;
+    }
+    "seekid" => {
+      let position = find_queue_position(&state, &song_numbers, parse_number(get_arg(args, 1)?)?)?;
+      let seek_to = parse_seconds(get_arg(args, 2)?)?;
+      seek_position(ctx, &mut state, &library, position, seek_to)?;
+    }
+    "setvol" => {
+      let volume: f64 = parse_number(get_arg(args, 1)?)?;
+      ctx.player.set_volume((volume / 100.0).clamp(0.0, 1.0));
+    }
+    "volume" => {
+      let change: f64 = parse_number(get_arg(args, 1)?)?;
+      ctx
+        .player
+        .set_volume((state.volume + change / 100.0).clamp(0.0, 1.0));
+    }
+    "getvol" => push_pair(&mut out, "volume", (state.volume * 100.0).round()),
+    "repeat" => {
+      state.repeat_mode = match (parse_bool(get_arg(args, 1)?)?, &state.repeat_mode) {
+        (false, _) => RepeatMode::None,
+        (true, RepeatMode::None) => RepeatMode::All,
+        (true, repeat_mode) => repeat_mode.clone(),
+      };
+    }
+    "single" => {
+      // Ronix has no single mode that stops, so single maps to repeating one song
+      state.repeat_mode = match (get_arg(args, 1)?, &state.repeat_mode) {
+        ("1", _) => RepeatMode::One,
+        ("0", RepeatMode::One) => RepeatMode::All,
+        ("0" | "oneshot", repeat_mode) => repeat_mode.clone(),
+        _ => return Err(Ack::new(ACK_ERROR_ARG, "Invalid single mode")),
+      };
+    }
+    "random" => {
+      let is_shuffled = parse_bool(get_arg(args, 1)?)?;
+      player::set_shuffle(&mut state, &library, is_shuffled);
+    }
+    "consume" => {
+      if parse_bool(get_arg(args, 1)?)? {
+        return Err(Ack::new(ACK_ERROR_ARG, "Consume mode is not supported"));
+      }
+    }
+    "add" => {
+      let song_ids = get_songs_by_uri(&mut library, &music_folders, get_arg(args, 1)?)?;
+      player::queue_songs(&mut state, song_ids);
+    }
+    "addid" => {
+      let song_id = get_songs_by_uri(&mut library, &music_folders, get_arg(args, 1)?)?
+        .into_iter()
+        .next()
+        .unwrap();
+      push_pair(&mut out, "Id", song_numbers.get(&song_id));
+      match args.get(2) {
+        Some(position) => insert_at_position(&mut state, parse_number(position)?, song_id),
+        None => player::queue_songs(&mut state, vec![song_id]),
+      }
+    }
+    "clear" => {
+      player::stop(&mut state, &ctx.player, &ctx.fader);
+      state.current_song = None;
+      state.next_songs.clear();
+      state.automatic_next_songs.clear();
+    }
+    "delete" => {
+      let (start, end) = parse_range(get_arg(args, 1)?)?;
+      let end = end.unwrap_or(get_queue(&state).len());
+      // Removing from the end keeps the earlier positions valid
+      for position in (start..end).rev() {
+        remove_position(ctx, &mut state, &library, position)?;
+      }
+    }
+    "deleteid" => {
+      let position = find_queue_position(&state, &song_numbers, parse_number(get_arg(args, 1)?)?)?;
+      remove_position(ctx, &mut state, &library, position)?;
+    }
+    "playlistinfo" | "playlistid" | "plchanges" | "plchangesposid" => {
+      let queue = get_queue(&state);
+      let (start, end) = match (args[0].as_str(), args.get(1)) {
+        ("playlistinfo", Some(range)) => parse_range(range)?,
+        ("playlistid", Some(number)) => {
+          let position = find_queue_position(&state, &song_numbers, parse_number(number)?)?;
+          (position, Some(get_next_position(position)?))
+        }
+        _ => (0, None),
+      };
+      let end = end.unwrap_or(queue.len()).min(queue.len());
+      for position in start..end {
+        if args[0] == "plchangesposid" {
+          push_pair(&mut out, "cpos", position);
+          push_pair(&mut out, "Id", song_numbers.get(&queue[position]));
+        } else {
+          push_queue_song(
+            &mut out,
+            position,
+            &queue[position],
+            &library,
+            &music_folders,
+            &mut song_numbers,
+          );
+        }
+      }
+    }
+    "lsinfo" | "listall" | "listallinfo" => {
+      let uri = args.get(1).map(|uri| uri.trim_matches('/')).unwrap_or("");
+      let prefix = match uri {
+        "" => String::new(),
+        _ => format!("{}/", uri),
+      };
+      let mut songs: Vec<(String, &String)> = library
+        .songs
+        .iter()
+        .map(|(song_id, song)| (get_song_uri(&song.path, &music_folders), song_id))
+        .filter(|(song_uri, _)| song_uri.starts_with(&prefix) || song_uri == uri)
+        .collect();
+      if songs.is_empty() && !uri.is_empty() {
+        return Err(Ack::new(ACK_ERROR_NO_EXIST, "No such directory"));
+      }
+      songs.sort();
+      // lsinfo only shows direct children, listall goes through every directory
+      let is_recursive = args[0] != "lsinfo";
+      let mut directories = BTreeSet::new();
+      for (song_uri, song_id) in songs.iter() {
+        let relative = match song_uri.strip_prefix(&prefix) {
+          Some(relative) => relative,
+          None => song_uri.rsplit('/').next().unwrap(),
+        };
+        let mut directory = prefix.clone();
+        for component in relative
+          .split('/')
+          .rev()
+          .skip(1)
+          .collect::<Vec<&str>>()
+          .into_iter()
+          .rev()
+        {
+          directory.push_str(component);
+          if directories.insert(directory.clone()) {
+            push_pair(&mut out, "directory", &directory);
+          }
+          if !is_recursive {
+            break;
+          }
+          directory.push('/');
+        }
+        if is_recursive || !relative.contains('/') {
+          match args[0].as_str() {
+            "listall" => push_pair(&mut out, "file", song_uri),
+            _ => push_song(&mut out, song_id, &library, &music_folders),
+          }
+        }
+      }
+      if args[0] == "lsinfo" && uri.is_empty() {
+        for playlist in library.playlists.values() {
+          push_pair(&mut out, "playlist", &playlist.name);
+        }
+      }
+    }
+    "find" | "search" | "findadd" | "searchadd" | "count" => {
+      let ignore_case = args[0].starts_with("search");
+      let operator = match ignore_case {
+        true => FilterOperator::Contains,
+        false => FilterOperator::Equals,
+      };
+      let filter = parse_filter(&args[1..], operator)?;
+      let song_ids = find_songs(&library, &music_folders, &filter, ignore_case);
+      match args[0].as_str() {
+        "findadd" | "searchadd" => player::queue_songs(&mut state, song_ids),
+        "count" => {
+          push_pair(&mut out, "songs", song_ids.len());
+          push_pair(
+            &mut out,
+            "playtime",
+            song_ids
+              .iter()
+              .map(|song_id| library.songs.get(song_id).unwrap().duration as u64)
+              .sum::<u64>(),
+          );
+        }
+        _ => {
+          for song_id in song_ids.iter() {
+            push_song(&mut out, song_id, &library, &music_folders);
+          }
+        }
+      }
+    }
+    "list" => {
+      let tag = get_arg(args, 1)?.to_lowercase();
+      // "list album ARTIST" is an old shorthand for filtering albums by artist
+      let filter = match &args[2..] {
+        [artist] if tag == "album" && !artist.starts_with('(') => {
+          Filter::Tag("artist".to_string(), FilterOperator::Equals, artist.clone())
+        }
+        filter_args => parse_filter(filter_args, FilterOperator::Equals)?,
+      };
+      let key = match tag.as_str() {
+        "artist" => "Artist",
+        "albumartist" => "AlbumArtist",
+        "album" => "Album",
+        "title" => "Title",
+        "file" => "file",
+        _ => return Ok(Vec::new()),
+      };
+      let values: BTreeSet<String> = find_songs(&library, &music_folders, &filter, false)
+        .iter()
+        .filter_map(|song_id| get_song_tags(song_id, &library, &music_folders))
+        .filter_map(|tags| get_tag_values(&tags, &tag).map(|values| values[0].to_string()))
+        .collect();
+      for value in values {
+        push_pair(&mut out, key, value);
+      }
+    }
+    "albumart" | "readpicture" => {
+      let offset = parse_number(get_arg(args, 2)?)?;
+      return read_cover(&library, &music_folders, get_arg(args, 1)?, offset);
+    }
+    "update" | "rescan" => {
+      start_update(ctx, server, music_folders.clone(), cover_art_names.clone());
+      push_pair(&mut out, "updating_db", 1);
+    }
+    "listplaylists" => {
+      for playlist in library.playlists.values() {
+        push_pair(&mut out, "playlist", &playlist.name);
+      }
+    }
+    "listplaylist" | "listplaylistinfo" => {
+      let id = find_playlist(&library, get_arg(args, 1)?)?;
+      for song_id in library.playlists.get(&id).unwrap().songs.iter() {
+        match args[0].as_str() {
+          "listplaylist" => {
+            if let Some(song) = library.songs.get(song_id) {
+              push_pair(&mut out, "file", get_song_uri(&song.path, &music_folders));
+            }
+          }
+          _ => push_song(&mut out, song_id, &library, &music_folders),
+        }
+      }
+    }
+    "load" => {
+      let id = find_playlist(&library, get_arg(args, 1)?)?;
+      let song_ids = library.playlists.get(&id).unwrap().songs.clone();
+      player::queue_songs(&mut state, song_ids);
+    }
+    "save" => {
+      let name = get_arg(args, 1)?.to_string();
+      if find_playlist(&library, &name).is_ok() {
+        return Err(Ack::new(ACK_ERROR_ARG, "Playlist already exists"));
+      }
+      create_playlist(&mut library, name, get_queue(&state));
+      library.mark_changed();
+    }
+    "rm" => {
+      let id = find_playlist(&library, get_arg(args, 1)?)?;
+      library.playlists.remove(&id);
+      write_playlists(&library);
+      library.mark_changed();
+    }
+    "rename" => {
+      let id = find_playlist(&library, get_arg(args, 1)?)?;
+      library.playlists.get_mut(&id).unwrap().name = get_arg(args, 2)?.to_string();
+      write_playlists(&library);
+      library.mark_changed();
+    }
+    "playlistadd" => {
+      let id = find_playlist(&library, get_arg(args, 1)?)?;
+      let song_ids = get_songs_by_uri(&mut library, &music_folders, get_arg(args, 2)?)?;
+      library
+        .playlists
+        .get_mut(&id)
+        .unwrap()
+        .songs
+        .extend(song_ids);
+      write_playlists(&library);
+      library.mark_changed();
+    }
+    "playlistclear" => {
+      let id = find_playlist(&library, get_arg(args, 1)?)?;
+      library.playlists.get_mut(&id).unwrap().songs.clear();
+      write_playlists(&library);
+      library.mark_changed();
+    }
+    "playlistdelete" => {
+      let id = find_playlist(&library, get_arg(args, 1)?)?;
+      let position: usize = parse_number(get_arg(args, 2)?)?;
+      let playlist = library.playlists.get_mut(&id).unwrap();
+      if position >= playlist.songs.len() {
+        return Err(Ack::new(ACK_ERROR_ARG, "Bad song index"));
+      }
+      playlist.songs.remove(position);
+      write_playlists(&library);
+      library.mark_changed();
+    }
+    command => {
+      return Err(Ack::new(
+        ACK_ERROR_UNKNOWN,
+        format!("unknown command \"{}\"", command),
+      ))
+    }
+  }
+  Ok(out.into_bytes())
+}
+
+// Lines without a length limit could fill the memory. Partial lines are kept between calls,
+// so reading can be cancelled in a select
+struct LineReader {
+  reader: BufReader<OwnedReadHalf>,
+  line: Vec<u8>,
+}
+
+impl LineReader {
+  async fn next_line(&mut self) -> io::Result<Option<String>> {
+    loop {
+      let available = self.reader.fill_buf().await?;
+      if available.is_empty() {
+        return Ok(None);
+      }
+      let (length, is_line_end) = match available.iter().position(|byte| *byte == b'\n') {
+        Some(end) => (end, true),
+        None => (available.len(), false),
+      };
+      self.line.extend_from_slice(&available[..length]);
+      self.reader.consume(length + is_line_end as usize);
+      if self.line.len() > MAX_LINE_LENGTH {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Line too long"));
+      }
+      if is_line_end {
+        let line = String::from_utf8(mem::take(&mut self.line))
+          .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        return Ok(Some(line.trim_end_matches('\r').to_string()));
+      }
+    }
+  }
+}
+
+async fn handle_connection(ctx: Context, server: Arc<Server>, stream: TcpStream) -> io::Result<()> {
+  let (reader, mut writer) = stream.into_split();
+  let mut lines = LineReader {
+    reader: BufReader::new(reader),
+    line: Vec::new(),
+  };
+  writer.write_all(GREETING).await?;
+  let mut seen_hashes = get_subsystem_hashes(&ctx);
+  let mut is_authorized = ctx.config.lock().unwrap().mpd.password.is_empty();
+  let mut command_list: Option<(bool, Vec<Vec<String>>)> = None;
+  let mut command_list_size = 0;
+  while let Some(line) = lines.next_line().await? {
+    let mpd = ctx.config.lock().unwrap().mpd.clone();
+    // Connections made before the server got disabled are closed on their next command
+    if !mpd.enabled {
+      return Ok(());
+    }
+    let args = match parse_args(&line) {
+      Ok(args) if args.is_empty() => continue,
+      Ok(args) => args,
+      Err(ack) => {
+        let response = format!("ACK [{}@0] {{}} {}\n", ack.code, ack.message);
+        writer.write_all(response.as_bytes()).await?;
+        continue;
+      }
+    };
+    match args[0].as_str() {
+      "close" => return Ok(()),
+      "password" => {
+        let response = match args.get(1) == Some(&mpd.password) {
+          true => {
+            is_authorized = true;
+            "OK\n".to_string()
+          }
+          false => format!(
+            "ACK [{}@0] {{password}} incorrect password\n",
+            ACK_ERROR_PASSWORD
+          ),
+        };
+        writer.write_all(response.as_bytes()).await?;
+        continue;
+      }
+      _ if !is_authorized => {
+        let response = format!(
+          "ACK [{}@0] {{{}}} you don't have permission for \"{}\"\n",
+          ACK_ERROR_PERMISSION, args[0], args[0]
+        );
+        writer.write_all(response.as_bytes()).await?;
+        continue;
+      }
+      "command_list_begin" | "command_list_ok_begin" => {
+        command_list = Some((args[0] == "command_list_ok_begin", Vec::new()));
+        command_list_size = 0;
+        continue;
+      }
+      "idle" => {
+        let subsystems: Vec<&'static str> = match args.len() {
+          1 => SUBSYSTEMS.to_vec(),
+          _ => SUBSYSTEMS
+            .iter()
+            .filter(|subsystem| args[1..].iter().any(|arg| arg.as_str() == **subsystem))
+            .cloned()
+            .collect(),
+        };
+        loop {
+          tokio::select! {
+            line = lines.next_line() => {
+              // Only noidle is allowed while idling
+              match line? {
+                Some(line) if line.trim() == "noidle" => break,
+                _ => return Ok(()),
+              }
+            }
+            _ = sleep(Duration::from_millis(100)) => {
+              let hashes = get_subsystem_hashes(&ctx);
+              let changed: Vec<&'static str> = subsystems
+                .iter()
+                .filter(|subsystem| hashes.get(**subsystem) != seen_hashes.get(**subsystem))
+                .cloned()
+                .collect();
+              if !changed.is_empty() {
+                let mut response = String::new();
+                for subsystem in changed {
+                  seen_hashes.insert(subsystem, hashes[subsystem]);
+                  push_pair(&mut response, "changed", subsystem);
+                }
+                writer.write_all(response.as_bytes()).await?;
+                break;
+              }
+            }
+          }
+        }
+        writer.write_all(b"OK\n").await?;
+        continue;
+      }
+      "noidle" => continue,
+      _ => {}
+    }
+    let (is_list_ok, commands) = match command_list.take() {
+      Some(list) if args[0] == "command_list_end" => list,
+      Some((is_list_ok, mut commands)) => {
+        command_list_size += line.len();
+        if command_list_size > MAX_COMMAND_LIST_SIZE {
+          return Ok(());
+        }
+        commands.push(args);
+        command_list = Some((is_list_ok, commands));
+        continue;
+      }
+      None => (false, vec![args]),
+    };
+    let mut response = Vec::new();
+    let mut failed = false;
+    for (i, args) in commands.iter().enumerate() {
+      match run_command(&ctx, &server, args) {
+        Ok(out) => {
+          response.extend(out);
+          if is_list_ok {
+            response.extend(b"list_OK\n");
+          }
+        }
+        Err(ack) => {
+          response.extend(
+            format!("ACK [{}@{}] {{{}}} {}\n", ack.code, i, args[0], ack.message).into_bytes(),
+          );
+          failed = true;
+          break;
+        }
+      }
+    }
+    if !failed {
+      response.extend(b"OK\n");
+    }
+    writer.write_all(&response).await?;
+  }
+  Ok(())
+}
+
+// Stops the running MPD server and starts it again with the current config
+pub fn start(ctx: &Context) {
+  let mut mpd_server = ctx.mpd_server.lock().unwrap();
+  if let Some(server) = mpd_server.take() {
+    server.abort();
+  }
+  let mpd = ctx.config.lock().unwrap().mpd.clone();
+  if !mpd.enabled {
+    return;
+  }
+  let ctx = ctx.clone();
+  *mpd_server = Some(tauri::async_runtime::spawn(async move {
+    let listener = match TcpListener::bind(("0.0.0.0", mpd.port)).await {
+      Ok(listener) => listener,
+      Err(e) => return println!("Failed to start MPD server: {}", e),
+    };
+    let server = Arc::new(Server {
+      song_numbers: Mutex::new(SongNumbers::default()),
+      started_at: get_current_time(),
+    });
+    loop {
+      let stream = match listener.accept().await {
+        Ok((stream, _)) => stream,
+        Err(_) => continue,
+      };
+      let ctx = ctx.clone();
+      let server = server.clone();
+      tokio::spawn(async move {
+        if let Err(e) = handle_connection(ctx, server, stream).await {
+          println!("Failed to handle MPD connection: {}", e);
+        }
+      });
+    }
+  }));
+}
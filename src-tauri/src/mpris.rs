@@ -0,0 +1,1000 @@
+use crate::{
+  covers::get_original_cover,
+  library::{add_external_song, Library, LibraryGuard},
+  player,
+  playlist::Playlist,
+  Context, PlayerScope, PlayerState, RepeatMode,
+};
+use gst::ClockTime;
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+use tokio::time::sleep;
+use zbus::{
+  dbus_interface, fdo,
+  zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value},
+  Connection, ConnectionBuilder, SignalContext,
+};
+
+const MPRIS_PATH: &str = "/org/mpris/MediaPlayer2";
+const TRACK_ID_PREFIX: &str = "/dev/ronanru/ronix/track/";
+const PLAYLIST_ID_PREFIX: &str = "/dev/ronanru/ronix/playlist/";
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+const TRACKLIST_LIMIT: usize = 1000;
+
+// Ids may contain characters that are not allowed in object paths
+fn encode_object_path(prefix: &str, id: &str) -> String {
+  let encoded: String = id.bytes().map(|b| format!("{:02x}", b)).collect();
+  format!("{}{}", prefix, encoded)
+}
+
+fn decode_object_path(prefix: &str, path: &str) -> Option<String> {
+  let encoded = path.strip_prefix(prefix)?;
+  let bytes = (0..encoded.len())
+    .step_by(2)
+    .map(|i| {
+      encoded
+        .get(i..i + 2)
+        .and_then(|b| u8::from_str_radix(b, 16).ok())
+    })
+    .collect::<Option<Vec<u8>>>()?;
+  String::from_utf8(bytes).ok()
+}
+
+pub fn get_track_id(song_id: &str) -> String {
+  encode_object_path(TRACK_ID_PREFIX, song_id)
+}
+
+pub fn parse_track_id(track_id: &str) -> Option<String> {
+  decode_object_path(TRACK_ID_PREFIX, track_id)
+}
+
+fn get_playlist_tuple(playlist_id: &str, name: &str) -> (OwnedObjectPath, String, String) {
+  (
+    OwnedObjectPath::try_from(encode_object_path(PLAYLIST_ID_PREFIX, playlist_id)).unwrap(),
+    name.to_string(),
+    String::new(),
+  )
+}
+
+pub fn file_uri_to_path(uri: &str) -> Option<PathBuf> {
+  let path = uri.strip_prefix("file://")?;
+  let bytes = path.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    if bytes[i] == b'%' {
+      if let Some(byte) = path
+        .get(i + 1..i + 3)
+        .and_then(|h| u8::from_str_radix(h, 16).ok())
+      {
+        decoded.push(byte);
+        i += 3;
+        continue;
+      }
+    }
+    decoded.push(bytes[i]);
+    i += 1;
+  }
+  String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+fn find_song_by_uri(library: &mut LibraryGuard, uri: &str) -> fdo::Result<String> {
+  let path = file_uri_to_path(uri)
+    .ok_or_else(|| fdo::Error::InvalidArgs(format!("Unsupported URI: {}", uri)))?;
+  add_external_song(library, &path)
+    .ok_or_else(|| fdo::Error::Failed(format!("Could not read {}", path.display())))
+}
+
+fn get_tracks(state: &PlayerState) -> Vec<String> {
+  let mut tracks: Vec<String> = Vec::new();
+  for song_id in state
+    .current_song
+    .iter()
+    .chain(state.next_songs.iter().rev())
+    .chain(state.automatic_next_songs.iter().rev())
+  {
+    if tracks.len() == TRACKLIST_LIMIT {
+      break;
+    }
+    // Track ids have to be unique, so repeated songs only show up once
+    if !tracks.contains(song_id) {
+      tracks.push(song_id.clone());
+    }
+  }
+  tracks
+}
+
+pub fn get_song_metadata(song_id: &str, library: &Library) -> HashMap<String, OwnedValue> {
+  let mut metadata = HashMap::new();
+  match library.songs.get(song_id) {
+    Some(song) => {
+      let album = library.albums.get(&song.album).unwrap();
+      let artist = library.artists.get(&album.artist).unwrap();
+      metadata.insert(
+        "mpris:trackid".to_string(),
+        Value::from(ObjectPath::try_from(get_track_id(song_id)).unwrap()).into(),
+      );
+      metadata.insert(
+        "mpris:length".to_string(),
+        Value::from(song.duration as i64 * 1_000_000).into(),
+      );
+      // The cached cover is only looked up again when it was evicted
+      if let Some(cover_art) = album
+        .cover_art
+        .as_ref()
+        .map(PathBuf::from)
+        .filter(|cover_art| cover_art.exists())
+        .or_else(|| get_original_cover(library, &song.album))
+      {
+        metadata.insert(
+          "mpris:artUrl".to_string(),
+          Value::from(format!("file://{}", cover_art.to_string_lossy())).into(),
+        );
+      }
+      metadata.insert(
+        "xesam:title".to_string(),
+        Value::from(song.title.clone()).into(),
+      );
+      metadata.insert(
+        "xesam:album".to_string(),
+        Value::from(album.name.clone()).into(),
+      );
+      metadata.insert(
+        "xesam:artist".to_string(),
+        Value::from(vec![artist.name.clone()]).into(),
+      );
+      metadata.insert(
+        "xesam:url".to_string(),
+        Value::from(format!("file://{}", song.path.to_str().unwrap())).into(),
+      );
+    }
+    None => {
+      metadata.insert(
+        "mpris:trackid".to_string(),
+        Value::from(ObjectPath::from_static_str(NO_TRACK).unwrap()).into(),
+      );
+    }
+  }
+  metadata
+}
+
+struct RootInterface;
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2")]
+impl RootInterface {
+  fn raise(&self) {}
+
+  fn quit(&self) {}
+
+  #[dbus_interface(property)]
+  fn can_quit(&self) -> bool {
+    false
+  }
+
+  #[dbus_interface(property)]
+  fn can_raise(&self) -> bool {
+    false
+  }
+
+  #[dbus_interface(property)]
+  fn has_track_list(&self) -> bool {
+    true
+  }
+
+  #[dbus_interface(property)]
+  fn identity(&self) -> String {
+    "Ronix".to_string()
+  }
+
+  #[dbus_interface(property)]
+  fn desktop_entry(&self) -> String {
+    "ronix".to_string()
+  }
+
+  #[dbus_interface(property)]
+  fn supported_uri_schemes(&self) -> Vec<String> {
+    vec!["file".to_string()]
+  }
+
+  #[dbus_interface(property)]
+  fn supported_mime_types(&self) -> Vec<String> {
+    [
+      "audio/mpeg",
+      "audio/flac",
+      "audio/ogg",
+      "audio/opus",
+      "audio/mp4",
+      "audio/aac",
+      "audio/x-wav",
+      "audio/x-aiff",
+      "audio/x-ape",
+      "audio/x-wavpack",
+      "audio/x-musepack",
+    ]
+    .iter()
+    .map(|m| m.to_string())
+    .collect()
+  }
+}
+
+struct PlayerInterface {
+  ctx: Context,
+}
+
+impl PlayerInterface {
+  // In microseconds from GStreamer, which seeking goes by too
+  fn get_position(&self) -> i64 {
+    self
+      .ctx
+      .player
+      .position()
+      .map_or(0, |position| position.useconds() as i64)
+  }
+}
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2.Player")]
+impl PlayerInterface {
+  fn next(&self) {
+    player::next_song(
+      &mut self.ctx.player_state.lock().unwrap(),
+      &self.ctx.library.lock().unwrap(),
+      &self.ctx.player,
+      Some(&self.ctx.fader),
+    )
+  }
+
+  fn previous(&self) {
+    player::previous_song(
+      &mut self.ctx.player_state.lock().unwrap(),
+      &self.ctx.player,
+      &self.ctx.fader,
+      &self.ctx.library.lock().unwrap(),
+    )
+  }
+
+  fn pause(&self) {
+    player::pause(
+      &mut self.ctx.player_state.lock().unwrap(),
+      &self.ctx.player,
+      &self.ctx.fader,
+    );
+  }
+
+  fn play_pause(&self) {
+    let mut state = self.ctx.player_state.lock().unwrap();
+    if state.current_song.is_some() {
+      player::toggle_pause(&mut state, &self.ctx.player, &self.ctx.fader);
+    }
+  }
+
+  fn stop(&self) {
+    let mut state = self.ctx.player_state.lock().unwrap();
+    if state.current_song.is_some() {
+      player::stop(&mut state, &self.ctx.player, &self.ctx.fader);
+    }
+  }
+
+  fn play(&self) {
+    player::play(
+      &mut self.ctx.player_state.lock().unwrap(),
+      &self.ctx.player,
+      &self.ctx.fader,
+    );
+  }
+
+  fn seek(&self, offset: i64) {
+    let mut state = self.ctx.player_state.lock().unwrap();
+    let library = self.ctx.library.lock().unwrap();
+    let duration = match state
+      .current_song
+      .as_ref()
+      .and_then(|song_id| library.songs.get(song_id))
+    {
+      Some(song) => song.duration as i64,
+      None => return,
+    };
+    let position = self.get_position().saturating_add(offset);
+    if position >= duration * 1_000_000 {
+      player::next_song(
+        &mut state,
+        &library,
+        &self.ctx.player,
+        Some(&self.ctx.fader),
+      );
+    } else {
+      let position = ClockTime::from_useconds(position.max(0) as u64);
+      player::seek_precisely(&mut state, &self.ctx.player, position);
+    }
+  }
+
+  #[dbus_interface(name = "SetPosition")]
+  fn set_track_position(&self, track_id: ObjectPath<'_>, position: i64) {
+    let mut state = self.ctx.player_state.lock().unwrap();
+    let library = self.ctx.library.lock().unwrap();
+    let current_song = match &state.current_song {
+      Some(song_id) if get_track_id(song_id) == track_id.as_str() => song_id,
+      _ => return,
+    };
+    let duration = library.songs.get(current_song).unwrap().duration as i64;
+    if (0..=duration * 1_000_000).contains(&position) {
+      let position = ClockTime::from_useconds(position as u64);
+      player::seek_precisely(&mut state, &self.ctx.player, position);
+    }
+  }
+
+  fn open_uri(&self, uri: String) -> fdo::Result<()> {
+    let mut state = self.ctx.player_state.lock().unwrap();
+    let mut library = self.ctx.library.lock().unwrap();
+    let song_id = find_song_by_uri(&mut library, &uri)?;
+    player::play_song_in_scope(
+      &song_id,
+      PlayerScope::Library,
+      &library,
+      &self.ctx.player,
+      &self.ctx.fader,
+      &mut state,
+    );
+    Ok(())
+  }
+
+  #[dbus_interface(signal)]
+  async fn seeked(ctxt: &SignalContext<'_>, position: i64) -> zbus::Result<()>;
+
+  #[dbus_interface(property)]
+  fn playback_status(&self) -> String {
+    let state = self.ctx.player_state.lock().unwrap();
+    match (&state.current_song, state.paused_at) {
+      (None, _) => "Stopped",
+      (Some(_), Some(_)) if state.is_stopped => "Stopped",
+      (Some(_), Some(_)) => "Paused",
+      (Some(_), None) => "Playing",
+    }
+    .to_string()
+  }
+
+  #[dbus_interface(property)]
+  fn loop_status(&self) -> String {
+    match self.ctx.player_state.lock().unwrap().repeat_mode {
+      RepeatMode::None => "None",
+      RepeatMode::One => "Track",
+      RepeatMode::All => "Playlist",
+    }
+    .to_string()
+  }
+
+  #[dbus_interface(property)]
+  fn set_loop_status(&self, loop_status: String) {
+    self.ctx.player_state.lock().unwrap().repeat_mode = match loop_status.as_str() {
+      "Track" => RepeatMode::One,
+      "Playlist" => RepeatMode::All,
+      _ => RepeatMode::None,
+    };
+  }
+
+  #[dbus_interface(property)]
+  fn rate(&self) -> f64 {
+    1.0
+  }
+
+  #[dbus_interface(property)]
+  fn set_rate(&self, _rate: f64) {}
+
+  #[dbus_interface(property)]
+  fn shuffle(&self) -> bool {
+    self.ctx.player_state.lock().unwrap().is_shuffled
+  }
+
+  #[dbus_interface(property)]
+  fn set_shuffle(&self, shuffle: bool) {
+    player::set_shuffle(
+      &mut self.ctx.player_state.lock().unwrap(),
+      &self.ctx.library.lock().unwrap(),
+      shuffle,
+    );
+  }
+
+  #[dbus_interface(property)]
+  fn metadata(&self) -> HashMap<String, OwnedValue> {
+    let state = self.ctx.player_state.lock().unwrap();
+    get_song_metadata(
+      state.current_song.as_deref().unwrap_or_default(),
+      &self.ctx.library.lock().unwrap(),
+    )
+  }
+
+  #[dbus_interface(property)]
+  fn volume(&self) -> f64 {
+    self.ctx.player_state.lock().unwrap().volume
+  }
+
+  #[dbus_interface(property)]
+  fn set_volume(&self, volume: f64) {
+    self.ctx.player.set_volume(volume.max(0.0));
+  }
+
+  #[dbus_interface(property)]
+  fn position(&self) -> i64 {
+    match self.ctx.player_state.lock().unwrap().current_song {
+      Some(_) => self.get_position(),
+      None => 0,
+    }
+  }
+
+  #[dbus_interface(property)]
+  fn minimum_rate(&self) -> f64 {
+    1.0
+  }
+
+  #[dbus_interface(property)]
+  fn maximum_rate(&self) -> f64 {
+    1.0
+  }
+
+  #[dbus_interface(property)]
+  fn can_go_next(&self) -> bool {
+    true
+  }
+
+  #[dbus_interface(property)]
+  fn can_go_previous(&self) -> bool {
+    true
+  }
+
+  #[dbus_interface(property)]
+  fn can_play(&self) -> bool {
+    self.ctx.player_state.lock().unwrap().current_song.is_some()
+  }
+
+  #[dbus_interface(property)]
+  fn can_pause(&self) -> bool {
+    self.ctx.player_state.lock().unwrap().current_song.is_some()
+  }
+
+  #[dbus_interface(property)]
+  fn can_seek(&self) -> bool {
+    self.ctx.player_state.lock().unwrap().current_song.is_some()
+  }
+
+  #[dbus_interface(property)]
+  fn can_control(&self) -> bool {
+    true
+  }
+}
+
+struct TrackListInterface {
+  ctx: Context,
+}
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2.TrackList")]
+impl TrackListInterface {
+  fn get_tracks_metadata(
+    &self,
+    track_ids: Vec<OwnedObjectPath>,
+  ) -> Vec<HashMap<String, OwnedValue>> {
+    let library = self.ctx.library.lock().unwrap();
+    track_ids
+      .iter()
+      .filter_map(|track_id| parse_track_id(track_id.as_str()))
+      .filter(|song_id| library.songs.contains_key(song_id))
+      .map(|song_id| get_song_metadata(&song_id, &library))
+      .collect()
+  }
+
+  fn add_track(
+    &self,
+    uri: String,
+    after_track: ObjectPath<'_>,
+    set_as_current: bool,
+  ) -> fdo::Result<()> {
+    let mut state = self.ctx.player_state.lock().unwrap();
+    let mut library = self.ctx.library.lock().unwrap();
+    let song_id = find_song_by_uri(&mut library, &uri)?;
+    if set_as_current {
+      player::play_song(
+        &song_id,
+        &library,
+        &self.ctx.player,
+        Some(&self.ctx.fader),
+        &mut state,
+        true,
+      );
+      return Ok(());
+    }
+    // The queues are played from the back, so inserting before a song plays after it
+    match parse_track_id(after_track.as_str()) {
+      Some(after_song) if Some(&after_song) != state.current_song.as_ref() => {
+        if let Some(i) = state.next_songs.iter().rposition(|id| id == &after_song) {
+          state.next_songs.insert(i, song_id);
+        } else if let Some(i) = state
+          .automatic_next_songs
+          .iter()
+          .rposition(|id| id == &after_song)
+        {
+          state.automatic_next_songs.insert(i, song_id);
+        } else {
+          return Err(fdo::Error::InvalidArgs("Unknown track".to_string()));
+        }
+      }
+      _ => state.next_songs.push(song_id),
+    }
+    Ok(())
+  }
+
+  fn remove_track(&self, track_id: ObjectPath<'_>) {
+    let mut state = self.ctx.player_state.lock().unwrap();
+    if let Some(song_id) = parse_track_id(track_id.as_str()) {
+      if let Some(i) = state.next_songs.iter().rposition(|id| id == &song_id) {
+        state.next_songs.remove(i);
+      } else if let Some(i) = state
+        .automatic_next_songs
+        .iter()
+        .rposition(|id| id == &song_id)
+      {
+        state.automatic_next_songs.remove(i);
+      }
+    }
+  }
+
+  fn go_to(&self, track_id: ObjectPath<'_>) {
+    let mut state = self.ctx.player_state.lock().unwrap();
+    let library = self.ctx.library.lock().unwrap();
+    let song_id = match parse_track_id(track_id.as_str()) {
+      Some(song_id) => song_id,
+      None => return,
+    };
+    if state.current_song.as_ref() == Some(&song_id) {
+      return player::seek(&mut state, &self.ctx.player, 0);
+    }
+    // Everything queued before the chosen track gets skipped
+    if let Some(i) = state.next_songs.iter().rposition(|id| id == &song_id) {
+      state.next_songs.truncate(i);
+    } else if let Some(i) = state
+      .automatic_next_songs
+      .iter()
+      .rposition(|id| id == &song_id)
+    {
+      state.next_songs.clear();
+      state.automatic_next_songs.truncate(i);
+    } else {
+      return;
+    }
+    player::play_song(
+      &song_id,
+      &library,
+      &self.ctx.player,
+      Some(&self.ctx.fader),
+      &mut state,
+      true,
+    );
+  }
+
+  #[dbus_interface(signal)]
+  async fn track_list_replaced(
+    ctxt: &SignalContext<'_>,
+    tracks: Vec<OwnedObjectPath>,
+    current_track: OwnedObjectPath,
+  ) -> zbus::Result<()>;
+
+  #[dbus_interface(property)]
+  fn tracks(&self) -> Vec<OwnedObjectPath> {
+    get_tracks(&self.ctx.player_state.lock().unwrap())
+      .iter()
+      .map(|song_id| OwnedObjectPath::try_from(get_track_id(song_id)).unwrap())
+      .collect()
+  }
+
+  #[dbus_interface(property)]
+  fn can_edit_tracks(&self) -> bool {
+    true
+  }
+}
+
+struct PlaylistsInterface {
+  ctx: Context,
+}
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2.Playlists")]
+impl PlaylistsInterface {
+  fn activate_playlist(&self, playlist_id: ObjectPath<'_>) -> fdo::Result<()> {
+    let playlist_id = decode_object_path(PLAYLIST_ID_PREFIX, playlist_id.as_str())
+      .ok_or_else(|| fdo::Error::InvalidArgs("Unknown playlist".to_string()))?;
+    let mut state = self.ctx.player_state.lock().unwrap();
+    let library = self.ctx.library.lock().unwrap();
+    let first_song = library
+      .playlists
+      .get(&playlist_id)
+      .and_then(|playlist| playlist.songs.first())
+      .cloned()
+      .ok_or_else(|| fdo::Error::Failed("Playlist is empty".to_string()))?;
+    player::play_song_in_scope(
+      &first_song,
+      PlayerScope::Playlist(playlist_id),
+      &library,
+      &self.ctx.player,
+      &self.ctx.fader,
+      &mut state,
+    );
+    Ok(())
+  }
+
+  fn get_playlists(
+    &self,
+    index: u32,
+    max_count: u32,
+    _order: String,
+    reverse_order: bool,
+  ) -> Vec<(OwnedObjectPath, String, String)> {
+    let library = self.ctx.library.lock().unwrap();
+    let mut playlists: Vec<(&String, &Playlist)> = library.playlists.iter().collect();
+    playlists.sort_by_key(|(_, playlist)| playlist.name.to_lowercase());
+    if reverse_order {
+      playlists.reverse();
+    }
+    playlists
+      .into_iter()
+      .skip(index as usize)
+      .take(max_count as usize)
+      .map(|(id, playlist)| get_playlist_tuple(id, &playlist.name))
+      .collect()
+  }
+
+  #[dbus_interface(signal)]
+  async fn playlist_changed(
+    ctxt: &SignalContext<'_>,
+    playlist: (OwnedObjectPath, String, String),
+  ) -> zbus::Result<()>;
+
+  #[dbus_interface(property)]
+  fn playlist_count(&self) -> u32 {
+    self.ctx.library.lock().unwrap().playlists.len() as u32
+  }
+
+  #[dbus_interface(property)]
+  fn orderings(&self) -> Vec<String> {
+    vec!["Alphabetical".to_string()]
+  }
+
+  #[dbus_interface(property)]
+  fn active_playlist(&self) -> (bool, (OwnedObjectPath, String, String)) {
+    let state = self.ctx.player_state.lock().unwrap();
+    let library = self.ctx.library.lock().unwrap();
+    match &state.scope {
+      PlayerScope::Playlist(playlist_id) if library.playlists.contains_key(playlist_id) => (
+        true,
+        get_playlist_tuple(playlist_id, &library.playlists[playlist_id].name),
+      ),
+      _ => (
+        false,
+        (
+          OwnedObjectPath::try_from("/").unwrap(),
+          String::new(),
+          String::new(),
+        ),
+      ),
+    }
+  }
+}
+
+#[derive(PartialEq)]
+struct PlaylistsSnapshot {
+  playlists: Vec<(String, String)>,
+  active_playlist: Option<String>,
+}
+
+impl PlaylistsSnapshot {
+  fn new(state: &PlayerState, library: &Library) -> Self {
+    let mut playlists: Vec<(String, String)> = library
+      .playlists
+      .iter()
+      .map(|(id, playlist)| (id.clone(), playlist.name.clone()))
+      .collect();
+    playlists.sort();
+    PlaylistsSnapshot {
+      playlists,
+      active_playlist: match &state.scope {
+        PlayerScope::Playlist(playlist_id) => Some(playlist_id.clone()),
+        _ => None,
+      },
+    }
+  }
+}
+
+#[derive(PartialEq)]
+struct PlayerSnapshot {
+  current_song: Option<String>,
+  is_paused: bool,
+  is_stopped: bool,
+  song_started_at: u32,
+  repeat_mode: RepeatMode,
+  is_shuffled: bool,
+  volume: f64,
+  tracks: Vec<String>,
+}
+
+impl From<&PlayerState> for PlayerSnapshot {
+  fn from(state: &PlayerState) -> Self {
+    PlayerSnapshot {
+      current_song: state.current_song.clone(),
+      is_paused: state.paused_at.is_some(),
+      is_stopped: state.is_stopped,
+      song_started_at: state.song_started_at,
+      repeat_mode: state.repeat_mode.clone(),
+      is_shuffled: state.is_shuffled,
+      volume: state.volume,
+      tracks: get_tracks(state),
+    }
+  }
+}
+
+async fn watch_player_state(ctx: Context, connection: Connection) -> zbus::Result<()> {
+  let object_server = connection.object_server();
+  let player_ref = object_server
+    .interface::<_, PlayerInterface>(MPRIS_PATH)
+    .await?;
+  let track_list_ref = object_server
+    .interface::<_, TrackListInterface>(MPRIS_PATH)
+    .await?;
+  let playlists_ref = object_server
+    .interface::<_, PlaylistsInterface>(MPRIS_PATH)
+    .await?;
+  let get_snapshots = || {
+    let state = ctx.player_state.lock().unwrap();
+    let library = ctx.library.lock().unwrap();
+    (
+      PlayerSnapshot::from(&*state),
+      PlaylistsSnapshot::new(&state, &library),
+    )
+  };
+  let (mut old, mut old_playlists) = get_snapshots();
+  loop {
+    sleep(Duration::from_millis(100)).await;
+    let (new, new_playlists) = get_snapshots();
+    if new != old {
+      let iface = player_ref.get().await;
+      let ctxt = player_ref.signal_context();
+      if new.current_song != old.current_song {
+        iface.metadata_changed(ctxt).await?;
+        iface.can_play_changed(ctxt).await?;
+        iface.can_pause_changed(ctxt).await?;
+        iface.can_seek_changed(ctxt).await?;
+      } else if new.is_paused == old.is_paused && new.song_started_at != old.song_started_at {
+        PlayerInterface::seeked(ctxt, iface.position()).await?;
+      }
+      if new.current_song.is_some() != old.current_song.is_some()
+        || new.is_paused != old.is_paused
+        || new.is_stopped != old.is_stopped
+      {
+        iface.playback_status_changed(ctxt).await?;
+      }
+      if new.repeat_mode != old.repeat_mode {
+        iface.loop_status_changed(ctxt).await?;
+      }
+      if new.is_shuffled != old.is_shuffled {
+        iface.shuffle_changed(ctxt).await?;
+      }
+      if new.volume != old.volume {
+        iface.volume_changed(ctxt).await?;
+      }
+      if new.tracks != old.tracks {
+        TrackListInterface::track_list_replaced(
+          track_list_ref.signal_context(),
+          new
+            .tracks
+            .iter()
+            .map(|song_id| OwnedObjectPath::try_from(get_track_id(song_id)).unwrap())
+            .collect(),
+          OwnedObjectPath::try_from(match &new.current_song {
+            Some(song_id) => get_track_id(song_id),
+            None => NO_TRACK.to_string(),
+          })
+          .unwrap(),
+        )
+        .await?;
+      }
+      old = new;
+    }
+    if new_playlists != old_playlists {
+      let iface = playlists_ref.get().await;
+      let ctxt = playlists_ref.signal_context();
+      if new_playlists.playlists.len() != old_playlists.playlists.len() {
+        iface.playlist_count_changed(ctxt).await?;
+      }
+      for (id, name) in new_playlists.playlists.iter() {
+        if old_playlists
+          .playlists
+          .iter()
+          .any(|(old_id, old_name)| old_id == id && old_name != name)
+        {
+          PlaylistsInterface::playlist_changed(ctxt, get_playlist_tuple(id, name)).await?;
+        }
+      }
+      if new_playlists.active_playlist != old_playlists.active_playlist {
+        iface.active_playlist_changed(ctxt).await?;
+      }
+      old_playlists = new_playlists;
+    }
+  }
+}
+
+pub async fn start(ctx: Context) -> zbus::Result<()> {
+  let connection = ConnectionBuilder::session()?
+    .name("org.mpris.MediaPlayer2.ronix")?
+    .serve_at(MPRIS_PATH, RootInterface)?
+    .serve_at(MPRIS_PATH, PlayerInterface { ctx: ctx.clone() })?
+    .serve_at(MPRIS_PATH, TrackListInterface { ctx: ctx.clone() })?
+    .serve_at(MPRIS_PATH, PlaylistsInterface { ctx: ctx.clone() })?
+    .build()
+    .await?;
+  tokio::spawn(async move {
+    if let Err(e) = watch_player_state(ctx, connection).await {
+      println!("MPRIS server stopped: {}", e);
+    }
+  });
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    build_context,
+    config::Config,
+    get_current_time,
+    library::{Album, Artist, Song},
+  };
+  use tokio::net::UnixStream;
+  use zbus::Guid;
+
+  const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+  const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+
+  fn create_library() -> Library {
+    let mut library = Library::default();
+    library.artists.insert(
+      "artist".to_string(),
+      Artist {
+        name: "Artist".to_string(),
+        sort_name: None,
+        play_count: 0,
+        last_played: None,
+      },
+    );
+    library.albums.insert(
+      "album".to_string(),
+      Album {
+        name: "Album".to_string(),
+        sort_name: None,
+        cover_art: None,
+        artist: "artist".to_string(),
+        play_count: 0,
+        last_played: None,
+      },
+    );
+    for song_id in ["first", "second"] {
+      library.songs.insert(
+        song_id.to_string(),
+        Song {
+          title: song_id.to_string(),
+          sort_title: None,
+          path: PathBuf::from(format!("/nonexistent/{}.flac", song_id)),
+          duration: 200,
+          album: "album".to_string(),
+          genre: None,
+          year: None,
+          added_at: 0,
+          play_count: 0,
+          skip_count: 0,
+          last_played: None,
+          rating: 0,
+          is_favorite: false,
+        },
+      );
+    }
+    library
+  }
+
+  // The interfaces are served over a socket pair, so no session bus is needed.
+  // The first song is paused at its start, with the second one next
+  async fn connect() -> (Context, Connection, Connection) {
+    gst::init().unwrap();
+    let ctx = build_context(Config::default(), create_library(), None, None);
+    {
+      let mut state = ctx.player_state.lock().unwrap();
+      let now = get_current_time();
+      state.current_song = Some("first".to_string());
+      state.next_songs = vec!["second".to_string()];
+      state.song_started_at = now;
+      state.paused_at = Some(now);
+    }
+    let (server_stream, client_stream) = UnixStream::pair().unwrap();
+    let guid = Guid::generate();
+    let server = ConnectionBuilder::unix_stream(server_stream)
+      .server(&guid)
+      .p2p()
+      .serve_at(MPRIS_PATH, PlayerInterface { ctx: ctx.clone() })
+      .unwrap()
+      .build();
+    let client = ConnectionBuilder::unix_stream(client_stream).p2p().build();
+    let (server, client) = tokio::try_join!(server, client).unwrap();
+    (ctx, server, client)
+  }
+
+  async fn call<B>(client: &Connection, method: &str, body: &B)
+  where
+    B: serde::Serialize + zbus::zvariant::DynamicType,
+  {
+    client
+      .call_method(
+        None::<&str>,
+        MPRIS_PATH,
+        Some(PLAYER_INTERFACE),
+        method,
+        body,
+      )
+      .await
+      .unwrap();
+  }
+
+  async fn set_property(client: &Connection, name: &str, value: Value<'_>) {
+    client
+      .call_method(
+        None::<&str>,
+        MPRIS_PATH,
+        Some(PROPERTIES_INTERFACE),
+        "Set",
+        &(PLAYER_INTERFACE, name, value),
+      )
+      .await
+      .unwrap();
+  }
+
+  #[test]
+  fn plays_and_pauses() {
+    tauri::async_runtime::block_on(async {
+      let (ctx, _server, client) = connect().await;
+      call(&client, "Play", &()).await;
+      assert!(ctx.player_state.lock().unwrap().paused_at.is_none());
+      call(&client, "Pause", &()).await;
+      assert!(ctx.player_state.lock().unwrap().paused_at.is_some());
+    });
+  }
+
+  #[test]
+  fn seeks_by_offset_and_to_positions() {
+    tauri::async_runtime::block_on(async {
+      let (ctx, _server, client) = connect().await;
+      call(&client, "Seek", &30_500_000i64).await;
+      assert_eq!(player::get_position(&ctx.player_state.lock().unwrap()), 30);
+      let track_id = ObjectPath::try_from(get_track_id("first")).unwrap();
+      call(&client, "SetPosition", &(&track_id, 90_000_000i64)).await;
+      assert_eq!(player::get_position(&ctx.player_state.lock().unwrap()), 90);
+      // Positions past the end are ignored
+      call(&client, "SetPosition", &(&track_id, 300_000_000i64)).await;
+      assert_eq!(player::get_position(&ctx.player_state.lock().unwrap()), 90);
+    });
+  }
+
+  #[test]
+  fn seeking_past_the_end_plays_the_next_song() {
+    tauri::async_runtime::block_on(async {
+      let (ctx, _server, client) = connect().await;
+      call(&client, "Seek", &i64::MAX).await;
+      assert_eq!(
+        ctx.player_state.lock().unwrap().current_song.as_deref(),
+        Some("second")
+      );
+    });
+  }
+
+  #[test]
+  fn sets_loop_status_and_shuffle() {
+    tauri::async_runtime::block_on(async {
+      let (ctx, _server, client) = connect().await;
+      set_property(&client, "LoopStatus", Value::from("Track")).await;
+      assert!(ctx.player_state.lock().unwrap().repeat_mode == RepeatMode::One);
+      set_property(&client, "LoopStatus", Value::from("Playlist")).await;
+      assert!(ctx.player_state.lock().unwrap().repeat_mode == RepeatMode::All);
+      set_property(&client, "Shuffle", Value::from(true)).await;
+      assert!(ctx.player_state.lock().unwrap().is_shuffled);
+    });
+  }
+}
@@ -0,0 +1,190 @@
+use crate::{
+  library::{Album, Artist, Library, Song},
+  playlist::Playlist,
+};
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Deserialize, Type)]
+pub struct PageInput {
+  // The last id of the previous page, pages are ordered by id
+  after: Option<String>,
+  limit: u32,
+}
+
+#[derive(Serialize, Type)]
+pub struct Page<T> {
+  items: HashMap<String, T>,
+  // Passed as `after` to get the next page, there are no more pages without it
+  next: Option<String>,
+  total: u32,
+}
+
+#[derive(Deserialize, Type)]
+pub struct IdsInput {
+  artists: Vec<String>,
+  albums: Vec<String>,
+  songs: Vec<String>,
+}
+
+#[derive(Serialize, Type, Default, Clone)]
+pub struct LibraryPart {
+  pub artists: HashMap<String, Artist>,
+  pub albums: HashMap<String, Album>,
+  pub songs: HashMap<String, Song>,
+  pub playlists: HashMap<String, Playlist>,
+}
+
+#[derive(Serialize, Type, Default)]
+pub struct LibraryChanges {
+  // Added or changed since the last changes
+  changed: LibraryPart,
+  removed_artists: Vec<String>,
+  removed_albums: Vec<String>,
+  removed_songs: Vec<String>,
+  removed_playlists: Vec<String>,
+}
+
+pub fn get_page<T: Clone>(items: &HashMap<String, T>, input: PageInput) -> Page<T> {
+  let mut ids: Vec<&String> = items
+    .keys()
+    .filter(|id| input.after.as_ref().map_or(true, |after| *id > after))
+    .collect();
+  ids.sort_unstable();
+  let limit = input.limit.max(1) as usize;
+  Page {
+    next: (ids.len() > limit).then(|| ids[limit - 1].clone()),
+    items: ids
+      .into_iter()
+      .take(limit)
+      .map(|id| (id.clone(), items.get(id).unwrap().clone()))
+      .collect(),
+    total: items.len() as u32,
+  }
+}
+
+fn pick<T: Clone>(items: &HashMap<String, T>, ids: &[String]) -> HashMap<String, T> {
+  ids
+    .iter()
+    .filter_map(|id| items.get(id).map(|item| (id.clone(), item.clone())))
+    .collect()
+}
+
+// Unknown ids are left out
+pub fn get_by_ids(library: &Library, input: IdsInput) -> LibraryPart {
+  LibraryPart {
+    artists: pick(&library.artists, &input.artists),
+    albums: pick(&library.albums, &input.albums),
+    songs: pick(&library.songs, &input.songs),
+    playlists: HashMap::new(),
+  }
+}
+
+// The album with its artist and songs
+pub fn get_album_songs(library: &Library, album_id: &String) -> LibraryPart {
+  let mut part = LibraryPart::default();
+  if let Some(album) = library.albums.get(album_id) {
+    part.albums.insert(album_id.clone(), album.clone());
+    if let Some(artist) = library.artists.get(&album.artist) {
+      part.artists.insert(album.artist.clone(), artist.clone());
+    }
+    part.songs = library
+      .songs
+      .iter()
+      .filter(|(_, song)| &song.album == album_id)
+      .map(|(id, song)| (id.clone(), song.clone()))
+      .collect();
+  }
+  part
+}
+
+// The artist with their albums and songs
+pub fn get_artist_songs(library: &Library, artist_id: &String) -> LibraryPart {
+  let mut part = LibraryPart::default();
+  if let Some(artist) = library.artists.get(artist_id) {
+    part.artists.insert(artist_id.clone(), artist.clone());
+    part.albums = library
+      .albums
+      .iter()
+      .filter(|(_, album)| &album.artist == artist_id)
+      .map(|(id, album)| (id.clone(), album.clone()))
+      .collect();
+    let album_ids: HashSet<&String> = part.albums.keys().collect();
+    part.songs = library
+      .songs
+      .iter()
+      .filter(|(_, song)| album_ids.contains(&song.album))
+      .map(|(id, song)| (id.clone(), song.clone()))
+      .collect();
+  }
+  part
+}
+
+fn diff<T: Clone + PartialEq>(
+  old: &mut HashMap<String, T>,
+  new: &HashMap<String, T>,
+  changed: &mut HashMap<String, T>,
+  removed: &mut Vec<String>,
+) {
+  for (id, item) in new.iter() {
+    if old.get(id) != Some(item) {
+      changed.insert(id.clone(), item.clone());
+      old.insert(id.clone(), item.clone());
+    }
+  }
+  old.retain(|id, _| {
+    let is_kept = new.contains_key(id);
+    if !is_kept {
+      removed.push(id.clone());
+    }
+    is_kept
+  });
+}
+
+pub fn get_snapshot(library: &Library) -> LibraryPart {
+  LibraryPart {
+    artists: library.artists.clone(),
+    albums: library.albums.clone(),
+    songs: library.songs.clone(),
+    playlists: library.playlists.clone(),
+  }
+}
+
+// Compares the library to what the subscriber has last seen, and updates that
+pub fn get_changes(library: &Library, snapshot: &mut LibraryPart) -> Option<LibraryChanges> {
+  let mut changes = LibraryChanges::default();
+  diff(
+    &mut snapshot.songs,
+    &library.songs,
+    &mut changes.changed.songs,
+    &mut changes.removed_songs,
+  );
+  diff(
+    &mut snapshot.artists,
+    &library.artists,
+    &mut changes.changed.artists,
+    &mut changes.removed_artists,
+  );
+  diff(
+    &mut snapshot.albums,
+    &library.albums,
+    &mut changes.changed.albums,
+    &mut changes.removed_albums,
+  );
+  diff(
+    &mut snapshot.playlists,
+    &library.playlists,
+    &mut changes.changed.playlists,
+    &mut changes.removed_playlists,
+  );
+  let is_empty = changes.changed.artists.is_empty()
+    && changes.changed.albums.is_empty()
+    && changes.changed.songs.is_empty()
+    && changes.changed.playlists.is_empty()
+    && changes.removed_artists.is_empty()
+    && changes.removed_albums.is_empty()
+    && changes.removed_songs.is_empty()
+    && changes.removed_playlists.is_empty();
+  (!is_empty).then_some(changes)
+}
@@ -0,0 +1,106 @@
+use crate::{
+  covers::{self, CoverSize},
+  Context,
+};
+use image::RgbImage;
+use rspc::Type;
+use serde::Serialize;
+use std::{collections::HashMap, path::PathBuf};
+
+#[derive(Type, Serialize, PartialEq, Clone)]
+pub struct Palette {
+  // The most common color
+  dominant: String,
+  // The most common saturated color that's neither too dark nor too light, for the accent
+  vibrant: String,
+}
+
+pub enum CachedPalette {
+  // None when the cover has no usable colors
+  Palette(Option<Palette>),
+  // Covers that can't be read are tried again once the library changed, from its revision then
+  Unreadable(u64),
+}
+
+fn to_hex(color: [u8; 3]) -> String {
+  format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+// Both from 0 to 1
+fn get_saturation_and_lightness(color: [u8; 3]) -> (f64, f64) {
+  let [r, g, b] = color.map(|channel| channel as f64 / 255.0);
+  let max = r.max(g).max(b);
+  let min = r.min(g).min(b);
+  let lightness = (max + min) / 2.0;
+  let saturation = if max == min {
+    0.0
+  } else {
+    (max - min) / (1.0 - (2.0 * lightness - 1.0).abs())
+  };
+  (saturation, lightness)
+}
+
+pub fn extract_palette(image: &RgbImage) -> Option<Palette> {
+  // Similar colors share a bucket, with 16 levels per channel
+  let mut buckets: HashMap<[u8; 3], (u32, [u64; 3])> = HashMap::new();
+  for pixel in image.pixels() {
+    let bucket = buckets
+      .entry([pixel[0] >> 4, pixel[1] >> 4, pixel[2] >> 4])
+      .or_insert((0, [0; 3]));
+    bucket.0 += 1;
+    for i in 0..3 {
+      bucket.1[i] += pixel[i] as u64;
+    }
+  }
+  let colors: Vec<(u32, [u8; 3])> = buckets
+    .into_values()
+    .map(|(count, sums)| (count, sums.map(|sum| (sum / count as u64) as u8)))
+    .collect();
+  let (_, dominant) = colors.iter().max_by_key(|(count, _)| *count)?;
+  let vibrant = colors
+    .iter()
+    .filter_map(|(count, color)| {
+      let (saturation, lightness) = get_saturation_and_lightness(*color);
+      (saturation >= 0.35 && (0.25..=0.75).contains(&lightness))
+        .then(|| (*count as f64 * saturation * saturation, color))
+    })
+    .max_by(|a, b| a.0.total_cmp(&b.0))
+    .map_or(dominant, |(_, color)| color);
+  Some(Palette {
+    dominant: to_hex(*dominant),
+    vibrant: to_hex(*vibrant),
+  })
+}
+
+// Cached by cover, so albums sharing one and rescans don't compute it again
+pub async fn get_palette(ctx: &Context, song_id: &str) -> Option<Palette> {
+  let (album_id, cover_art, revision) = {
+    let library = ctx.library.lock().unwrap();
+    let album_id = library.songs.get(song_id)?.album.clone();
+    let cover_art = PathBuf::from(library.albums.get(&album_id)?.cover_art.as_ref()?);
+    (album_id, cover_art, ctx.library.revision())
+  };
+  match ctx.palettes.lock().unwrap().get(&cover_art) {
+    Some(CachedPalette::Palette(palette)) => return palette.clone(),
+    Some(CachedPalette::Unreadable(failed_at)) if *failed_at == revision => return None,
+    _ => {}
+  }
+  let blocking_ctx = ctx.clone();
+  let palette = tokio::task::spawn_blocking(move || {
+    let (data, _) = covers::read_cover(&blocking_ctx, &album_id, CoverSize::Small)?;
+    Some(
+      image::load_from_memory(&data)
+        .ok()
+        .and_then(|image| extract_palette(&image.to_rgb8())),
+    )
+  })
+  .await
+  .ok()
+  .flatten();
+  let cached = match &palette {
+    Some(palette) => CachedPalette::Palette(palette.clone()),
+    None => CachedPalette::Unreadable(revision),
+  };
+  ctx.palettes.lock().unwrap().insert(cover_art, cached);
+  palette.flatten()
+}
@@ -1,6 +1,8 @@
 use crate::{
+  fade::{FadeKind, Fader},
   get_current_time,
   library::{get_automatic_next_songs, Library},
+  palette::{self, Palette},
   Context, PlayerScope, PlayerState, RepeatMode,
 };
 use async_stream::stream;
@@ -8,7 +10,6 @@ use gst::ClockTime;
 use gst_player::Player;
 use rspc::{Router, RouterBuilder, Type};
 use serde::{Deserialize, Serialize};
-use souvlaki::{MediaControls, MediaMetadata, MediaPlayback};
 use std::{
   sync::{Arc, Mutex},
   time::Duration,
@@ -27,6 +28,8 @@ struct CurrentSongData {
   song_started_at: u32,
   paused_at: Option<u32>,
   volume: f64,
+  // Only with the dynamic accent color
+  palette: Option<Palette>,
 }
 
 impl From<&Arc<Mutex<PlayerState>>> for CurrentSongData {
@@ -37,6 +40,7 @@ impl From<&Arc<Mutex<PlayerState>>> for CurrentSongData {
       song_started_at: state.song_started_at,
       paused_at: state.paused_at,
       volume: state.volume,
+      palette: None,
     }
   }
 }
@@ -45,7 +49,7 @@ pub fn next_song(
   mut state: &mut PlayerState,
   library: &Library,
   player: &Player,
-  mut os_controls: &mut MediaControls,
+  fader: Option<&Fader>,
 ) {
   if let Some(current_song) = &state.current_song {
     let next_song = state.next_songs.pop().or_else(|| {
@@ -67,51 +71,62 @@ pub fn next_song(
     state.previous_songs.push(current_song.to_string());
     state.current_song = None;
     if let Some(song_id) = &next_song {
-      play_song(
-        song_id,
-        &library,
-        &player,
-        &mut state,
-        &mut os_controls,
-        true,
-      );
+      play_song(song_id, &library, &player, fader, &mut state, true);
     }
   }
 }
 
-pub fn toggle_pause(state: &mut PlayerState, player: &Player, os_controls: &mut MediaControls) {
+pub fn toggle_pause(state: &mut PlayerState, player: &Player, fader: &Fader) {
+  let player = player.clone();
   match state.paused_at {
     Some(paused_at) => {
-      player.play();
+      fader.fade_in(FadeKind::PauseResume, move || player.play());
       state.song_started_at += get_current_time() - paused_at;
       state.paused_at = None;
-      os_controls
-        .set_playback(MediaPlayback::Playing { progress: None })
-        .unwrap();
+      state.is_stopped = false;
     }
     None => {
-      player.pause();
+      fader.fade_out(FadeKind::PauseResume, move || player.pause());
       state.paused_at = Some(get_current_time());
-      os_controls
-        .set_playback(MediaPlayback::Paused { progress: None })
-        .unwrap();
     }
   }
 }
 
+pub fn play(state: &mut PlayerState, player: &Player, fader: &Fader) {
+  if state.current_song.is_some() && state.paused_at.is_some() {
+    toggle_pause(state, player, fader);
+  }
+}
+
+pub fn pause(state: &mut PlayerState, player: &Player, fader: &Fader) {
+  if state.current_song.is_some() && state.paused_at.is_none() {
+    toggle_pause(state, player, fader);
+  }
+}
+
 pub fn play_song(
   song_id: &str,
   library: &Library,
   player: &Player,
+  fader: Option<&Fader>,
   state: &mut PlayerState,
-  os_controls: &mut MediaControls,
   save_to_prev: bool,
 ) {
   let song = library.songs.get(song_id).unwrap();
-  let album = library.albums.get(&song.album).unwrap();
-  let artist = library.artists.get(&album.artist).unwrap();
-  player.set_uri(Some(&format!("file://{}", song.path.to_str().unwrap())));
-  player.play();
+  let uri = format!("file://{}", song.path.to_str().unwrap());
+  match fader {
+    Some(fader) => {
+      let player = player.clone();
+      fader.fade_through(FadeKind::Skip, move || {
+        player.set_uri(Some(&uri));
+        player.play();
+      });
+    }
+    None => {
+      player.set_uri(Some(&uri));
+      player.play();
+    }
+  }
   if let Some(current_song) = &state.current_song {
     if save_to_prev {
       state.previous_songs.push(current_song.to_string());
@@ -125,30 +140,44 @@ pub fn play_song(
   state.current_song = Some(song_id.to_string());
   state.song_started_at = get_current_time();
   state.paused_at = None;
-  os_controls
-    .set_metadata(MediaMetadata {
-      title: Some(&song.title),
-      album: Some(&album.name),
-      artist: Some(&artist.name),
-      cover_url: album
-        .cover_art
-        .as_ref()
-        .map(|p| format!("file://{}", p))
-        .as_deref(),
-      duration: Some(Duration::from_millis(song.duration as u64)),
-    })
-    .unwrap();
-  os_controls
-    .set_playback(MediaPlayback::Playing { progress: None })
-    .unwrap();
+  state.is_stopped = false;
 }
 
-pub fn previous_song(
-  state: &mut PlayerState,
+pub fn play_song_in_scope(
+  song_id: &str,
+  scope: PlayerScope,
+  library: &Library,
   player: &Player,
+  fader: &Fader,
+  state: &mut PlayerState,
+) {
+  state.automatic_next_songs =
+    get_automatic_next_songs(library, &song_id.to_string(), state.is_shuffled, &scope);
+  state.scope = scope;
+  play_song(song_id, library, player, Some(fader), state, true)
+}
+
+pub fn play_songs(
+  song_ids: &[String],
   library: &Library,
-  os_controls: &mut MediaControls,
+  player: &Player,
+  fader: &Fader,
+  state: &mut PlayerState,
 ) {
+  if let Some((first_song, other_songs)) = song_ids.split_first() {
+    state.next_songs.extend(other_songs.iter().rev().cloned());
+    state.automatic_next_songs.clear();
+    play_song(first_song, library, player, Some(fader), state, true);
+  }
+}
+
+pub fn queue_songs(state: &mut PlayerState, song_ids: Vec<String>) {
+  for song_id in song_ids {
+    state.next_songs.insert(0, song_id);
+  }
+}
+
+pub fn previous_song(state: &mut PlayerState, player: &Player, fader: &Fader, library: &Library) {
   if (get_current_time() - state.song_started_at) > 5 {
     return seek(state, player, 0);
   }
@@ -156,34 +185,57 @@ pub fn previous_song(
     if let Some(song_id) = &state.current_song {
       state.automatic_next_songs.push(song_id.clone());
     }
-    play_song(&song_id, library, player, state, os_controls, false);
+    play_song(&song_id, library, player, Some(fader), state, false);
   } else {
-    player.stop();
+    let player = player.clone();
+    fader.fade_out(FadeKind::Stop, move || player.stop());
     state.current_song = None;
   }
 }
 
+pub fn stop(state: &mut PlayerState, player: &Player, fader: &Fader) {
+  let player = player.clone();
+  fader.fade_out(FadeKind::Stop, move || player.stop());
+  state.paused_at = Some(get_current_time());
+  state.song_started_at = get_current_time();
+  state.is_stopped = true;
+}
+
 pub fn seek(state: &mut PlayerState, player: &Player, seek_to: u32) {
-  player.seek(ClockTime::from_seconds(seek_to as u64));
-  state.song_started_at = state.paused_at.unwrap_or(get_current_time()) - seek_to;
+  seek_precisely(state, player, ClockTime::from_seconds(seek_to as u64));
+}
+
+// For clients that seek in microseconds, like MPRIS
+pub fn seek_precisely(state: &mut PlayerState, player: &Player, seek_to: ClockTime) {
+  player.seek(seek_to);
+  state.song_started_at = state.paused_at.unwrap_or(get_current_time()) - seek_to.seconds() as u32;
+}
+
+pub fn get_position(state: &PlayerState) -> u32 {
+  state.paused_at.unwrap_or(get_current_time()) - state.song_started_at
+}
+
+pub fn set_shuffle(state: &mut PlayerState, library: &Library, is_shuffled: bool) {
+  state.is_shuffled = is_shuffled;
+  if let Some(current_song) = &state.current_song {
+    state.automatic_next_songs =
+      get_automatic_next_songs(library, current_song, state.is_shuffled, &state.scope);
+  }
 }
 
 pub fn get_router() -> RouterBuilder<Context> {
   Router::<Context>::new()
     .mutation("playSong", |t| {
       t(|ctx, input: PlaySongInput| {
-        let library = ctx.library.lock().unwrap();
+        // The player state is always locked before the library
         let mut state = ctx.player_state.lock().unwrap();
-        state.automatic_next_songs =
-          get_automatic_next_songs(&library, &input.song_id, state.is_shuffled, &input.scope);
-        state.scope = input.scope;
-        play_song(
+        play_song_in_scope(
           &input.song_id,
-          &library,
+          input.scope,
+          &ctx.library.lock().unwrap(),
           &ctx.player,
+          &ctx.fader,
           &mut state,
-          &mut ctx.os_controls.lock().unwrap(),
-          true,
         )
       })
     })
@@ -192,10 +244,22 @@ pub fn get_router() -> RouterBuilder<Context> {
         toggle_pause(
           &mut ctx.player_state.lock().unwrap(),
           &ctx.player,
-          &mut ctx.os_controls.lock().unwrap(),
+          &ctx.fader,
         );
       })
     })
+    .mutation("queueSongs", |t| {
+      t(|ctx, input: Vec<String>| {
+        let song_ids = {
+          let library = ctx.library.lock().unwrap();
+          input
+            .into_iter()
+            .filter(|song_id| library.songs.contains_key(song_id))
+            .collect()
+        };
+        queue_songs(&mut ctx.player_state.lock().unwrap(), song_ids)
+      })
+    })
     .mutation("seek", |t| {
       t(|ctx, input: u32| seek(&mut ctx.player_state.lock().unwrap(), &ctx.player, input))
     })
@@ -204,8 +268,8 @@ pub fn get_router() -> RouterBuilder<Context> {
         previous_song(
           &mut ctx.player_state.lock().unwrap(),
           &ctx.player,
+          &ctx.fader,
           &ctx.library.lock().unwrap(),
-          &mut ctx.os_controls.lock().unwrap(),
         )
       })
     })
@@ -215,21 +279,16 @@ pub fn get_router() -> RouterBuilder<Context> {
           &mut ctx.player_state.lock().unwrap(),
           &ctx.library.lock().unwrap(),
           &ctx.player,
-          &mut ctx.os_controls.lock().unwrap(),
+          Some(&ctx.fader),
         )
       })
     })
     .mutation("toggleShuffle", |t| {
       t(|ctx, _: ()| {
         let mut state = ctx.player_state.lock().unwrap();
-        state.is_shuffled = !state.is_shuffled;
-        state.automatic_next_songs = get_automatic_next_songs(
-          &ctx.library.lock().unwrap(),
-          state.current_song.as_ref().unwrap(),
-          state.is_shuffled,
-          &state.scope,
-        );
-        state.is_shuffled
+        let is_shuffled = !state.is_shuffled;
+        set_shuffle(&mut state, &ctx.library.lock().unwrap(), is_shuffled);
+        is_shuffled
       })
     })
     .mutation("toggleRepeatMode", |t| {
@@ -255,7 +314,12 @@ pub fn get_router() -> RouterBuilder<Context> {
           let state = ctx.player_state.clone();
           let mut old_song_data = CurrentSongData::from(&state);
           loop {
-            let new_song_data = CurrentSongData::from(&state);
+            let mut new_song_data = CurrentSongData::from(&state);
+            if ctx.config.lock().unwrap().dynamic_accent {
+              if let Some(song_id) = new_song_data.current_song.clone() {
+                new_song_data.palette = palette::get_palette(&ctx, &song_id).await;
+              }
+            }
             if new_song_data != old_song_data {
               old_song_data = new_song_data.clone();
               yield new_song_data;
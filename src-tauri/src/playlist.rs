@@ -0,0 +1,315 @@
+use crate::{
+  library::{Library, Song},
+  query::{self, get_fuse},
+  Context,
+};
+use directories::ProjectDirs;
+use nanoid::nanoid;
+use rspc::{Router, RouterBuilder, Type};
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::{HashMap, HashSet},
+  fs::{create_dir_all, File},
+  path::PathBuf,
+};
+
+#[derive(Serialize, Clone, Type, PartialEq)]
+pub struct Playlist {
+  pub name: String,
+  pub songs: Vec<String>,
+  // Smart playlists have the songs matching a search query
+  pub query: Option<String>,
+}
+
+// Song ids change on every scan, so playlists are saved with song paths
+#[derive(Serialize, Deserialize)]
+struct SavedPlaylist {
+  name: String,
+  songs: Vec<PathBuf>,
+  #[serde(default)]
+  query: Option<String>,
+}
+
+#[derive(Deserialize, Type)]
+struct RenamePlaylistInput {
+  id: String,
+  name: String,
+}
+
+#[derive(Deserialize, Type)]
+struct SmartPlaylistInput {
+  name: String,
+  query: String,
+}
+
+#[derive(Deserialize, Type)]
+struct SetQueryInput {
+  id: String,
+  query: String,
+}
+
+#[derive(Deserialize, Type)]
+struct AddSongsInput {
+  id: String,
+  song_ids: Vec<String>,
+}
+
+#[derive(Deserialize, Type)]
+struct RemoveSongInput {
+  id: String,
+  index: u32,
+}
+
+pub fn get_playlists_file_path() -> PathBuf {
+  ProjectDirs::from("dev", "ronanru", "ronix")
+    .unwrap()
+    .data_dir()
+    .join("playlists.json")
+}
+
+pub fn read_playlists(songs: &HashMap<String, Song>) -> HashMap<String, Playlist> {
+  let saved_playlists: HashMap<String, SavedPlaylist> = File::open(get_playlists_file_path())
+    .ok()
+    .and_then(|file| serde_json::from_reader(file).ok())
+    .unwrap_or_default();
+  let song_ids: HashMap<&PathBuf, &String> =
+    songs.iter().map(|(id, song)| (&song.path, id)).collect();
+  saved_playlists
+    .into_iter()
+    .map(|(id, playlist)| {
+      (
+        id,
+        Playlist {
+          name: playlist.name,
+          songs: playlist
+            .songs
+            .iter()
+            .filter_map(|path| song_ids.get(path).map(|id| id.to_string()))
+            .collect(),
+          query: playlist.query,
+        },
+      )
+    })
+    .collect()
+}
+
+pub fn write_playlists(library: &Library) {
+  let saved_playlists: HashMap<&String, SavedPlaylist> = library
+    .playlists
+    .iter()
+    .map(|(id, playlist)| {
+      (
+        id,
+        SavedPlaylist {
+          name: playlist.name.clone(),
+          songs: playlist
+            .songs
+            .iter()
+            .filter_map(|song_id| library.songs.get(song_id).map(|song| song.path.clone()))
+            .collect(),
+          query: playlist.query.clone(),
+        },
+      )
+    })
+    .collect();
+  let playlists_file_path = get_playlists_file_path();
+  create_dir_all(&playlists_file_path.parent().unwrap()).unwrap();
+  let playlists_file = File::create(&playlists_file_path).unwrap();
+  serde_json::to_writer_pretty(&playlists_file, &saved_playlists).unwrap();
+}
+
+pub fn create_playlist(library: &mut Library, name: String, songs: Vec<String>) -> String {
+  let id = nanoid!();
+  library.playlists.insert(
+    id.clone(),
+    Playlist {
+      name,
+      songs,
+      query: None,
+    },
+  );
+  write_playlists(library);
+  id
+}
+
+pub fn create_smart_playlist(library: &mut Library, name: String, query: String) -> String {
+  let id = nanoid!();
+  library.playlists.insert(
+    id.clone(),
+    Playlist {
+      name,
+      songs: Vec::new(),
+      query: Some(query),
+    },
+  );
+  refresh_smart_playlists(library);
+  write_playlists(library);
+  id
+}
+
+// Smart playlists are sorted by artist, album and title
+fn get_smart_playlist_key<'a>(
+  library: &'a Library,
+  song_id: &'a String,
+) -> Option<(&'a String, &'a String, &'a String, &'a String)> {
+  let song = library.songs.get(song_id)?;
+  let album = library.albums.get(&song.album)?;
+  let artist = library.artists.get(&album.artist)?;
+  Some((&artist.name, &album.name, &song.title, song_id))
+}
+
+pub fn refresh_smart_playlists(library: &mut Library) {
+  let fuse = get_fuse();
+  let smart_playlists: Vec<(String, Vec<String>)> = library
+    .playlists
+    .iter()
+    .filter_map(|(id, playlist)| {
+      let query = query::parse(playlist.query.as_ref()?);
+      let mut songs: Vec<(&String, &String, &String, &String)> = library
+        .songs
+        .iter()
+        .filter(|(_, song)| query.matches(library, &fuse, song))
+        .filter_map(|(song_id, _)| get_smart_playlist_key(library, song_id))
+        .collect();
+      songs.sort();
+      Some((
+        id.clone(),
+        songs
+          .into_iter()
+          .map(|(_, _, _, song_id)| song_id.clone())
+          .collect(),
+      ))
+    })
+    .collect();
+  for (id, songs) in smart_playlists {
+    library.playlists.get_mut(&id).unwrap().songs = songs;
+  }
+}
+
+// Only the given songs are matched again, the others stay where they are
+pub fn update_smart_playlists(library: &mut Library, song_ids: &[String]) {
+  let fuse = get_fuse();
+  let changed_songs: HashSet<&String> = song_ids.iter().collect();
+  let smart_playlists: Vec<(String, Vec<String>)> = library
+    .playlists
+    .iter()
+    .filter_map(|(id, playlist)| {
+      let query = query::parse(playlist.query.as_ref()?);
+      let mut songs = playlist.songs.clone();
+      songs.retain(|song_id| !changed_songs.contains(song_id));
+      for song_id in song_ids {
+        let key = match library.songs.get(song_id) {
+          Some(song) if query.matches(library, &fuse, song) => {
+            get_smart_playlist_key(library, song_id)
+          }
+          _ => None,
+        };
+        if let Some(key) = key {
+          let position = songs.partition_point(|other_id| {
+            get_smart_playlist_key(library, other_id).map_or(true, |other_key| other_key < key)
+          });
+          songs.insert(position, song_id.clone());
+        }
+      }
+      Some((id.clone(), songs))
+    })
+    .collect();
+  for (id, songs) in smart_playlists {
+    library.playlists.get_mut(&id).unwrap().songs = songs;
+  }
+}
+
+pub fn get_router() -> RouterBuilder<Context> {
+  Router::<Context>::new()
+    .mutation("create", |t| {
+      t(|ctx, input: String| {
+        let mut library = ctx.library.lock().unwrap();
+        library.mark_changed();
+        create_playlist(&mut library, input, Vec::new())
+      })
+    })
+    .mutation("createSmart", |t| {
+      t(|ctx, input: SmartPlaylistInput| {
+        let mut library = ctx.library.lock().unwrap();
+        library.mark_changed();
+        create_smart_playlist(&mut library, input.name, input.query)
+      })
+    })
+    .mutation("setQuery", |t| {
+      t(|ctx, input: SetQueryInput| {
+        let mut library = ctx.library.lock().unwrap();
+        match library.playlists.get_mut(&input.id) {
+          Some(playlist) if playlist.query.is_some() => {
+            playlist.query = Some(input.query);
+            refresh_smart_playlists(&mut library);
+            write_playlists(&library);
+            library.mark_changed();
+            "Successfully updated"
+          }
+          _ => "Could not find smart playlist",
+        }
+      })
+    })
+    .mutation("rename", |t| {
+      t(|ctx, input: RenamePlaylistInput| {
+        let mut library = ctx.library.lock().unwrap();
+        match library.playlists.get_mut(&input.id) {
+          Some(playlist) => {
+            playlist.name = input.name;
+            write_playlists(&library);
+            library.mark_changed();
+            "Successfully renamed"
+          }
+          None => "Could not find playlist to rename",
+        }
+      })
+    })
+    .mutation("delete", |t| {
+      t(|ctx, input: String| {
+        let mut library = ctx.library.lock().unwrap();
+        match library.playlists.remove(&input) {
+          Some(_) => {
+            write_playlists(&library);
+            library.mark_changed();
+            "Successfully deleted"
+          }
+          None => "Could not find playlist to delete",
+        }
+      })
+    })
+    .mutation("addSongs", |t| {
+      t(|ctx, input: AddSongsInput| {
+        let mut library = ctx.library.lock().unwrap();
+        let song_ids: Vec<String> = input
+          .song_ids
+          .into_iter()
+          .filter(|song_id| library.songs.contains_key(song_id))
+          .collect();
+        match library.playlists.get_mut(&input.id) {
+          Some(playlist) if playlist.query.is_some() => "Smart playlists can't be edited",
+          Some(playlist) => {
+            playlist.songs.extend(song_ids);
+            write_playlists(&library);
+            library.mark_changed();
+            "Successfully added"
+          }
+          None => "Could not find playlist",
+        }
+      })
+    })
+    .mutation("removeSong", |t| {
+      t(|ctx, input: RemoveSongInput| {
+        let mut library = ctx.library.lock().unwrap();
+        match library.playlists.get_mut(&input.id) {
+          Some(playlist) if playlist.query.is_some() => "Smart playlists can't be edited",
+          Some(playlist) if (input.index as usize) < playlist.songs.len() => {
+            playlist.songs.remove(input.index as usize);
+            write_playlists(&library);
+            library.mark_changed();
+            "Successfully removed"
+          }
+          _ => "Could not find song to remove",
+        }
+      })
+    })
+}
@@ -0,0 +1,479 @@
+use crate::{
+  library::{Library, Song},
+  search::fold,
+};
+use fuse_rust::Fuse;
+use std::mem;
+
+#[derive(Clone, Copy)]
+pub enum Field {
+  Artist,
+  Album,
+  Title,
+  Genre,
+  Path,
+  Year,
+  Duration,
+  Rating,
+  Plays,
+  Skips,
+}
+
+pub enum Condition {
+  // Quoted values have to be contained as is, others are matched fuzzily.
+  // Both ignore case and diacritics
+  Text { value: String, is_exact: bool },
+  // Both bounds are inclusive
+  Range { min: Option<u32>, max: Option<u32> },
+}
+
+pub enum Filter {
+  All(Vec<Filter>),
+  Any(Vec<Filter>),
+  Not(Box<Filter>),
+  // Contained in the title, album or artist
+  Phrase(String),
+  // Fuzzy matched against the title, album or artist, used for the free words of OR groups
+  Text(String),
+  Field(Field, Condition),
+  Favorite,
+}
+
+// Free text is fuzzy matched like before, everything else is a filter.
+// With OR, the free words are matched in their group's filter instead, and the text is empty
+pub struct Query {
+  pub text: String,
+  pub filter: Filter,
+}
+
+struct Token {
+  text: String,
+  is_quoted: bool,
+  // The whole token was quoted, so it can't be a field
+  is_phrase: bool,
+}
+
+pub fn get_fuse() -> Fuse {
+  Fuse {
+    location: 0,
+    distance: 100,
+    threshold: 0.3,
+    max_pattern_length: 32,
+    is_case_sensitive: false,
+    tokenize: false,
+  }
+}
+
+fn tokenize(query: &str) -> Vec<Token> {
+  let mut tokens = Vec::new();
+  let mut token = Token {
+    text: String::new(),
+    is_quoted: false,
+    is_phrase: false,
+  };
+  let mut is_in_quotes = false;
+  for c in query.chars() {
+    match c {
+      '"' => {
+        if !is_in_quotes && (token.text.is_empty() || token.text == "-") {
+          token.is_phrase = true;
+        }
+        token.is_quoted = true;
+        is_in_quotes = !is_in_quotes;
+      }
+      c if c.is_whitespace() && !is_in_quotes => {
+        if !token.text.is_empty() {
+          tokens.push(mem::replace(
+            &mut token,
+            Token {
+              text: String::new(),
+              is_quoted: false,
+              is_phrase: false,
+            },
+          ));
+        }
+        token.is_quoted = false;
+        token.is_phrase = false;
+      }
+      c => token.text.push(c),
+    }
+  }
+  if !token.text.is_empty() {
+    tokens.push(token);
+  }
+  tokens
+}
+
+fn get_field(name: &str) -> Option<Field> {
+  match name.to_lowercase().as_str() {
+    "artist" => Some(Field::Artist),
+    "album" => Some(Field::Album),
+    "title" => Some(Field::Title),
+    "genre" => Some(Field::Genre),
+    "path" | "file" => Some(Field::Path),
+    "year" => Some(Field::Year),
+    "duration" | "length" => Some(Field::Duration),
+    "rating" => Some(Field::Rating),
+    "plays" => Some(Field::Plays),
+    "skips" => Some(Field::Skips),
+    _ => None,
+  }
+}
+
+// Durations can be written in seconds, as 3:30 or as 3m30s
+fn parse_number(field: Field, text: &str) -> Option<u32> {
+  if !matches!(field, Field::Duration) {
+    return text.parse().ok();
+  }
+  if let Some((minutes, seconds)) = text.split_once(':') {
+    let minutes = minutes.parse::<u32>().ok()?.checked_mul(60)?;
+    return minutes.checked_add(seconds.parse::<u32>().ok()?);
+  }
+  let mut total = 0;
+  let mut number = String::new();
+  for c in text.chars() {
+    let unit = match c {
+      '0'..='9' => {
+        number.push(c);
+        continue;
+      }
+      'h' => 3600,
+      'm' => 60,
+      's' => 1,
+      _ => return None,
+    };
+    let seconds = mem::take(&mut number)
+      .parse::<u32>()
+      .ok()?
+      .checked_mul(unit)?;
+    total = seconds.checked_add(total)?;
+  }
+  if !number.is_empty() {
+    total = number.parse::<u32>().ok()?.checked_add(total)?;
+  }
+  Some(total)
+}
+
+// Accepts 4, >=4, <4, 1995..2000, 1995.. and ..2000
+fn parse_range(field: Field, value: &str) -> Option<Condition> {
+  let (min, max) = if let Some(value) = value.strip_prefix(">=") {
+    (Some(parse_number(field, value)?), None)
+  } else if let Some(value) = value.strip_prefix("<=") {
+    (None, Some(parse_number(field, value)?))
+  } else if let Some(value) = value.strip_prefix('>') {
+    (Some(parse_number(field, value)?.checked_add(1)?), None)
+  } else if let Some(value) = value.strip_prefix('<') {
+    (None, Some(parse_number(field, value)?.checked_sub(1)?))
+  } else if let Some((min, max)) = value.split_once("..") {
+    (
+      match min {
+        "" => None,
+        min => Some(parse_number(field, min)?),
+      },
+      match max {
+        "" => None,
+        max => Some(parse_number(field, max)?),
+      },
+    )
+  } else {
+    let number = parse_number(field, value)?;
+    (Some(number), Some(number))
+  };
+  Some(Condition::Range { min, max })
+}
+
+fn parse_field(token: &str, is_quoted: bool) -> Option<Filter> {
+  let (name, value) = token.split_once(':')?;
+  if value.is_empty() {
+    return None;
+  }
+  if name.eq_ignore_ascii_case("is") {
+    return match value.to_lowercase().as_str() {
+      "favorite" | "fav" => Some(Filter::Favorite),
+      _ => None,
+    };
+  }
+  let field = get_field(name)?;
+  match field {
+    Field::Artist | Field::Album | Field::Title | Field::Genre | Field::Path => {
+      Some(Filter::Field(
+        field,
+        Condition::Text {
+          value: fold(value),
+          is_exact: is_quoted,
+        },
+      ))
+    }
+    _ => parse_range(field, value).map(|condition| Filter::Field(field, condition)),
+  }
+}
+
+// Filters are combined with AND, and OR splits them in groups
+pub fn parse(query: &str) -> Query {
+  // The free words and filters of each group
+  let mut groups: Vec<(Vec<String>, Vec<Filter>)> = vec![(Vec::new(), Vec::new())];
+  for token in tokenize(query) {
+    if token.text == "OR" && !token.is_quoted {
+      groups.push((Vec::new(), Vec::new()));
+      continue;
+    }
+    let (words, filters) = groups.last_mut().unwrap();
+    let is_negated = token.text.len() > 1 && token.text.starts_with('-');
+    let text = if is_negated {
+      token.text[1..].to_string()
+    } else {
+      token.text
+    };
+    let filter = match parse_field(&text, token.is_quoted) {
+      Some(filter) if !token.is_phrase => filter,
+      // Negated words are excluded as is, fuzzy matching them would hide too much
+      _ if token.is_phrase || is_negated => Filter::Phrase(fold(&text)),
+      _ => {
+        words.push(text);
+        continue;
+      }
+    };
+    filters.push(if is_negated {
+      Filter::Not(Box::new(filter))
+    } else {
+      filter
+    });
+  }
+  groups.retain(|(words, filters)| !words.is_empty() || !filters.is_empty());
+  if groups.len() > 1 {
+    return Query {
+      text: String::new(),
+      filter: Filter::Any(
+        groups
+          .into_iter()
+          .map(|(words, mut filters)| {
+            if !words.is_empty() {
+              filters.push(Filter::Text(fold(&words.join(" "))));
+            }
+            Filter::All(filters)
+          })
+          .collect(),
+      ),
+    };
+  }
+  let (words, filters) = groups.pop().unwrap_or_default();
+  Query {
+    text: words.join(" "),
+    filter: Filter::All(filters),
+  }
+}
+
+fn get_text<'a>(library: &'a Library, song: &'a Song, field: Field) -> Option<&'a str> {
+  match field {
+    Field::Artist => library
+      .albums
+      .get(&song.album)
+      .and_then(|album| library.artists.get(&album.artist))
+      .map(|artist| artist.name.as_str()),
+    Field::Album => library
+      .albums
+      .get(&song.album)
+      .map(|album| album.name.as_str()),
+    Field::Title => Some(&song.title),
+    Field::Genre => song.genre.as_deref(),
+    Field::Path => song.path.to_str(),
+    _ => None,
+  }
+}
+
+fn get_number(song: &Song, field: Field) -> Option<u32> {
+  match field {
+    Field::Year => song.year,
+    Field::Duration => Some(song.duration),
+    Field::Rating => Some(song.rating as u32),
+    Field::Plays => Some(song.play_count),
+    Field::Skips => Some(song.skip_count),
+    _ => None,
+  }
+}
+
+// The text has to be folded already
+fn matches_text(library: &Library, fuse: &Fuse, song: &Song, text: &str) -> bool {
+  [Field::Title, Field::Album, Field::Artist]
+    .iter()
+    .filter_map(|field| get_text(library, song, *field))
+    .any(|field_text| {
+      fuse
+        .search_text_in_string(text, &fold(field_text))
+        .is_some()
+    })
+}
+
+impl Filter {
+  pub fn matches(&self, library: &Library, fuse: &Fuse, song: &Song) -> bool {
+    match self {
+      Filter::All(filters) => filters
+        .iter()
+        .all(|filter| filter.matches(library, fuse, song)),
+      Filter::Any(filters) => filters
+        .iter()
+        .any(|filter| filter.matches(library, fuse, song)),
+      Filter::Not(filter) => !filter.matches(library, fuse, song),
+      Filter::Phrase(phrase) => [Field::Title, Field::Album, Field::Artist]
+        .iter()
+        .filter_map(|field| get_text(library, song, *field))
+        .any(|text| fold(text).contains(phrase)),
+      Filter::Text(text) => matches_text(library, fuse, song, text),
+      Filter::Field(field, Condition::Text { value, is_exact }) => {
+        match get_text(library, song, *field) {
+          Some(text) if *is_exact => fold(text).contains(value),
+          Some(text) => fuse.search_text_in_string(value, &fold(text)).is_some(),
+          None => false,
+        }
+      }
+      Filter::Field(field, Condition::Range { min, max }) => match get_number(song, *field) {
+        Some(number) => {
+          min.map_or(true, |min| number >= min) && max.map_or(true, |max| number <= max)
+        }
+        None => false,
+      },
+      Filter::Favorite => song.is_favorite,
+    }
+  }
+}
+
+impl Query {
+  pub fn has_filters(&self) -> bool {
+    !matches!(&self.filter, Filter::All(filters) if filters.is_empty())
+  }
+
+  pub fn add_filter(&mut self, filter: Filter) {
+    let previous_filter = mem::replace(&mut self.filter, Filter::All(Vec::new()));
+    self.filter = Filter::All(vec![previous_filter, filter]);
+  }
+
+  // Used where there is no separate fuzzy search, like smart playlists
+  pub fn matches(&self, library: &Library, fuse: &Fuse, song: &Song) -> bool {
+    (self.text.is_empty() || matches_text(library, fuse, song, &fold(&self.text)))
+      && self.filter.matches(library, fuse, song)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn get_filters(query: &Query) -> &[Filter] {
+    match &query.filter {
+      Filter::All(filters) => filters,
+      _ => panic!("expected filters combined with AND"),
+    }
+  }
+
+  fn get_range(field: Field, value: &str) -> Option<(Option<u32>, Option<u32>)> {
+    match parse_range(field, value)? {
+      Condition::Range { min, max } => Some((min, max)),
+      Condition::Text { .. } => None,
+    }
+  }
+
+  #[test]
+  fn splits_free_words_from_fields() {
+    let query = parse("hello Artist:Björk world");
+    assert_eq!(query.text, "hello world");
+    assert!(matches!(
+      get_filters(&query),
+      [Filter::Field(Field::Artist, Condition::Text { value, is_exact: false })] if value == "bjork"
+    ));
+    // Unknown fields and empty values are searched as words
+    assert_eq!(parse("mood:happy artist:").text, "mood:happy artist:");
+    assert!(!parse("mood:happy").has_filters());
+  }
+
+  #[test]
+  fn parses_numbers_and_ranges() {
+    assert_eq!(get_range(Field::Rating, "4"), Some((Some(4), Some(4))));
+    assert_eq!(get_range(Field::Rating, ">=4"), Some((Some(4), None)));
+    assert_eq!(get_range(Field::Rating, ">4"), Some((Some(5), None)));
+    assert_eq!(get_range(Field::Rating, "<4"), Some((None, Some(3))));
+    assert_eq!(get_range(Field::Rating, "<0"), None);
+    assert_eq!(
+      get_range(Field::Year, "1995..2000"),
+      Some((Some(1995), Some(2000)))
+    );
+    assert_eq!(get_range(Field::Year, "1995.."), Some((Some(1995), None)));
+    assert_eq!(get_range(Field::Year, "..2000"), Some((None, Some(2000))));
+    assert_eq!(get_range(Field::Year, "soon"), None);
+    assert_eq!(
+      get_range(Field::Duration, "3:30"),
+      Some((Some(210), Some(210)))
+    );
+    assert_eq!(
+      get_range(Field::Duration, "<3m30s"),
+      Some((None, Some(209)))
+    );
+    assert_eq!(
+      get_range(Field::Duration, "1h"),
+      Some((Some(3600), Some(3600)))
+    );
+    assert_eq!(get_range(Field::Duration, "90"), Some((Some(90), Some(90))));
+    // Durations too long for a u32 are invalid instead of overflowing
+    assert_eq!(get_range(Field::Duration, "99999999h"), None);
+    assert_eq!(get_range(Field::Duration, "71582789m"), None);
+    assert_eq!(get_range(Field::Duration, "71582788:9999"), None);
+    assert_eq!(get_range(Field::Duration, "1193046h4294967295s"), None);
+  }
+
+  #[test]
+  fn negates_fields_and_words() {
+    let query = parse("-genre:rock -live");
+    assert!(query.text.is_empty());
+    let (genre, live) = match get_filters(&query) {
+      [Filter::Not(genre), Filter::Not(live)] => (&**genre, &**live),
+      _ => panic!("expected two negated filters"),
+    };
+    assert!(matches!(
+      genre,
+      Filter::Field(Field::Genre, Condition::Text { value, .. }) if value == "rock"
+    ));
+    assert!(matches!(live, Filter::Phrase(phrase) if phrase == "live"));
+    // A lone dash is a word
+    assert_eq!(parse("-").text, "-");
+  }
+
+  #[test]
+  fn quotes_make_values_exact_and_phrases() {
+    let query = parse(r#"artist:"The National" "Fake Empire" is:fav"#);
+    assert!(query.text.is_empty());
+    assert!(matches!(
+      get_filters(&query),
+      [
+        Filter::Field(Field::Artist, Condition::Text { value, is_exact: true }),
+        Filter::Phrase(phrase),
+        Filter::Favorite,
+      ] if value == "the national" && phrase == "fake empire"
+    ));
+    // A quoted field is a phrase, not a field
+    assert!(matches!(
+      get_filters(&parse(r#""year:2000""#)),
+      [Filter::Phrase(phrase)] if phrase == "year:2000"
+    ));
+  }
+
+  #[test]
+  fn or_splits_words_and_filters_in_groups() {
+    let query = parse("foo rating:5 OR bar");
+    assert!(query.text.is_empty());
+    let groups = match &query.filter {
+      Filter::Any(groups) => groups,
+      _ => panic!("expected groups combined with OR"),
+    };
+    let (first, second) = match groups.as_slice() {
+      [Filter::All(first), Filter::All(second)] => (first, second),
+      _ => panic!("expected two groups"),
+    };
+    assert!(matches!(
+      first.as_slice(),
+      [Filter::Field(Field::Rating, _), Filter::Text(text)] if text == "foo"
+    ));
+    assert!(matches!(second.as_slice(), [Filter::Text(text)] if text == "bar"));
+    // Quoted or empty groups don't split
+    assert_eq!(parse(r#"foo "OR" bar"#).text, "foo bar");
+    assert_eq!(parse("OR foo OR").text, "foo");
+  }
+}
@@ -0,0 +1,213 @@
+use crate::library::{Library, LibraryGuard};
+use directories::ProjectDirs;
+use lofty::{
+  id3::v2::{Frame, FrameFlags, FrameValue, Popularimeter},
+  mpeg::MpegFile,
+  AudioFile, ItemKey, ItemValue, ParseOptions, Probe, Tag, TagExt, TagType, TaggedFileExt,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::HashMap,
+  fs::{create_dir_all, File},
+  path::{Path, PathBuf},
+};
+
+// The Windows Media Player values, which most players use to map POPM ratings to stars
+const POPM_RATINGS: [u8; 6] = [0, 1, 64, 128, 196, 255];
+const POPM_EMAIL: &str = "ronix";
+
+// Song ids change on every scan, so ratings are saved with song paths
+#[derive(Serialize, Deserialize, Clone, Default)]
+struct SavedRating {
+  rating: u8,
+  is_favorite: bool,
+}
+
+fn get_ratings_file_path() -> PathBuf {
+  ProjectDirs::from("dev", "ronanru", "ronix")
+    .unwrap()
+    .data_dir()
+    .join("ratings.json")
+}
+
+fn read_ratings() -> HashMap<PathBuf, SavedRating> {
+  File::open(get_ratings_file_path())
+    .ok()
+    .and_then(|file| serde_json::from_reader(file).ok())
+    .unwrap_or_default()
+}
+
+fn save_rating(path: &Path, rating: SavedRating) {
+  let mut ratings = read_ratings();
+  ratings.insert(path.to_path_buf(), rating);
+  let ratings_file_path = get_ratings_file_path();
+  create_dir_all(ratings_file_path.parent().unwrap()).unwrap();
+  let ratings_file = File::create(ratings_file_path).unwrap();
+  serde_json::to_writer(ratings_file, &ratings).unwrap();
+}
+
+// The POPM frame is kept as bytes in the generic tag: the email, a null byte, then the rating
+fn read_popm(tags: &Tag) -> Option<u8> {
+  let key = ItemKey::from_key(TagType::Id3v2, "POPM");
+  let bytes = match tags.get(&key)?.value() {
+    ItemValue::Binary(bytes) => bytes,
+    _ => return None,
+  };
+  let email_end = bytes.iter().position(|byte| *byte == 0)?;
+  Some(match *bytes.get(email_end + 1)? {
+    0 => 0,
+    1..=31 => 1,
+    32..=95 => 2,
+    96..=159 => 3,
+    160..=223 => 4,
+    _ => 5,
+  })
+}
+
+// FMPS_RATING goes from 0 to 1, RATING is either in stars or from 0 to 100
+pub fn read_rating_tag(tags: &Tag) -> u8 {
+  if tags.tag_type() == TagType::Id3v2 {
+    return read_popm(tags).unwrap_or(0);
+  }
+  if let Some(rating) = tags
+    .get_string(&ItemKey::Unknown("FMPS_RATING".to_string()))
+    .and_then(|rating| rating.parse::<f64>().ok())
+  {
+    return (rating.clamp(0.0, 1.0) * 5.0).round() as u8;
+  }
+  tags
+    .get_string(&ItemKey::Unknown("RATING".to_string()))
+    .and_then(|rating| rating.parse::<u32>().ok())
+    .map(|rating| match rating {
+      0..=5 => rating as u8,
+      _ => ((rating.min(100) + 10) / 20) as u8,
+    })
+    .unwrap_or(0)
+}
+
+fn write_rating_tag(path: &Path, rating: u8) -> Result<(), String> {
+  let tagged_file = Probe::open(path)
+    .and_then(|probe| probe.read())
+    .map_err(|e| e.to_string())?;
+  match tagged_file.primary_tag_type() {
+    TagType::Id3v2 => {
+      let mpeg_file = File::open(path)
+        .map_err(|e| e.to_string())
+        .and_then(|mut file| {
+          MpegFile::read_from(&mut file, ParseOptions::new()).map_err(|e| e.to_string())
+        })?;
+      let mut tags = mpeg_file.id3v2().cloned().unwrap_or_default();
+      // Keep the play counter other players may have stored
+      let counter = match tags.get("POPM").map(|frame| frame.content()) {
+        Some(FrameValue::Popularimeter(popularimeter)) => popularimeter.counter,
+        _ => 0,
+      };
+      let frame = Frame::new(
+        "POPM",
+        FrameValue::Popularimeter(Popularimeter {
+          email: POPM_EMAIL.to_string(),
+          rating: POPM_RATINGS[rating as usize],
+          counter,
+        }),
+        FrameFlags::default(),
+      )
+      .map_err(|e| e.to_string())?;
+      tags.insert(frame);
+      tags.save_to_path(path).map_err(|e| e.to_string())
+    }
+    TagType::VorbisComments => {
+      let mut tags = tagged_file
+        .primary_tag()
+        .cloned()
+        .unwrap_or_else(|| Tag::new(TagType::VorbisComments));
+      tags.insert_text(
+        ItemKey::Unknown("FMPS_RATING".to_string()),
+        (rating as f64 / 5.0).to_string(),
+      );
+      if tags
+        .get_string(&ItemKey::Unknown("RATING".to_string()))
+        .is_some()
+      {
+        tags.insert_text(
+          ItemKey::Unknown("RATING".to_string()),
+          (rating as u32 * 20).to_string(),
+        );
+      }
+      tags.save_to_path(path).map_err(|e| e.to_string())
+    }
+    _ => Err("Ratings can only be written to ID3 and Vorbis tags".to_string()),
+  }
+}
+
+// Ratings set in Ronix take precedence over the ones read from the files
+pub fn read_saved_ratings(library: &mut Library) {
+  let ratings = read_ratings();
+  for song in library.songs.values_mut() {
+    if let Some(saved_rating) = ratings.get(&song.path) {
+      song.rating = saved_rating.rating;
+      song.is_favorite = saved_rating.is_favorite;
+    }
+  }
+}
+
+pub fn set_rating(
+  library: &mut LibraryGuard,
+  song_id: &str,
+  rating: u8,
+  write_tags: bool,
+) -> String {
+  let song = match library.songs.get_mut(song_id) {
+    Some(song) => song,
+    None => return "Could not find song to rate".to_string(),
+  };
+  let rating = rating.min(5);
+  song.rating = rating;
+  save_rating(
+    &song.path,
+    SavedRating {
+      rating,
+      is_favorite: song.is_favorite,
+    },
+  );
+  let path = song.path.clone();
+  library.mark_songs_changed([song_id.to_string()]);
+  if write_tags {
+    if let Err(e) = write_rating_tag(&path, rating) {
+      return format!(
+        "Saved the rating, but failed to write it to the file: {}",
+        e
+      );
+    }
+  }
+  "Rating saved".to_string()
+}
+
+pub fn set_favorite(library: &mut LibraryGuard, song_id: &str, is_favorite: bool) -> String {
+  let song = match library.songs.get_mut(song_id) {
+    Some(song) => song,
+    None => return "Could not find song".to_string(),
+  };
+  song.is_favorite = is_favorite;
+  save_rating(
+    &song.path,
+    SavedRating {
+      rating: song.rating,
+      is_favorite,
+    },
+  );
+  library.mark_songs_changed([song_id.to_string()]);
+  if is_favorite {
+    "Added to favorites".to_string()
+  } else {
+    "Removed from favorites".to_string()
+  }
+}
+
+// Unrated songs are weighted like three star songs, favorites like five star ones
+pub fn get_shuffle_weight(rating: u8, is_favorite: bool) -> f64 {
+  match (rating, is_favorite) {
+    (_, true) => 5.0,
+    (0, false) => 3.0,
+    (rating, false) => rating as f64,
+  }
+}
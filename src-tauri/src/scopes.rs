@@ -0,0 +1,229 @@
+use crate::{library::Song, search::fold, PlayerScope};
+use rspc::Type;
+use serde::Serialize;
+use std::{
+  collections::{BTreeMap, HashSet},
+  path::{Path, PathBuf},
+};
+
+#[derive(Clone)]
+struct Genre {
+  // As first seen, the key is folded
+  name: String,
+  songs: HashSet<String>,
+}
+
+// Songs by genre, year and folder, kept up to date as songs are read like the search index
+#[derive(Clone, Default)]
+pub struct ScopeIndex {
+  genres: BTreeMap<String, Genre>,
+  years: BTreeMap<u32, HashSet<String>>,
+  // Only the songs directly in each folder
+  folders: BTreeMap<PathBuf, HashSet<String>>,
+}
+
+#[derive(Serialize, Type)]
+pub struct GenreEntry {
+  pub name: String,
+  pub song_count: u32,
+}
+
+#[derive(Serialize, Type)]
+pub struct YearEntry {
+  year: u32,
+  song_count: u32,
+}
+
+#[derive(Serialize, Type)]
+pub struct FolderEntry {
+  path: String,
+  name: String,
+  // Including the songs in subfolders
+  song_count: u32,
+}
+
+#[derive(Serialize, Type)]
+pub struct FolderListing {
+  folders: Vec<FolderEntry>,
+  songs: Vec<String>,
+}
+
+// One tag can have several genres, like "Rock; Pop" or "Rock/Pop"
+pub fn split_genres(genre: &str) -> Vec<String> {
+  genre
+    .split(|c| matches!(c, ';' | '/' | '\\' | '|' | '\0'))
+    .map(str::trim)
+    .filter(|genre| !genre.is_empty())
+    .map(str::to_string)
+    .collect()
+}
+
+impl ScopeIndex {
+  pub fn add(&mut self, id: &str, song: &Song) {
+    for genre in song.genre.as_deref().map(split_genres).unwrap_or_default() {
+      self
+        .genres
+        .entry(fold(&genre))
+        .or_insert_with(|| Genre {
+          name: genre,
+          songs: HashSet::new(),
+        })
+        .songs
+        .insert(id.to_string());
+    }
+    if let Some(year) = song.year {
+      self.years.entry(year).or_default().insert(id.to_string());
+    }
+    if let Some(folder) = song.path.parent() {
+      self
+        .folders
+        .entry(folder.to_path_buf())
+        .or_default()
+        .insert(id.to_string());
+    }
+  }
+
+  pub fn remove(&mut self, id: &str, song: &Song) {
+    for genre in song.genre.as_deref().map(split_genres).unwrap_or_default() {
+      let key = fold(&genre);
+      if let Some(genre) = self.genres.get_mut(&key) {
+        genre.songs.remove(id);
+        if genre.songs.is_empty() {
+          self.genres.remove(&key);
+        }
+      }
+    }
+    if let Some(year) = song.year {
+      if let Some(songs) = self.years.get_mut(&year) {
+        songs.remove(id);
+        if songs.is_empty() {
+          self.years.remove(&year);
+        }
+      }
+    }
+    if let Some(folder) = song.path.parent() {
+      if let Some(songs) = self.folders.get_mut(folder) {
+        songs.remove(id);
+        if songs.is_empty() {
+          self.folders.remove(folder);
+        }
+      }
+    }
+  }
+
+  // Paths are ordered by component, so a folder's subfolders come right after it
+  fn get_subfolders<'a>(
+    &'a self,
+    folder: &'a Path,
+  ) -> impl Iterator<Item = (&'a PathBuf, &'a HashSet<String>)> {
+    self
+      .folders
+      .range(folder.to_path_buf()..)
+      .take_while(move |(path, _)| path.starts_with(folder))
+  }
+
+  // None for the scopes that aren't indexed
+  pub fn get_songs(&self, scope: &PlayerScope) -> Option<HashSet<&String>> {
+    match scope {
+      PlayerScope::Genre(genre) => Some(
+        self
+          .genres
+          .get(&fold(genre))
+          .map(|genre| genre.songs.iter().collect())
+          .unwrap_or_default(),
+      ),
+      PlayerScope::Year(year) => Some(
+        self
+          .years
+          .get(year)
+          .map(|songs| songs.iter().collect())
+          .unwrap_or_default(),
+      ),
+      PlayerScope::Decade(decade) => {
+        let start = decade - decade % 10;
+        Some(
+          self
+            .years
+            .range(start..start.saturating_add(10))
+            .flat_map(|(_, songs)| songs.iter())
+            .collect(),
+        )
+      }
+      PlayerScope::Folder(folder) => Some(
+        self
+          .get_subfolders(Path::new(folder))
+          .flat_map(|(_, songs)| songs.iter())
+          .collect(),
+      ),
+      _ => None,
+    }
+  }
+
+  pub fn get_genres(&self) -> Vec<GenreEntry> {
+    self
+      .genres
+      .values()
+      .map(|genre| GenreEntry {
+        name: genre.name.clone(),
+        song_count: genre.songs.len() as u32,
+      })
+      .collect()
+  }
+
+  pub fn get_years(&self) -> Vec<YearEntry> {
+    self
+      .years
+      .iter()
+      .map(|(year, songs)| YearEntry {
+        year: *year,
+        song_count: songs.len() as u32,
+      })
+      .collect()
+  }
+
+  // Without a folder, lists the music folders themselves
+  pub fn get_folder(&self, folder: Option<&Path>, music_folders: &[PathBuf]) -> FolderListing {
+    let mut subfolders: BTreeMap<PathBuf, u32> = BTreeMap::new();
+    match folder {
+      Some(folder) => {
+        for (path, songs) in self.get_subfolders(folder) {
+          if let Some(child) = path
+            .strip_prefix(folder)
+            .ok()
+            .and_then(|rest| rest.components().next())
+          {
+            *subfolders.entry(folder.join(child)).or_default() += songs.len() as u32;
+          }
+        }
+      }
+      None => {
+        for music_folder in music_folders {
+          let song_count: usize = self
+            .get_subfolders(music_folder)
+            .map(|(_, songs)| songs.len())
+            .sum();
+          if song_count > 0 {
+            subfolders.insert(music_folder.clone(), song_count as u32);
+          }
+        }
+      }
+    }
+    FolderListing {
+      folders: subfolders
+        .into_iter()
+        .map(|(path, song_count)| FolderEntry {
+          name: path
+            .file_name()
+            .map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy())
+            .to_string(),
+          path: path.to_string_lossy().to_string(),
+          song_count,
+        })
+        .collect(),
+      songs: folder
+        .and_then(|folder| self.folders.get(folder))
+        .map(|songs| songs.iter().cloned().collect())
+        .unwrap_or_default(),
+    }
+  }
+}
@@ -0,0 +1,466 @@
+use crate::{
+  config::ScrobblingConfig, get_current_time, history::is_listened, player::get_position, Context,
+};
+use directories::ProjectDirs;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+  collections::BTreeMap,
+  fs::{create_dir_all, File},
+  path::PathBuf,
+  time::Duration,
+};
+use tokio::{
+  sync::mpsc::unbounded_channel,
+  time::{interval, MissedTickBehavior},
+};
+
+// Songs shorter than this are never scrobbled
+const MIN_DURATION: u32 = 30;
+const RETRY_INTERVAL: u32 = 60;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Track {
+  artist: String,
+  album: String,
+  title: String,
+  duration: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+enum Service {
+  ListenBrainz,
+  LastFm,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct QueuedScrobble {
+  service: Service,
+  track: Track,
+  listened_at: u32,
+}
+
+enum Outcome {
+  Sent,
+  // The service is unreachable or asked to come back later
+  Retry,
+  // The token or session key was refused, retrying won't help until it changes
+  Unauthorized,
+  Rejected(String),
+}
+
+struct Play {
+  song_id: String,
+  track: Track,
+  started_at: u32,
+  position: u32,
+  listened: u32,
+  is_announced: bool,
+  is_scrobbled: bool,
+}
+
+fn get_queue_file_path() -> PathBuf {
+  ProjectDirs::from("dev", "ronanru", "ronix")
+    .unwrap()
+    .data_dir()
+    .join("scrobbles.json")
+}
+
+fn read_queue() -> Vec<QueuedScrobble> {
+  File::open(get_queue_file_path())
+    .ok()
+    .and_then(|file| serde_json::from_reader(file).ok())
+    .unwrap_or_default()
+}
+
+fn write_queue(queue: &[QueuedScrobble]) {
+  let queue_file_path = get_queue_file_path();
+  create_dir_all(queue_file_path.parent().unwrap()).unwrap();
+  let queue_file = File::create(queue_file_path).unwrap();
+  serde_json::to_writer(queue_file, queue).unwrap();
+}
+
+fn get_service_name(service: &Service) -> &'static str {
+  match service {
+    Service::ListenBrainz => "ListenBrainz",
+    Service::LastFm => "Last.fm",
+  }
+}
+
+fn get_credentials(config: &ScrobblingConfig, service: &Service) -> String {
+  match service {
+    Service::ListenBrainz => config.listenbrainz_token.clone(),
+    Service::LastFm => config.lastfm_session_key.clone(),
+  }
+}
+
+fn get_enabled_services(config: &ScrobblingConfig) -> Vec<Service> {
+  let mut services = Vec::new();
+  if config.listenbrainz_enabled && !config.listenbrainz_token.is_empty() {
+    services.push(Service::ListenBrainz);
+  }
+  if config.lastfm_enabled && !config.lastfm_session_key.is_empty() {
+    services.push(Service::LastFm);
+  }
+  services
+}
+
+async fn call_lastfm(
+  client: &Client,
+  config: &ScrobblingConfig,
+  mut params: BTreeMap<&str, String>,
+) -> Result<Value, Outcome> {
+  params.insert("api_key", config.lastfm_api_key.clone());
+  let mut signature = String::new();
+  for (key, value) in params.iter() {
+    signature.push_str(key);
+    signature.push_str(value);
+  }
+  signature.push_str(&config.lastfm_api_secret);
+  params.insert("api_sig", format!("{:x}", md5::compute(signature)));
+  params.insert("format", "json".to_string());
+  let response = client
+    .post(&config.lastfm_url)
+    .form(&params)
+    .send()
+    .await
+    .map_err(|_| Outcome::Retry)?;
+  let status = response.status();
+  let body: Value = response.json().await.unwrap_or(Value::Null);
+  match body.get("error").and_then(Value::as_u64) {
+    None if status.is_success() => Ok(body),
+    // Last.fm is down or rate limiting
+    Some(11 | 16 | 29) => Err(Outcome::Retry),
+    // The session has to be renewed by logging in again
+    Some(9) => Err(Outcome::Unauthorized),
+    _ if status.is_server_error() => Err(Outcome::Retry),
+    _ => Err(Outcome::Rejected(
+      body
+        .get("message")
+        .and_then(Value::as_str)
+        .unwrap_or(status.as_str())
+        .to_string(),
+    )),
+  }
+}
+
+async fn call_listenbrainz(
+  client: &Client,
+  config: &ScrobblingConfig,
+  listen_type: &str,
+  listen: Value,
+) -> Outcome {
+  let response = client
+    .post(format!(
+      "{}/1/submit-listens",
+      config.listenbrainz_url.trim_end_matches('/')
+    ))
+    .header(
+      "Authorization",
+      format!("Token {}", config.listenbrainz_token),
+    )
+    .json(&json!({ "listen_type": listen_type, "payload": [listen] }))
+    .send()
+    .await;
+  match response {
+    Ok(response) if response.status().is_success() => Outcome::Sent,
+    Ok(response) if response.status() == StatusCode::UNAUTHORIZED => Outcome::Unauthorized,
+    Ok(response)
+      if !response.status().is_server_error()
+        && response.status() != StatusCode::TOO_MANY_REQUESTS =>
+    {
+      Outcome::Rejected(response.status().to_string())
+    }
+    _ => Outcome::Retry,
+  }
+}
+
+// Sends a scrobble, or a now playing update when `listened_at` is None
+async fn submit(
+  client: &Client,
+  config: &ScrobblingConfig,
+  service: &Service,
+  track: &Track,
+  listened_at: Option<u32>,
+) -> Outcome {
+  match service {
+    Service::ListenBrainz => {
+      let mut listen = json!({
+        "track_metadata": {
+          "artist_name": track.artist,
+          "release_name": track.album,
+          "track_name": track.title,
+          "additional_info": {
+            "duration_ms": track.duration * 1000,
+            "media_player": "Ronix",
+            "submission_client": "Ronix",
+          },
+        },
+      });
+      let listen_type = match listened_at {
+        Some(listened_at) => {
+          listen["listened_at"] = json!(listened_at);
+          "single"
+        }
+        None => "playing_now",
+      };
+      call_listenbrainz(client, config, listen_type, listen).await
+    }
+    Service::LastFm => {
+      let mut params = BTreeMap::from([
+        ("artist", track.artist.clone()),
+        ("album", track.album.clone()),
+        ("track", track.title.clone()),
+        ("duration", track.duration.to_string()),
+        ("sk", config.lastfm_session_key.clone()),
+      ]);
+      match listened_at {
+        Some(listened_at) => {
+          params.insert("method", "track.scrobble".to_string());
+          params.insert("timestamp", listened_at.to_string());
+        }
+        None => {
+          params.insert("method", "track.updateNowPlaying".to_string());
+        }
+      }
+      match call_lastfm(client, config, params).await {
+        Ok(_) => Outcome::Sent,
+        Err(outcome) => outcome,
+      }
+    }
+  }
+}
+
+// Sends the queued scrobbles in order, keeping those of services that can't be reached or that
+// refused the credentials, which are added to `rejected`
+async fn flush_queue(
+  client: &Client,
+  config: &ScrobblingConfig,
+  queue: &mut Vec<QueuedScrobble>,
+  rejected: &mut Vec<(Service, String)>,
+) {
+  let enabled_services = get_enabled_services(config);
+  let mut offline_services: Vec<Service> = rejected
+    .iter()
+    .map(|(service, _)| service.clone())
+    .collect();
+  let mut remaining = Vec::new();
+  for scrobble in queue.drain(..) {
+    if !enabled_services.contains(&scrobble.service) || offline_services.contains(&scrobble.service)
+    {
+      remaining.push(scrobble);
+      continue;
+    }
+    match submit(
+      client,
+      config,
+      &scrobble.service,
+      &scrobble.track,
+      Some(scrobble.listened_at),
+    )
+    .await
+    {
+      Outcome::Sent => {}
+      Outcome::Retry => {
+        offline_services.push(scrobble.service.clone());
+        remaining.push(scrobble);
+      }
+      Outcome::Unauthorized => {
+        rejected.push((
+          scrobble.service.clone(),
+          get_credentials(config, &scrobble.service),
+        ));
+        offline_services.push(scrobble.service.clone());
+        remaining.push(scrobble);
+      }
+      Outcome::Rejected(e) => println!("Dropping scrobble of {}: {}", scrobble.track.title, e),
+    }
+  }
+  *queue = remaining;
+}
+
+fn get_track(ctx: &Context, song_id: &str) -> Option<Track> {
+  let library = ctx.library.lock().unwrap();
+  let song = library.songs.get(song_id)?;
+  let album = library.albums.get(&song.album)?;
+  let artist = library.artists.get(&album.artist)?;
+  Some(Track {
+    artist: artist.name.clone(),
+    album: album.name.clone(),
+    title: song.title.clone(),
+    duration: song.duration,
+  })
+}
+
+fn get_client() -> Client {
+  Client::builder()
+    .timeout(REQUEST_TIMEOUT)
+    .build()
+    .unwrap_or_default()
+}
+
+pub async fn log_in_lastfm(
+  config: &ScrobblingConfig,
+  username: String,
+  password: String,
+) -> Result<String, String> {
+  let params = BTreeMap::from([
+    ("method", "auth.getMobileSession".to_string()),
+    ("username", username),
+    ("password", password),
+  ]);
+  match call_lastfm(&get_client(), config, params).await {
+    Ok(body) => body["session"]["key"]
+      .as_str()
+      .map(|key| key.to_string())
+      .ok_or_else(|| "Last.fm sent an invalid response".to_string()),
+    Err(Outcome::Rejected(e)) => Err(e),
+    Err(Outcome::Unauthorized) => Err("Last.fm refused the session".to_string()),
+    Err(_) => Err("Last.fm is unreachable".to_string()),
+  }
+}
+
+// Watches the player, announcing every new song and scrobbling it once it has been listened to.
+// Requests run in their own tasks, so a slow service doesn't hold up the watching
+pub fn start(ctx: Context) {
+  tauri::async_runtime::spawn(async move {
+    let client = get_client();
+    let mut queue = read_queue();
+    // Scrobbles queued while a flush runs are after the ones it got
+    let (flushed_sender, mut flushed_receiver) = unbounded_channel();
+    let mut is_flushing = false;
+    let mut play: Option<Play> = None;
+    let mut last_check = get_current_time();
+    let mut last_flush = 0;
+    // Services that refused their credentials, with those credentials. They're paused until the
+    // credentials change, their scrobbles are kept in the queue
+    let mut rejected: Vec<(Service, String)> = Vec::new();
+    let mut ticker = interval(Duration::from_secs(1));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+      ticker.tick().await;
+      let config = ctx.config.lock().unwrap().scrobbling.clone();
+      if let Ok((flushed_count, remaining, flush_rejected)) = flushed_receiver.try_recv() {
+        is_flushing = false;
+        let new_scrobbles = queue.split_off(flushed_count);
+        queue = remaining;
+        queue.extend(new_scrobbles);
+        write_queue(&queue);
+        // Credentials changed during the flush are tried again
+        for (service, credentials) in flush_rejected {
+          if get_credentials(&config, &service) == credentials
+            && !rejected
+              .iter()
+              .any(|(rejected_service, _)| rejected_service == &service)
+          {
+            rejected.push((service, credentials));
+          }
+        }
+      }
+      if rejected
+        .iter()
+        .any(|(service, credentials)| get_credentials(&config, service) != *credentials)
+      {
+        rejected.retain(|(service, credentials)| get_credentials(&config, service) == *credentials);
+        // New credentials get tried right away
+        last_flush = 0;
+      }
+      *ctx.scrobbling_error.lock().unwrap() = (!rejected.is_empty()).then(|| {
+        let names: Vec<&str> = rejected
+          .iter()
+          .map(|(service, _)| get_service_name(service))
+          .collect();
+        format!(
+          "{} refused the credentials, scrobbles are kept until they're changed",
+          names.join(" and ")
+        )
+      });
+      let now = get_current_time();
+      let (song_id, position, is_paused) = {
+        let state = ctx.player_state.lock().unwrap();
+        (
+          state.current_song.clone(),
+          get_position(&state),
+          state.paused_at.is_some(),
+        )
+      };
+      let is_new_play = match (&play, &song_id) {
+        // Repeating a song or seeking back after it was scrobbled counts as another listen
+        (Some(play), Some(song_id)) => {
+          &play.song_id != song_id || (play.is_scrobbled && position < play.position)
+        }
+        (None, Some(_)) => true,
+        (_, None) => false,
+      };
+      if song_id.is_none() {
+        play = None;
+      }
+      if is_new_play {
+        let song_id = song_id.unwrap();
+        play = get_track(&ctx, &song_id).map(|track| Play {
+          song_id,
+          track,
+          started_at: now - position,
+          position,
+          listened: 0,
+          is_announced: false,
+          is_scrobbled: false,
+        });
+      }
+      if let Some(play) = play.as_mut() {
+        if !is_paused {
+          play.listened += now.saturating_sub(last_check);
+          if !play.is_announced {
+            play.is_announced = true;
+            for service in get_enabled_services(&config) {
+              if !rejected
+                .iter()
+                .any(|(rejected_service, _)| rejected_service == &service)
+              {
+                let client = client.clone();
+                let config = config.clone();
+                let track = play.track.clone();
+                tauri::async_runtime::spawn(async move {
+                  submit(&client, &config, &service, &track, None).await;
+                });
+              }
+            }
+          }
+        }
+        play.position = position;
+        if !play.is_scrobbled
+          && play.track.duration >= MIN_DURATION
+          && is_listened(play.track.duration, play.listened)
+        {
+          play.is_scrobbled = true;
+          for service in get_enabled_services(&config) {
+            queue.push(QueuedScrobble {
+              service,
+              track: play.track.clone(),
+              listened_at: play.started_at,
+            });
+          }
+          // Saved before sending, so scrobbles survive going offline or quitting
+          write_queue(&queue);
+          last_flush = 0;
+        }
+      }
+      last_check = now;
+      if !is_flushing && !queue.is_empty() && now - last_flush >= RETRY_INTERVAL {
+        is_flushing = true;
+        last_flush = now;
+        let client = client.clone();
+        let flushed_count = queue.len();
+        let mut remaining = queue.clone();
+        let mut flush_rejected = rejected.clone();
+        let flushed_sender = flushed_sender.clone();
+        tauri::async_runtime::spawn(async move {
+          flush_queue(&client, &config, &mut remaining, &mut flush_rejected).await;
+          let _ = flushed_sender.send((flushed_count, remaining, flush_rejected));
+        });
+      }
+    }
+  });
+}
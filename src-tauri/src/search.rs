@@ -0,0 +1,373 @@
+use deunicode::deunicode;
+use std::{
+  collections::{HashMap, HashSet},
+  mem,
+};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+// At least this share of the query's trigrams have to be found in a name
+const MIN_RECALL: f64 = 0.5;
+// Removed names are only skipped until there are this many of them
+const MAX_REMOVED: usize = 1000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DocumentKind {
+  Artist,
+  Album,
+  Song,
+}
+
+// Names can have several documents: the name itself, its sort name and their transliterations
+#[derive(Clone)]
+struct Document {
+  kind: DocumentKind,
+  id: String,
+  name: String,
+  trigram_count: u32,
+  is_transliteration: bool,
+  is_removed: bool,
+}
+
+pub struct SearchHit<'a> {
+  pub kind: DocumentKind,
+  pub id: &'a String,
+  pub score: f64,
+}
+
+// Trigrams of artist names, album names and song titles, kept up to date as songs are read
+#[derive(Clone, Default)]
+pub struct SearchIndex {
+  documents: Vec<Document>,
+  positions: HashMap<(DocumentKind, String), Vec<u32>>,
+  postings: HashMap<[char; 3], Vec<u32>>,
+  removed_count: usize,
+}
+
+// Case, diacritics, width and ligatures are ignored
+pub fn fold(text: &str) -> String {
+  let mut folded = String::new();
+  for c in text.nfkd().filter(|c| !is_combining_mark(*c)) {
+    // Letters that don't decompose into a base letter and a mark
+    match c {
+      'æ' | 'Æ' => folded.push_str("ae"),
+      'œ' | 'Œ' => folded.push_str("oe"),
+      'ß' | 'ẞ' => folded.push_str("ss"),
+      'þ' | 'Þ' => folded.push_str("th"),
+      'ø' | 'Ø' => folded.push('o'),
+      'đ' | 'Đ' | 'ð' | 'Ð' => folded.push('d'),
+      'ł' | 'Ł' => folded.push('l'),
+      'ı' => folded.push('i'),
+      c => folded.extend(c.to_lowercase()),
+    }
+  }
+  folded
+}
+
+// Cyrillic, Greek and kana are romanized, and Chinese characters written in pinyin
+pub fn transliterate(text: &str) -> String {
+  fold(&deunicode(text))
+}
+
+fn normalize(text: &str) -> String {
+  let mut normalized = String::new();
+  for c in fold(text).chars() {
+    if c.is_alphanumeric() {
+      normalized.push(c);
+    } else if !normalized.is_empty() && !normalized.ends_with(' ') {
+      normalized.push(' ');
+    }
+  }
+  normalized.trim_end().to_string()
+}
+
+// Words are padded so their start weighs more, the last word of a query may still be typed
+fn get_trigrams(text: &str, is_query: bool) -> Vec<[char; 3]> {
+  let words: Vec<&str> = text.split(' ').filter(|word| !word.is_empty()).collect();
+  let mut trigrams = Vec::new();
+  for (i, word) in words.iter().enumerate() {
+    let mut chars = vec![' ', ' '];
+    chars.extend(word.chars());
+    if !is_query || i < words.len() - 1 {
+      chars.push(' ');
+    }
+    trigrams.extend(
+      chars
+        .windows(3)
+        .map(|window| [window[0], window[1], window[2]]),
+    );
+  }
+  trigrams.sort_unstable();
+  trigrams.dedup();
+  trigrams
+}
+
+impl SearchIndex {
+  fn insert(&mut self, kind: DocumentKind, id: &str, name: String, is_transliteration: bool) {
+    let trigrams = get_trigrams(&name, false);
+    let position = self.documents.len() as u32;
+    for trigram in trigrams.iter() {
+      self.postings.entry(*trigram).or_default().push(position);
+    }
+    self.documents.push(Document {
+      kind,
+      id: id.to_string(),
+      name,
+      trigram_count: trigrams.len() as u32,
+      is_transliteration,
+      is_removed: false,
+    });
+    self
+      .positions
+      .entry((kind, id.to_string()))
+      .or_default()
+      .push(position);
+  }
+
+  // Transliterations are only indexed when they differ, so Latin names aren't indexed twice
+  pub fn add(&mut self, kind: DocumentKind, id: &str, name: &str, sort_name: Option<&str>) {
+    self.remove(kind, id);
+    let mut names: Vec<(String, bool)> = Vec::new();
+    for name in [Some(name), sort_name].into_iter().flatten() {
+      names.push((normalize(name), false));
+      names.push((normalize(&transliterate(name)), true));
+    }
+    for (i, (name, is_transliteration)) in names.iter().enumerate() {
+      if !name.is_empty() && !names[..i].iter().any(|(other_name, _)| other_name == name) {
+        self.insert(kind, id, name.clone(), *is_transliteration);
+      }
+    }
+  }
+
+  pub fn remove(&mut self, kind: DocumentKind, id: &str) {
+    let positions = match self.positions.remove(&(kind, id.to_string())) {
+      Some(positions) => positions,
+      None => return,
+    };
+    for position in positions {
+      self.documents[position as usize].is_removed = true;
+      self.removed_count += 1;
+    }
+    if self.removed_count > MAX_REMOVED {
+      let documents = mem::take(&mut self.documents);
+      *self = SearchIndex::default();
+      for document in documents
+        .into_iter()
+        .filter(|document| !document.is_removed)
+      {
+        self.insert(
+          document.kind,
+          &document.id,
+          document.name,
+          document.is_transliteration,
+        );
+      }
+    }
+  }
+
+  // Ranked by how much of the query was found, with exact and prefix matches first.
+  // With transliteration, the query is romanized too so any script can find any other
+  pub fn search(&self, query: &str, is_transliterating: bool) -> Vec<SearchHit> {
+    let query = if is_transliterating {
+      normalize(&transliterate(query))
+    } else {
+      normalize(query)
+    };
+    let trigrams = get_trigrams(&query, true);
+    if trigrams.is_empty() {
+      return Vec::new();
+    }
+    let mut counts = vec![0u16; self.documents.len()];
+    let mut candidates = Vec::new();
+    for trigram in trigrams.iter() {
+      for position in self.postings.get(trigram).into_iter().flatten() {
+        let count = &mut counts[*position as usize];
+        if *count == 0 {
+          candidates.push(*position);
+        }
+        *count += 1;
+      }
+    }
+    let min_count = ((trigrams.len() as f64 * MIN_RECALL).ceil() as u16).max(1);
+    let mut hits: Vec<SearchHit> = candidates
+      .into_iter()
+      .filter_map(|position| {
+        let count = counts[position as usize];
+        let document = &self.documents[position as usize];
+        if count < min_count
+          || document.is_removed
+          || (document.is_transliteration && !is_transliterating)
+        {
+          return None;
+        }
+        let recall = count as f64 / trigrams.len() as f64;
+        let precision = count as f64 / document.trigram_count.max(1) as f64;
+        let bonus = if document.name == query {
+          0.5
+        } else if document.name.starts_with(&query) {
+          0.3
+        } else if document.name.contains(&query) {
+          0.2
+        } else {
+          0.0
+        };
+        Some(SearchHit {
+          kind: document.kind,
+          id: &document.id,
+          score: recall * 0.8 + precision * 0.2 + bonus,
+        })
+      })
+      .collect();
+    // Only the best matching document of each name is kept
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let mut seen = HashSet::new();
+    hits.retain(|hit| seen.insert((hit.kind, hit.id)));
+    hits
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::{Duration, Instant};
+
+  fn ids<'a>(hits: &'a [SearchHit]) -> Vec<&'a str> {
+    hits.iter().map(|hit| hit.id.as_str()).collect()
+  }
+
+  #[test]
+  fn pads_words_and_leaves_the_last_query_word_open() {
+    assert_eq!(
+      get_trigrams("ab", false),
+      [[' ', ' ', 'a'], [' ', 'a', 'b'], ['a', 'b', ' ']]
+    );
+    assert_eq!(get_trigrams("ab", true), [[' ', ' ', 'a'], [' ', 'a', 'b']]);
+    assert_eq!(get_trigrams("ab ab", false), get_trigrams("ab", false));
+    assert!(get_trigrams("  ", true).is_empty());
+  }
+
+  #[test]
+  fn ranks_exact_then_prefix_then_partial_matches() {
+    let mut index = SearchIndex::default();
+    index.add(
+      DocumentKind::Song,
+      "radio-edit",
+      "Idioteque Radio Edit",
+      None,
+    );
+    index.add(
+      DocumentKind::Album,
+      "radiohead-live",
+      "Radiohead Live",
+      None,
+    );
+    index.add(DocumentKind::Artist, "radiohead", "Radiohead", None);
+    index.add(DocumentKind::Album, "radio", "Radio", None);
+    index.add(DocumentKind::Song, "android", "Paranoid Android", None);
+    assert_eq!(
+      ids(&index.search("radio", false)),
+      ["radio", "radiohead", "radiohead-live", "radio-edit"]
+    );
+    // Names with only part of the query come after the ones with all of it
+    assert_eq!(
+      ids(&index.search("radiohead", false)),
+      ["radiohead", "radiohead-live", "radio", "radio-edit"]
+    );
+  }
+
+  #[test]
+  fn finds_names_while_typing_and_with_typos() {
+    let mut index = SearchIndex::default();
+    index.add(DocumentKind::Artist, "artist", "Björk", None);
+    index.add(DocumentKind::Song, "song", "Hyperballad", None);
+    assert_eq!(ids(&index.search("bjo", false)), ["artist"]);
+    assert_eq!(ids(&index.search("hyperbalad", false)), ["song"]);
+  }
+
+  #[test]
+  fn matches_sort_names_and_transliterations() {
+    let mut index = SearchIndex::default();
+    index.add(
+      DocumentKind::Artist,
+      "artist",
+      "Земфира",
+      Some("Zemfira Ramazanova"),
+    );
+    assert_eq!(ids(&index.search("ramazanova", false)), ["artist"]);
+    assert_eq!(ids(&index.search("zemfira", false)), ["artist"]);
+    let mut index = SearchIndex::default();
+    index.add(DocumentKind::Artist, "artist", "Земфира", None);
+    assert!(index.search("zemfira", false).is_empty());
+    assert_eq!(ids(&index.search("zemfira", true)), ["artist"]);
+  }
+
+  #[test]
+  fn skips_removed_and_replaced_names() {
+    let mut index = SearchIndex::default();
+    index.add(DocumentKind::Song, "song", "Karma Police", None);
+    index.add(DocumentKind::Song, "song", "Lucky", None);
+    assert!(index.search("karma", false).is_empty());
+    assert_eq!(ids(&index.search("lucky", false)), ["song"]);
+    index.remove(DocumentKind::Song, "song");
+    assert!(index.search("lucky", false).is_empty());
+  }
+
+  #[test]
+  fn compacts_after_many_removals() {
+    let mut index = SearchIndex::default();
+    for i in 0..=MAX_REMOVED {
+      index.add(
+        DocumentKind::Song,
+        &i.to_string(),
+        &format!("Removed {}", i),
+        None,
+      );
+    }
+    index.add(DocumentKind::Song, "kept", "Kept Song", None);
+    for i in 0..=MAX_REMOVED {
+      index.remove(DocumentKind::Song, &i.to_string());
+    }
+    assert_eq!(index.documents.len(), 1);
+    assert_eq!(index.removed_count, 0);
+    assert_eq!(ids(&index.search("kept", false)), ["kept"]);
+    assert!(index.search("removed", false).is_empty());
+  }
+
+  // Made up names from random syllables, so trigrams are spread like in real libraries
+  fn get_name(seed: &mut u64, words: usize) -> String {
+    const SYLLABLES: [&str; 16] = [
+      "ka", "lo", "mi", "ne", "ru", "sa", "to", "vi", "bre", "dan", "for", "gil", "hor", "jun",
+      "pel", "str",
+    ];
+    let mut name = Vec::new();
+    for _ in 0..words {
+      let mut word = String::new();
+      for _ in 0..3 {
+        *seed = seed
+          .wrapping_mul(6364136223846793005)
+          .wrapping_add(1442695040888963407);
+        word.push_str(SYLLABLES[(*seed >> 60) as usize]);
+      }
+      name.push(word);
+    }
+    name.join(" ")
+  }
+
+  // Run with `cargo test --release -- --ignored`, debug builds are much slower
+  #[test]
+  #[ignore]
+  fn searches_100k_songs_quickly() {
+    let mut index = SearchIndex::default();
+    let mut seed = 1;
+    for i in 0..100_000 {
+      let name = get_name(&mut seed, 3);
+      index.add(DocumentKind::Song, &i.to_string(), &name, None);
+    }
+    let query = get_name(&mut seed, 2);
+    let start = Instant::now();
+    for _ in 0..10 {
+      index.search(&query, true);
+    }
+    let elapsed = start.elapsed() / 10;
+    assert!(elapsed < Duration::from_millis(20), "took {:?}", elapsed);
+  }
+}
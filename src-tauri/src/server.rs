@@ -0,0 +1,206 @@
+use crate::{
+  config::RemoteConfig,
+  covers::{self, CoverSize},
+  ipc::get_socket_path,
+  library, player, Context,
+};
+use axum::{
+  extract::{Path, Query},
+  http::{header, Request, StatusCode},
+  middleware::{self, Next},
+  response::{Html, IntoResponse, Response},
+  routing::get,
+};
+use nanoid::nanoid;
+use rspc::Router;
+use std::{
+  collections::HashMap,
+  fs::{self, OpenOptions},
+  io::Write,
+  net::{SocketAddr, UdpSocket},
+  os::unix::fs::OpenOptionsExt,
+  path::PathBuf,
+  sync::Arc,
+};
+
+const REMOTE_PAGE: &str = include_str!("../remote/index.html");
+
+// The window can't use the cover protocol without a context, so it gets covers from here
+async fn serve_cover(
+  ctx: Context,
+  Path(album_id): Path<String>,
+  Query(query): Query<HashMap<String, String>>,
+) -> Response {
+  let size = CoverSize::parse(query.get("size").map(|size| size.as_str()));
+  let cover = tokio::task::spawn_blocking(move || covers::read_cover(&ctx, &album_id, size)).await;
+  match cover.ok().flatten() {
+    Some((data, mime_type)) => ([(header::CONTENT_TYPE, mime_type)], data).into_response(),
+    None => StatusCode::NOT_FOUND.into_response(),
+  }
+}
+
+// The window's origin, and the dev server's in debug builds
+fn is_tauri_origin(origin: &str) -> bool {
+  origin == "tauri://localhost"
+    || origin == "https://tauri.localhost"
+    || (cfg!(debug_assertions) && origin == "http://localhost:1420")
+}
+
+// Browsers let any page connect to localhost, so requests from other origins are refused
+// even with the token. Requests without an origin come from other programs
+async fn check_local_request<B>(
+  token: String,
+  request: Request<B>,
+  next: Next<B>,
+) -> Result<Response, StatusCode> {
+  let origin = request.headers().get(header::ORIGIN);
+  if origin.map_or(false, |origin| {
+    !origin.to_str().map_or(false, is_tauri_origin)
+  }) {
+    return Err(StatusCode::FORBIDDEN);
+  }
+  check_token(token, request, next).await
+}
+
+fn get_daemon_token_path() -> PathBuf {
+  get_socket_path().with_file_name("daemon-token")
+}
+
+// A new token for every daemon, saved where only this user can read it
+pub fn create_daemon_token() -> String {
+  let token = nanoid!();
+  let path = get_daemon_token_path();
+  let _ = fs::remove_file(&path);
+  let result = fs::create_dir_all(path.parent().unwrap()).and_then(|_| {
+    OpenOptions::new()
+      .write(true)
+      .create_new(true)
+      .mode(0o600)
+      .open(&path)?
+      .write_all(token.as_bytes())
+  });
+  if let Err(e) = result {
+    println!("Failed to save daemon token: {}", e);
+  }
+  token
+}
+
+pub fn remove_daemon_token() {
+  let _ = fs::remove_file(get_daemon_token_path());
+}
+
+// Only reachable from this machine, used by windows attaching to a daemon
+pub async fn serve_local(router: Arc<Router<Context>>, ctx: Context, port: u16, token: String) {
+  let cover_ctx = ctx.clone();
+  let app = axum::Router::new()
+    .route(
+      "/covers/:album_id",
+      get(move |album_id, query| serve_cover(cover_ctx.clone(), album_id, query)),
+    )
+    .nest("/rspc", router.endpoint(move || ctx.clone()).axum())
+    .layer(middleware::from_fn(move |request, next| {
+      check_local_request(token.clone(), request, next)
+    }));
+  let result = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], port)))
+    .serve(app.into_make_service())
+    .await;
+  if let Err(e) = result {
+    println!("Failed to serve daemon API: {}", e);
+  }
+}
+
+// Playback control and reading the library, paired phones can't change settings or files
+fn get_remote_router() -> Arc<Router<Context>> {
+  Router::<Context>::new()
+    .merge("player.", player::get_router())
+    .merge("library.", library::get_read_router())
+    .build()
+    .arced()
+}
+
+fn get_request_token<B>(request: &Request<B>) -> Option<&str> {
+  // Browsers can't set headers on websockets, so the token can also be a query parameter
+  request
+    .headers()
+    .get(header::AUTHORIZATION)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.strip_prefix("Bearer "))
+    .or_else(|| {
+      request
+        .uri()
+        .query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("token="))
+    })
+}
+
+// Compared in constant time, so the token can't be guessed one character at a time
+fn is_token_valid(request_token: &str, token: &str) -> bool {
+  request_token.len() == token.len()
+    && request_token
+      .bytes()
+      .zip(token.bytes())
+      .fold(0, |difference, (a, b)| difference | (a ^ b))
+      == 0
+}
+
+async fn check_token<B>(
+  token: String,
+  request: Request<B>,
+  next: Next<B>,
+) -> Result<Response, StatusCode> {
+  match get_request_token(&request) {
+    Some(request_token) if is_token_valid(request_token, &token) => Ok(next.run(request).await),
+    _ => Err(StatusCode::UNAUTHORIZED),
+  }
+}
+
+fn get_lan_address() -> Option<String> {
+  // Connecting a UDP socket sends nothing, it only picks the outgoing interface
+  let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+  socket.connect("8.8.8.8:80").ok()?;
+  Some(socket.local_addr().ok()?.ip().to_string())
+}
+
+pub fn get_remote_url(remote: &RemoteConfig) -> Option<String> {
+  if !remote.enabled {
+    return None;
+  }
+  Some(format!(
+    "http://{}:{}/?token={}",
+    get_lan_address().unwrap_or("localhost".to_string()),
+    remote.port,
+    remote.token
+  ))
+}
+
+// Stops the running remote server and starts it again with the current config
+pub fn start_remote(ctx: &Context) {
+  let mut remote_server = ctx.remote_server.lock().unwrap();
+  if let Some(server) = remote_server.take() {
+    server.abort();
+  }
+  let remote = ctx.config.lock().unwrap().remote.clone();
+  if !remote.enabled {
+    return;
+  }
+  let ctx = ctx.clone();
+  *remote_server = Some(tauri::async_runtime::spawn(async move {
+    let token = remote.token;
+    let api = get_remote_router()
+      .endpoint(move || ctx.clone())
+      .axum()
+      .layer(middleware::from_fn(move |request, next| {
+        check_token(token.clone(), request, next)
+      }));
+    let app = axum::Router::new()
+      .route("/", get(|| async { Html(REMOTE_PAGE) }))
+      .nest("/rspc", api);
+    let result = axum::Server::bind(&SocketAddr::from(([0, 0, 0, 0], remote.port)))
+      .serve(app.into_make_service())
+      .await;
+    if let Err(e) = result {
+      println!("Failed to serve remote control API: {}", e);
+    }
+  }));
+}
@@ -0,0 +1,337 @@
+use crate::{library::Library, search::fold};
+use rspc::Type;
+use serde::Deserialize;
+use std::{
+  cmp::Ordering,
+  collections::{HashMap, HashSet},
+  iter::Peekable,
+  str::Chars,
+};
+
+#[derive(Deserialize, Type, Clone, Copy, PartialEq)]
+pub enum SortKey {
+  Name,
+  Artist,
+  Album,
+  Year,
+  DateAdded,
+  PlayCount,
+  LastPlayed,
+  Duration,
+  Rating,
+}
+
+#[derive(Deserialize, Type)]
+pub enum SortKind {
+  Artists,
+  Albums,
+  Songs,
+}
+
+#[derive(Deserialize, Type)]
+pub struct SortInput {
+  kind: SortKind,
+  key: SortKey,
+  descending: bool,
+  // Everything of that kind when not given
+  ids: Option<Vec<String>>,
+}
+
+struct SortEntry<'a> {
+  id: &'a String,
+  number: Option<u64>,
+  names: Vec<String>,
+}
+
+// Sort tags win, otherwise leading articles and punctuation are skipped: "The Beatles" is under B
+pub fn get_sort_name(name: &str, sort_name: Option<&str>, articles: &[String]) -> String {
+  if let Some(sort_name) = sort_name {
+    return fold(sort_name);
+  }
+  let name = fold(name);
+  let name = name.trim_start_matches(|c: char| !c.is_alphanumeric());
+  for article in articles {
+    let article = fold(article);
+    if let Some(rest) = name
+      .strip_prefix(&article)
+      .and_then(|rest| rest.strip_prefix(' '))
+    {
+      if !rest.trim().is_empty() {
+        return rest.trim_start().to_string();
+      }
+    }
+  }
+  name.to_string()
+}
+
+fn take_number(chars: &mut Peekable<Chars>) -> String {
+  let mut number = String::new();
+  while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+    number.push(*c);
+    chars.next();
+  }
+  number.trim_start_matches('0').to_string()
+}
+
+// Numbers are compared by value, so "Track 2" comes before "Track 10"
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+  let mut a_chars = a.chars().peekable();
+  let mut b_chars = b.chars().peekable();
+  loop {
+    match (a_chars.peek().copied(), b_chars.peek().copied()) {
+      (None, None) => return Ordering::Equal,
+      (None, Some(_)) => return Ordering::Less,
+      (Some(_), None) => return Ordering::Greater,
+      (Some(a_char), Some(b_char)) if a_char.is_ascii_digit() && b_char.is_ascii_digit() => {
+        let a_number = take_number(&mut a_chars);
+        let b_number = take_number(&mut b_chars);
+        let ordering = a_number
+          .len()
+          .cmp(&b_number.len())
+          .then_with(|| a_number.cmp(&b_number));
+        if ordering != Ordering::Equal {
+          return ordering;
+        }
+      }
+      (Some(a_char), Some(b_char)) => {
+        let ordering = a_char.cmp(&b_char);
+        if ordering != Ordering::Equal {
+          return ordering;
+        }
+        a_chars.next();
+        b_chars.next();
+      }
+    }
+  }
+}
+
+// Artists and albums are shared by many entries, so their names are only folded once
+fn get_artist_name<'a>(
+  library: &'a Library,
+  names: &mut HashMap<&'a String, String>,
+  artist_id: &'a String,
+  articles: &[String],
+) -> String {
+  names
+    .entry(artist_id)
+    .or_insert_with(|| {
+      let artist = library.artists.get(artist_id).unwrap();
+      get_sort_name(&artist.name, artist.sort_name.as_deref(), articles)
+    })
+    .clone()
+}
+
+fn get_album_name<'a>(
+  library: &'a Library,
+  names: &mut HashMap<&'a String, String>,
+  album_id: &'a String,
+  articles: &[String],
+) -> String {
+  names
+    .entry(album_id)
+    .or_insert_with(|| {
+      let album = library.albums.get(album_id).unwrap();
+      get_sort_name(&album.name, album.sort_name.as_deref(), articles)
+    })
+    .clone()
+}
+
+// Albums and artists use the earliest year, the newest song added and their total duration
+pub fn get_sorted(library: &Library, input: SortInput, articles: &[String]) -> Vec<String> {
+  // Only what is sorted gets its names folded
+  let ids: Option<HashSet<&String>> = input.ids.as_ref().map(|ids| ids.iter().collect());
+  let is_wanted = |id: &String| ids.as_ref().map_or(true, |ids| ids.contains(id));
+  let mut artist_names: HashMap<&String, String> = HashMap::new();
+  let mut album_names: HashMap<&String, String> = HashMap::new();
+  let mut album_years: HashMap<&String, u32> = HashMap::new();
+  let mut album_added: HashMap<&String, u32> = HashMap::new();
+  let mut album_durations: HashMap<&String, u32> = HashMap::new();
+  let mut artist_years: HashMap<&String, u32> = HashMap::new();
+  let mut artist_added: HashMap<&String, u32> = HashMap::new();
+  let mut artist_durations: HashMap<&String, u32> = HashMap::new();
+  let needs_totals = matches!(
+    input.key,
+    SortKey::Year | SortKey::DateAdded | SortKey::Duration
+  );
+  if needs_totals && !matches!(input.kind, SortKind::Songs) {
+    for song in library.songs.values() {
+      let artist_id = &library.albums.get(&song.album).unwrap().artist;
+      let is_wanted = match input.kind {
+        SortKind::Artists => is_wanted(artist_id),
+        _ => is_wanted(&song.album),
+      };
+      if !is_wanted {
+        continue;
+      }
+      if let Some(year) = song.year {
+        for years in [
+          album_years.entry(&song.album).or_insert(year),
+          artist_years.entry(artist_id).or_insert(year),
+        ] {
+          *years = (*years).min(year);
+        }
+      }
+      for added in [
+        album_added.entry(&song.album).or_default(),
+        artist_added.entry(artist_id).or_default(),
+      ] {
+        *added = (*added).max(song.added_at);
+      }
+      *album_durations.entry(&song.album).or_default() += song.duration;
+      *artist_durations.entry(artist_id).or_default() += song.duration;
+    }
+  }
+  let mut entries: Vec<SortEntry> = Vec::new();
+  match input.kind {
+    SortKind::Artists => {
+      for (id, artist) in library.artists.iter().filter(|(id, _)| is_wanted(id)) {
+        entries.push(SortEntry {
+          id,
+          number: match input.key {
+            SortKey::Year => artist_years.get(id).map(|year| *year as u64),
+            SortKey::DateAdded => artist_added.get(id).map(|added| *added as u64),
+            SortKey::PlayCount => Some(artist.play_count as u64),
+            SortKey::LastPlayed => artist.last_played.map(|time| time as u64),
+            SortKey::Duration => artist_durations.get(id).map(|duration| *duration as u64),
+            _ => None,
+          },
+          names: vec![get_artist_name(library, &mut artist_names, id, articles)],
+        });
+      }
+    }
+    SortKind::Albums => {
+      for (id, album) in library.albums.iter().filter(|(id, _)| is_wanted(id)) {
+        let album_name = get_album_name(library, &mut album_names, id, articles);
+        entries.push(SortEntry {
+          id,
+          number: match input.key {
+            SortKey::Year => album_years.get(id).map(|year| *year as u64),
+            SortKey::DateAdded => album_added.get(id).map(|added| *added as u64),
+            SortKey::PlayCount => Some(album.play_count as u64),
+            SortKey::LastPlayed => album.last_played.map(|time| time as u64),
+            SortKey::Duration => album_durations.get(id).map(|duration| *duration as u64),
+            _ => None,
+          },
+          names: match input.key {
+            SortKey::Artist => vec![
+              get_artist_name(library, &mut artist_names, &album.artist, articles),
+              album_name,
+            ],
+            _ => vec![album_name],
+          },
+        });
+      }
+    }
+    SortKind::Songs => {
+      for (id, song) in library.songs.iter().filter(|(id, _)| is_wanted(id)) {
+        let title = get_sort_name(&song.title, song.sort_title.as_deref(), articles);
+        let album = library.albums.get(&song.album).unwrap();
+        entries.push(SortEntry {
+          id,
+          number: match input.key {
+            SortKey::Year => song.year.map(|year| year as u64),
+            SortKey::DateAdded => Some(song.added_at as u64),
+            SortKey::PlayCount => Some(song.play_count as u64),
+            SortKey::LastPlayed => song.last_played.map(|time| time as u64),
+            SortKey::Duration => Some(song.duration as u64),
+            SortKey::Rating => Some(song.rating as u64),
+            _ => None,
+          },
+          names: match input.key {
+            SortKey::Artist => vec![
+              get_artist_name(library, &mut artist_names, &album.artist, articles),
+              get_album_name(library, &mut album_names, &song.album, articles),
+              title,
+            ],
+            SortKey::Album => vec![
+              get_album_name(library, &mut album_names, &song.album, articles),
+              title,
+            ],
+            _ => vec![title],
+          },
+        });
+      }
+    }
+  }
+  entries.sort_by(|a, b| {
+    // Missing years or songs that were never played always come last
+    let ordering = match (a.number, b.number) {
+      (Some(a_number), Some(b_number)) => a_number.cmp(&b_number),
+      (None, Some(_)) => return Ordering::Greater,
+      (Some(_), None) => return Ordering::Less,
+      (None, None) => Ordering::Equal,
+    }
+    .then_with(|| {
+      a.names
+        .iter()
+        .zip(b.names.iter())
+        .map(|(a_name, b_name)| natural_cmp(a_name, b_name))
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+    });
+    if input.descending {
+      ordering.reverse()
+    } else {
+      ordering
+    }
+  });
+  entries.into_iter().map(|entry| entry.id.clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn compares_numbers_by_value() {
+    assert_eq!(natural_cmp("track 2", "track 10"), Ordering::Less);
+    assert_eq!(
+      natural_cmp("disc 1 track 10", "disc 1 track 9"),
+      Ordering::Greater
+    );
+    assert_eq!(natural_cmp("track 02", "track 2"), Ordering::Equal);
+    // Longer than any integer type
+    assert_eq!(
+      natural_cmp("99999999999999999999", "100000000000000000000"),
+      Ordering::Less
+    );
+    assert_eq!(natural_cmp("a", "b"), Ordering::Less);
+    assert_eq!(natural_cmp("ab", "abc"), Ordering::Less);
+    assert_eq!(natural_cmp("2 a", "2"), Ordering::Greater);
+  }
+
+  #[test]
+  fn skips_articles_and_punctuation() {
+    let articles = ["The".to_string(), "A".to_string()];
+    assert_eq!(get_sort_name("The Beatles", None, &articles), "beatles");
+    assert_eq!(
+      get_sort_name("A Tribe Called Quest", None, &articles),
+      "tribe called quest"
+    );
+    assert_eq!(
+      get_sort_name("...And Justice for All", None, &articles),
+      "and justice for all"
+    );
+    assert_eq!(
+      get_sort_name("Émilie Simon", None, &articles),
+      "emilie simon"
+    );
+    // Only whole words are articles, and names aren't left empty
+    assert_eq!(
+      get_sort_name("Theatre of Tragedy", None, &articles),
+      "theatre of tragedy"
+    );
+    assert_eq!(get_sort_name("The", None, &articles), "the");
+    assert_eq!(get_sort_name("The Beatles", None, &[]), "the beatles");
+  }
+
+  #[test]
+  fn prefers_sort_tags() {
+    let articles = ["The".to_string()];
+    assert_eq!(
+      get_sort_name("The Beatles", Some("Beatles, The"), &articles),
+      "beatles, the"
+    );
+    assert_eq!(get_sort_name("Björk", Some("Bjork"), &articles), "bjork");
+  }
+}
@@ -0,0 +1,477 @@
+use crate::{
+  history::{HistoryEvent, HistoryEventKind},
+  library::Library,
+  scopes::split_genres,
+  Context,
+};
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeZone, Timelike};
+use rspc::{Router, RouterBuilder, Type};
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::{BTreeMap, HashMap, HashSet},
+  fs,
+  path::PathBuf,
+};
+use tauri::api::dialog::blocking::FileDialogBuilder;
+
+#[derive(Deserialize, Type)]
+struct StatsInput {
+  // Unix timestamps, both optional to cover the whole history
+  from: Option<u32>,
+  to: Option<u32>,
+  limit: u32,
+}
+
+#[derive(Deserialize, Type)]
+enum ReportFormat {
+  Json,
+  Html,
+}
+
+#[derive(Deserialize, Type)]
+struct ExportReportInput {
+  year: i32,
+  format: ReportFormat,
+}
+
+#[derive(Serialize, Type)]
+struct TopEntry {
+  // None for genres
+  id: Option<String>,
+  name: String,
+  // The artist of songs and albums
+  artist: Option<String>,
+  plays: u32,
+  listened: u32,
+}
+
+#[derive(Serialize, Type)]
+struct DayListening {
+  date: String,
+  listened: u32,
+}
+
+#[derive(Serialize, Type)]
+struct Discovery {
+  new_songs: u32,
+  new_albums: u32,
+  new_artists: u32,
+  // Share of the songs played in the period that were played for the first time
+  new_song_ratio: f64,
+}
+
+#[derive(Serialize, Type)]
+struct Stats {
+  plays: u32,
+  skips: u32,
+  listened: u32,
+  top_songs: Vec<TopEntry>,
+  top_albums: Vec<TopEntry>,
+  top_artists: Vec<TopEntry>,
+  top_genres: Vec<TopEntry>,
+  listening_per_day: Vec<DayListening>,
+  // 168 values in seconds, starting on Monday at midnight
+  listening_per_hour_of_week: Vec<u32>,
+  longest_streak: u32,
+  current_streak: u32,
+  discovery: Discovery,
+}
+
+#[derive(Serialize, Type)]
+struct YearReport {
+  year: i32,
+  generated_at: String,
+  stats: Stats,
+}
+
+#[derive(Default)]
+struct Counter {
+  plays: u32,
+  listened: u32,
+}
+
+fn to_local(timestamp: u32) -> DateTime<Local> {
+  Local.timestamp_opt(timestamp as i64, 0).unwrap()
+}
+
+fn get_top_entries(
+  counters: HashMap<String, Counter>,
+  limit: u32,
+  describe: impl Fn(&str) -> (Option<String>, String, Option<String>),
+) -> Vec<TopEntry> {
+  let mut counters: Vec<(String, Counter)> = counters.into_iter().collect();
+  counters.sort_by(|(a_key, a), (b_key, b)| {
+    (b.plays, b.listened, a_key).cmp(&(a.plays, a.listened, b_key))
+  });
+  counters
+    .into_iter()
+    .take(limit as usize)
+    .map(|(key, counter)| {
+      let (id, name, artist) = describe(&key);
+      TopEntry {
+        id,
+        name,
+        artist,
+        plays: counter.plays,
+        listened: counter.listened,
+      }
+    })
+    .collect()
+}
+
+// Streaks are runs of consecutive days with at least one play
+fn get_streaks(days: &HashSet<NaiveDate>, today: NaiveDate) -> (u32, u32) {
+  let mut sorted_days: Vec<&NaiveDate> = days.iter().collect();
+  sorted_days.sort();
+  let mut longest_streak = 0;
+  let mut streak = 0;
+  let mut previous_day: Option<&NaiveDate> = None;
+  for day in sorted_days {
+    streak = match previous_day {
+      Some(previous_day) if previous_day.succ_opt() == Some(*day) => streak + 1,
+      _ => 1,
+    };
+    longest_streak = longest_streak.max(streak);
+    previous_day = Some(day);
+  }
+  // A streak isn't broken until the end of the day after the last play
+  let current_streak = match previous_day {
+    Some(day) if *day == today || day.succ_opt() == Some(today) => streak,
+    _ => 0,
+  };
+  (longest_streak, current_streak)
+}
+
+fn get_stats(library: &Library, from: Option<u32>, to: Option<u32>, limit: u32) -> Stats {
+  let history = &library.history;
+  let song_ids: HashMap<&PathBuf, &String> = library
+    .songs
+    .iter()
+    .map(|(id, song)| (&song.path, id))
+    .collect();
+  let is_in_period = |event: &HistoryEvent| {
+    from.map_or(true, |from| event.timestamp >= from) && to.map_or(true, |to| event.timestamp < to)
+  };
+
+  // Discovery compares the period with everything played before it
+  let mut known_songs = HashSet::new();
+  let mut known_albums = HashSet::new();
+  let mut known_artists = HashSet::new();
+  let mut played_songs = HashSet::new();
+  let mut new_songs = 0;
+  let mut new_albums = 0;
+  let mut new_artists = 0;
+
+  let mut plays = 0;
+  let mut skips = 0;
+  let mut listened = 0;
+  let mut songs: HashMap<String, Counter> = HashMap::new();
+  let mut albums: HashMap<String, Counter> = HashMap::new();
+  let mut artists: HashMap<String, Counter> = HashMap::new();
+  let mut genres: HashMap<String, Counter> = HashMap::new();
+  let mut listening_per_day: BTreeMap<NaiveDate, u32> = BTreeMap::new();
+  let mut listening_per_hour_of_week = vec![0; 168];
+  let mut days_with_plays = HashSet::new();
+
+  for event in history.iter() {
+    let song_id = song_ids.get(&event.path);
+    let song = song_id.and_then(|song_id| library.songs.get(*song_id));
+    let album_id = song.map(|song| &song.album);
+    let artist_id = album_id
+      .and_then(|album_id| library.albums.get(album_id))
+      .map(|album| &album.artist);
+    let is_play = event.kind == HistoryEventKind::Play;
+    if !is_in_period(event) {
+      if is_play && to.map_or(true, |to| event.timestamp < to) {
+        known_songs.insert(&event.path);
+        known_albums.extend(album_id);
+        known_artists.extend(artist_id);
+      }
+      continue;
+    }
+
+    let time = to_local(event.timestamp);
+    listened += event.listened;
+    *listening_per_day.entry(time.date_naive()).or_default() += event.listened;
+    let hour_of_week = time.weekday().num_days_from_monday() * 24 + time.hour();
+    listening_per_hour_of_week[hour_of_week as usize] += event.listened;
+    if !is_play {
+      skips += 1;
+      continue;
+    }
+    plays += 1;
+    days_with_plays.insert(time.date_naive());
+    played_songs.insert(&event.path);
+    if known_songs.insert(&event.path) {
+      new_songs += 1;
+    }
+    if let Some(album_id) = album_id {
+      if known_albums.insert(album_id) {
+        new_albums += 1;
+      }
+    }
+    if let Some(artist_id) = artist_id {
+      if known_artists.insert(artist_id) {
+        new_artists += 1;
+      }
+    }
+
+    // Songs that left the library still count towards the totals, but can't be ranked
+    let count = |counters: &mut HashMap<String, Counter>, key: &str| {
+      let counter = counters.entry(key.to_string()).or_default();
+      counter.plays += 1;
+      counter.listened += event.listened;
+    };
+    if let Some(song_id) = song_id {
+      count(&mut songs, song_id);
+    }
+    if let Some(album_id) = album_id {
+      count(&mut albums, album_id);
+    }
+    if let Some(artist_id) = artist_id {
+      count(&mut artists, artist_id);
+    }
+    if let Some(genre) = song.and_then(|song| song.genre.as_deref()) {
+      for genre in split_genres(genre) {
+        count(&mut genres, &genre);
+      }
+    }
+  }
+
+  let get_artist_name = |artist_id: &str| {
+    library
+      .artists
+      .get(artist_id)
+      .map(|artist| artist.name.clone())
+  };
+  let (longest_streak, current_streak) = get_streaks(&days_with_plays, Local::now().date_naive());
+  Stats {
+    plays,
+    skips,
+    listened,
+    top_songs: get_top_entries(songs, limit, |song_id| {
+      let song = library.songs.get(song_id).unwrap();
+      let album = library.albums.get(&song.album).unwrap();
+      (
+        Some(song_id.to_string()),
+        song.title.clone(),
+        get_artist_name(&album.artist),
+      )
+    }),
+    top_albums: get_top_entries(albums, limit, |album_id| {
+      let album = library.albums.get(album_id).unwrap();
+      (
+        Some(album_id.to_string()),
+        album.name.clone(),
+        get_artist_name(&album.artist),
+      )
+    }),
+    top_artists: get_top_entries(artists, limit, |artist_id| {
+      (
+        Some(artist_id.to_string()),
+        get_artist_name(artist_id).unwrap(),
+        None,
+      )
+    }),
+    top_genres: get_top_entries(genres, limit, |genre| (None, genre.to_string(), None)),
+    listening_per_day: listening_per_day
+      .into_iter()
+      .map(|(date, listened)| DayListening {
+        date: date.to_string(),
+        listened,
+      })
+      .collect(),
+    listening_per_hour_of_week,
+    longest_streak,
+    current_streak,
+    discovery: Discovery {
+      new_songs,
+      new_albums,
+      new_artists,
+      new_song_ratio: match played_songs.len() {
+        0 => 0.0,
+        played_songs => new_songs as f64 / played_songs as f64,
+      },
+    },
+  }
+}
+
+fn get_year_report(library: &Library, year: i32) -> YearReport {
+  let start_of_year = |year: i32| {
+    Local
+      .with_ymd_and_hms(year, 1, 1, 0, 0, 0)
+      .earliest()
+      .map(|time| time.timestamp().max(0) as u32)
+  };
+  YearReport {
+    year,
+    generated_at: Local::now().to_rfc3339(),
+    stats: get_stats(library, start_of_year(year), start_of_year(year + 1), 10),
+  }
+}
+
+fn escape_html(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+fn format_duration(seconds: u32) -> String {
+  match seconds {
+    0..=3599 => format!("{} min", seconds / 60),
+    _ => format!("{} h {} min", seconds / 3600, seconds / 60 % 60),
+  }
+}
+
+fn render_top_list(title: &str, entries: &[TopEntry]) -> String {
+  let items: String = entries
+    .iter()
+    .map(|entry| {
+      let artist = entry
+        .artist
+        .as_ref()
+        .map(|artist| format!(" <span>{}</span>", escape_html(artist)))
+        .unwrap_or_default();
+      format!(
+        "<li><b>{}</b>{}<small>{} plays</small></li>",
+        escape_html(&entry.name),
+        artist,
+        entry.plays
+      )
+    })
+    .collect();
+  format!("<section><h2>{}</h2><ol>{}</ol></section>", title, items)
+}
+
+// Everything is inlined so the file can be shared on its own
+fn render_html_report(report: &YearReport) -> String {
+  let stats = &report.stats;
+  let busiest_hour = stats
+    .listening_per_hour_of_week
+    .iter()
+    .copied()
+    .max()
+    .unwrap_or(0)
+    .max(1);
+  let days = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+  let heatmap: String = days
+    .iter()
+    .enumerate()
+    .map(|(day, name)| {
+      let cells: String = stats.listening_per_hour_of_week[day * 24..(day + 1) * 24]
+        .iter()
+        .map(|listened| {
+          format!(
+            "<td style=\"opacity:{:.2}\" title=\"{}\"></td>",
+            0.08 + 0.92 * *listened as f64 / busiest_hour as f64,
+            format_duration(*listened)
+          )
+        })
+        .collect();
+      format!("<tr><th>{}</th>{}</tr>", name, cells)
+    })
+    .collect();
+  format!(
+    r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{year} in music</title>
+<style>
+body {{ font-family: system-ui, sans-serif; background: #18181b; color: #f4f4f5; max-width: 48rem; margin: 0 auto; padding: 2rem 1rem; }}
+h1 {{ font-size: 2.5rem; margin: 0 0 2rem; }}
+.totals {{ display: grid; grid-template-columns: repeat(auto-fit, minmax(10rem, 1fr)); gap: 1rem; }}
+.totals div {{ background: #27272a; border-radius: 0.75rem; padding: 1rem; }}
+.totals b {{ display: block; font-size: 1.5rem; color: #34d399; }}
+.lists {{ display: grid; grid-template-columns: repeat(auto-fit, minmax(20rem, 1fr)); gap: 1rem; }}
+li {{ margin-bottom: 0.5rem; }}
+li span, li small {{ display: block; color: #a1a1aa; }}
+table {{ border-spacing: 2px; width: 100%; }}
+td {{ background: #34d399; height: 1rem; border-radius: 2px; }}
+th {{ text-align: left; font-weight: normal; color: #a1a1aa; width: 3rem; }}
+footer {{ margin-top: 2rem; color: #71717a; font-size: 0.875rem; }}
+</style>
+</head>
+<body>
+<h1>{year} in music</h1>
+<div class="totals">
+<div><b>{listened}</b>listened</div>
+<div><b>{plays}</b>plays</div>
+<div><b>{new_songs}</b>new songs</div>
+<div><b>{new_artists}</b>new artists</div>
+<div><b>{longest_streak} days</b>longest streak</div>
+</div>
+<div class="lists">
+{top_songs}
+{top_albums}
+{top_artists}
+{top_genres}
+</div>
+<section><h2>When you listened</h2><table>{heatmap}</table></section>
+<footer>Made with Ronix on {generated_at}</footer>
+</body>
+</html>
+"#,
+    year = report.year,
+    listened = format_duration(stats.listened),
+    plays = stats.plays,
+    new_songs = stats.discovery.new_songs,
+    new_artists = stats.discovery.new_artists,
+    longest_streak = stats.longest_streak,
+    top_songs = render_top_list("Top songs", &stats.top_songs),
+    top_albums = render_top_list("Top albums", &stats.top_albums),
+    top_artists = render_top_list("Top artists", &stats.top_artists),
+    top_genres = render_top_list("Top genres", &stats.top_genres),
+    heatmap = heatmap,
+    generated_at = &report.generated_at[..10],
+  )
+}
+
+pub fn get_router() -> RouterBuilder<Context> {
+  Router::<Context>::new()
+    .query("get", |t| {
+      t(|ctx, input: StatsInput| {
+        get_stats(
+          &ctx.library.lock().unwrap(),
+          input.from,
+          input.to,
+          input.limit,
+        )
+      })
+    })
+    .query("yearReport", |t| {
+      t(|ctx, input: i32| get_year_report(&ctx.library.lock().unwrap(), input))
+    })
+    .mutation("exportYearReport", |t| {
+      t(|ctx, input: ExportReportInput| async move {
+        // A headless daemon has no display to show the dialog on
+        if ctx.daemon_url.is_some() {
+          return "Reports can only be exported from the window".to_string();
+        }
+        let (extension, filter) = match input.format {
+          ReportFormat::Json => ("json", "JSON"),
+          ReportFormat::Html => ("html", "HTML"),
+        };
+        let path = match FileDialogBuilder::new()
+          .set_file_name(&format!("ronix-{}.{}", input.year, extension))
+          .add_filter(filter, &[extension])
+          .save_file()
+        {
+          Some(path) => path,
+          None => return "Export cancelled".to_string(),
+        };
+        let report = get_year_report(&ctx.library.lock().unwrap(), input.year);
+        let contents = match input.format {
+          ReportFormat::Json => serde_json::to_string_pretty(&report).unwrap(),
+          ReportFormat::Html => render_html_report(&report),
+        };
+        match fs::write(&path, contents) {
+          Ok(_) => format!("Saved to {}", path.display()),
+          Err(e) => format!("Failed to save the report: {}", e),
+        }
+      })
+    })
+}
@@ -0,0 +1,1028 @@
+use crate::{
+  config::SubsonicConfig,
+  covers::get_original_cover,
+  get_current_time,
+  history::{self, HistoryEventKind, HistoryScope},
+  library::{get_scope_songs, Album, Library, Song},
+  playlist::{create_playlist, write_playlists},
+  search::fold,
+  Context, PlayerScope,
+};
+use async_stream::stream;
+use axum::{
+  body::{Bytes, StreamBody},
+  extract::{Path, Query, State},
+  http::{header, HeaderMap, Method, StatusCode},
+  response::{IntoResponse, Response},
+  routing::get,
+  Form,
+};
+use rand::prelude::*;
+use serde_json::{json, Map, Value};
+use std::{
+  collections::{BTreeMap, HashMap, HashSet},
+  fs,
+  io::{self, SeekFrom},
+  net::SocketAddr,
+  path::Path as FilePath,
+  process::Stdio,
+  str::FromStr,
+};
+use tokio::{
+  io::{AsyncReadExt, AsyncSeekExt},
+  process::Command,
+};
+
+const API_VERSION: &str = "1.16.1";
+const IGNORED_ARTICLES: &[&str] = &["The", "An", "A"];
+
+const ERROR_GENERIC: u32 = 0;
+const ERROR_MISSING_PARAMETER: u32 = 10;
+const ERROR_WRONG_CREDENTIALS: u32 = 40;
+const ERROR_NOT_FOUND: u32 = 70;
+
+struct ApiError {
+  code: u32,
+  message: String,
+}
+
+impl ApiError {
+  fn new(code: u32, message: impl Into<String>) -> Self {
+    ApiError {
+      code,
+      message: message.into(),
+    }
+  }
+
+  fn not_found(what: &str) -> Self {
+    ApiError::new(ERROR_NOT_FOUND, format!("{} not found", what))
+  }
+}
+
+enum Reply {
+  Data(Value),
+  Raw(Response),
+}
+
+struct Params(Vec<(String, String)>);
+
+impl Params {
+  fn get(&self, key: &str) -> Option<&str> {
+    self
+      .0
+      .iter()
+      .find(|(k, _)| k == key)
+      .map(|(_, value)| value.as_str())
+  }
+
+  fn get_all(&self, key: &str) -> Vec<&str> {
+    self
+      .0
+      .iter()
+      .filter(|(k, _)| k == key)
+      .map(|(_, value)| value.as_str())
+      .collect()
+  }
+
+  fn require(&self, key: &str) -> Result<&str, ApiError> {
+    self.get(key).ok_or_else(|| {
+      ApiError::new(
+        ERROR_MISSING_PARAMETER,
+        format!("Required parameter is missing: {}", key),
+      )
+    })
+  }
+
+  fn number<T: FromStr>(&self, key: &str, default: T) -> T {
+    self
+      .get(key)
+      .and_then(|value| value.parse().ok())
+      .unwrap_or(default)
+  }
+}
+
+fn decode_hex(hex: &str) -> Option<String> {
+  let bytes = (0..hex.len())
+    .step_by(2)
+    .map(|i| {
+      hex
+        .get(i..i + 2)
+        .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+    })
+    .collect::<Option<Vec<u8>>>()?;
+  String::from_utf8(bytes).ok()
+}
+
+fn is_authorized(config: &SubsonicConfig, params: &Params) -> bool {
+  if config.username.is_empty() || params.get("u") != Some(config.username.as_str()) {
+    return false;
+  }
+  if let (Some(token), Some(salt)) = (params.get("t"), params.get("s")) {
+    let expected = format!("{:x}", md5::compute(format!("{}{}", config.password, salt)));
+    return token.eq_ignore_ascii_case(&expected);
+  }
+  match params.get("p") {
+    Some(password) => match password.strip_prefix("enc:") {
+      Some(hex) => decode_hex(hex).as_deref() == Some(config.password.as_str()),
+      None => password == config.password,
+    },
+    None => false,
+  }
+}
+
+fn get_content_type(path: &FilePath) -> &'static str {
+  let extension = path
+    .extension()
+    .map(|extension| extension.to_string_lossy().to_lowercase());
+  match extension.as_deref() {
+    Some("mp3") => "audio/mpeg",
+    Some("flac") => "audio/flac",
+    Some("ogg") | Some("opus") | Some("oga") => "audio/ogg",
+    Some("m4a") | Some("aac") | Some("mp4") => "audio/mp4",
+    Some("wav") => "audio/wav",
+    Some("jpg") | Some("jpeg") => "image/jpeg",
+    Some("png") => "image/png",
+    Some("webp") => "image/webp",
+    _ => "application/octet-stream",
+  }
+}
+
+fn get_suffix(path: &FilePath) -> String {
+  path
+    .extension()
+    .map(|extension| extension.to_string_lossy().to_lowercase())
+    .unwrap_or_default()
+}
+
+fn get_album_songs<'a>(library: &'a Library, album_id: &str) -> Vec<(&'a String, &'a Song)> {
+  let mut songs: Vec<(&String, &Song)> = library
+    .songs
+    .iter()
+    .filter(|(_, song)| song.album == album_id)
+    .collect();
+  songs.sort_by(|(_, a), (_, b)| a.path.cmp(&b.path));
+  songs
+}
+
+fn get_artist_albums<'a>(library: &'a Library, artist_id: &str) -> Vec<(&'a String, &'a Album)> {
+  let mut albums: Vec<(&String, &Album)> = library
+    .albums
+    .iter()
+    .filter(|(_, album)| album.artist == artist_id)
+    .collect();
+  albums.sort_by(|(_, a), (_, b)| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+  albums
+}
+
+fn song_to_json(song_id: &str, library: &Library) -> Option<Value> {
+  let song = library.songs.get(song_id)?;
+  let album = library.albums.get(&song.album)?;
+  let artist = library.artists.get(&album.artist)?;
+  let mut value = json!({
+    "id": song_id,
+    "parent": song.album,
+    "isDir": false,
+    "title": song.title,
+    "album": album.name,
+    "artist": artist.name,
+    "albumId": song.album,
+    "artistId": album.artist,
+    "duration": song.duration,
+    "playCount": song.play_count,
+    "size": fs::metadata(&song.path).map(|metadata| metadata.len()).unwrap_or(0),
+    "suffix": get_suffix(&song.path),
+    "contentType": get_content_type(&song.path),
+    "path": song.path.to_string_lossy(),
+    "type": "music",
+    "mediaType": "song",
+  });
+  if album.cover_art.is_some() {
+    value["coverArt"] = json!(song.album);
+  }
+  if let Some(year) = song.year {
+    value["year"] = json!(year);
+  }
+  if let Some(genre) = &song.genre {
+    value["genre"] = json!(genre);
+  }
+  if song.rating > 0 {
+    value["userRating"] = json!(song.rating);
+  }
+  Some(value)
+}
+
+fn album_to_json(album_id: &str, library: &Library) -> Option<Value> {
+  let album = library.albums.get(album_id)?;
+  let artist = library.artists.get(&album.artist)?;
+  let songs = get_album_songs(library, album_id);
+  let mut value = json!({
+    "id": album_id,
+    "name": album.name,
+    "title": album.name,
+    "album": album.name,
+    "artist": artist.name,
+    "artistId": album.artist,
+    "isDir": true,
+    "songCount": songs.len(),
+    "duration": songs.iter().map(|(_, song)| song.duration).sum::<u32>(),
+    "playCount": album.play_count,
+  });
+  if album.cover_art.is_some() {
+    value["coverArt"] = json!(album_id);
+  }
+  Some(value)
+}
+
+fn artist_to_json(artist_id: &str, library: &Library) -> Option<Value> {
+  let artist = library.artists.get(artist_id)?;
+  let albums = get_artist_albums(library, artist_id);
+  let mut value = json!({
+    "id": artist_id,
+    "name": artist.name,
+    "albumCount": albums.len(),
+  });
+  if let Some((album_id, _)) = albums.iter().find(|(_, album)| album.cover_art.is_some()) {
+    value["coverArt"] = json!(album_id);
+  }
+  Some(value)
+}
+
+fn playlist_to_json(
+  playlist_id: &str,
+  library: &Library,
+  owner: &str,
+  with_songs: bool,
+) -> Option<Value> {
+  let playlist = library.playlists.get(playlist_id)?;
+  let mut value = json!({
+    "id": playlist_id,
+    "name": playlist.name,
+    "owner": owner,
+    "public": false,
+    "songCount": playlist.songs.len(),
+    "duration": playlist
+      .songs
+      .iter()
+      .filter_map(|song_id| library.songs.get(song_id))
+      .map(|song| song.duration)
+      .sum::<u32>(),
+  });
+  if with_songs {
+    value["entry"] = playlist
+      .songs
+      .iter()
+      .filter_map(|song_id| song_to_json(song_id, library))
+      .collect();
+  }
+  Some(value)
+}
+
+fn get_index_name(name: &str) -> String {
+  let name = IGNORED_ARTICLES
+    .iter()
+    .find_map(|article| name.strip_prefix(&format!("{} ", article)))
+    .unwrap_or(name);
+  match name.chars().next() {
+    Some(c) if c.is_alphabetic() => c.to_uppercase().to_string(),
+    _ => "#".to_string(),
+  }
+}
+
+fn get_artist_index(library: &Library) -> Vec<Value> {
+  let mut index: BTreeMap<String, Vec<(String, Value)>> = BTreeMap::new();
+  for artist_id in library.artists.keys() {
+    let artist = artist_to_json(artist_id, library).unwrap();
+    let name = library.artists.get(artist_id).unwrap().name.clone();
+    index
+      .entry(get_index_name(&name))
+      .or_default()
+      .push((name.to_lowercase(), artist));
+  }
+  index
+    .into_iter()
+    .map(|(name, mut artists)| {
+      artists.sort_by(|(a, _), (b, _)| a.cmp(b));
+      json!({
+        "name": name,
+        "artist": artists.into_iter().map(|(_, artist)| artist).collect::<Vec<Value>>(),
+      })
+    })
+    .collect()
+}
+
+fn paginate<T>(
+  items: Vec<T>,
+  params: &Params,
+  count_key: &str,
+  offset_key: &str,
+  default_count: usize,
+) -> Vec<T> {
+  items
+    .into_iter()
+    .skip(params.number(offset_key, 0))
+    .take(params.number(count_key, default_count))
+    .collect()
+}
+
+fn search(library: &Library, params: &Params) -> Value {
+  // Clients sync the whole library by searching for an empty query
+  let query = params
+    .get("query")
+    .unwrap_or("")
+    .trim_matches('"')
+    .to_lowercase();
+  let matches = |text: &str| query.is_empty() || text.to_lowercase().contains(&query);
+  let mut artists: Vec<(&String, &String)> = library
+    .artists
+    .iter()
+    .filter(|(_, artist)| matches(&artist.name))
+    .map(|(id, artist)| (&artist.name, id))
+    .collect();
+  artists.sort();
+  let mut albums: Vec<(&String, &String)> = library
+    .albums
+    .iter()
+    .filter(|(_, album)| matches(&album.name))
+    .map(|(id, album)| (&album.name, id))
+    .collect();
+  albums.sort();
+  let mut songs: Vec<(&String, &String)> = library
+    .songs
+    .iter()
+    .filter(|(_, song)| matches(&song.title))
+    .map(|(id, song)| (&song.title, id))
+    .collect();
+  songs.sort();
+  json!({
+    "artist": paginate(artists, params, "artistCount", "artistOffset", 20)
+      .into_iter()
+      .filter_map(|(_, id)| artist_to_json(id, library))
+      .collect::<Vec<Value>>(),
+    "album": paginate(albums, params, "albumCount", "albumOffset", 20)
+      .into_iter()
+      .filter_map(|(_, id)| album_to_json(id, library))
+      .collect::<Vec<Value>>(),
+    "song": paginate(songs, params, "songCount", "songOffset", 20)
+      .into_iter()
+      .filter_map(|(_, id)| song_to_json(id, library))
+      .collect::<Vec<Value>>(),
+  })
+}
+
+fn get_album_list(library: &Library, params: &Params) -> Result<Vec<Value>, ApiError> {
+  let mut albums: Vec<&String> = library.albums.keys().collect();
+  match params.require("type")? {
+    "random" => albums.shuffle(&mut thread_rng()),
+    "alphabeticalByName" => {
+      albums.sort_by_key(|album_id| library.albums.get(*album_id).unwrap().name.to_lowercase())
+    }
+    "alphabeticalByArtist" => albums.sort_by_key(|album_id| {
+      let album = library.albums.get(*album_id).unwrap();
+      (
+        library
+          .artists
+          .get(&album.artist)
+          .unwrap()
+          .name
+          .to_lowercase(),
+        album.name.to_lowercase(),
+      )
+    }),
+    "newest" => {
+      let mut added: HashMap<&String, u32> = HashMap::new();
+      for song in library.songs.values() {
+        let album_added = added.entry(&song.album).or_default();
+        *album_added = (*album_added).max(song.added_at);
+      }
+      albums.sort_by_key(|album_id| std::cmp::Reverse(added.get(*album_id).copied()));
+    }
+    "frequent" => {
+      albums.retain(|album_id| library.albums.get(*album_id).unwrap().play_count > 0);
+      albums.sort_by_key(|album_id| {
+        std::cmp::Reverse(library.albums.get(*album_id).unwrap().play_count)
+      });
+    }
+    "recent" => {
+      albums.retain(|album_id| library.albums.get(*album_id).unwrap().last_played.is_some());
+      albums.sort_by_key(|album_id| {
+        std::cmp::Reverse(library.albums.get(*album_id).unwrap().last_played)
+      });
+    }
+    // Albums are from the year of their earliest song
+    "byYear" => {
+      let from_year: u32 = params.number("fromYear", 0);
+      let to_year: u32 = params.number("toYear", u32::MAX);
+      let mut years: HashMap<&String, u32> = HashMap::new();
+      for song in library.songs.values() {
+        if let Some(year) = song.year {
+          let album_year = years.entry(&song.album).or_insert(year);
+          *album_year = (*album_year).min(year);
+        }
+      }
+      albums.retain(|album_id| {
+        years.get(album_id).map_or(false, |year| {
+          *year >= from_year.min(to_year) && *year <= from_year.max(to_year)
+        })
+      });
+      albums.sort_by_key(|album_id| years.get(album_id));
+      // A range from a later year to an earlier one is listed newest first
+      if from_year > to_year {
+        albums.reverse();
+      }
+    }
+    "byGenre" => {
+      let genre = PlayerScope::Genre(params.require("genre")?.to_string());
+      let genre_albums: HashSet<&String> = get_scope_songs(library, &genre)
+        .into_iter()
+        .map(|song_id| &library.songs.get(song_id).unwrap().album)
+        .collect();
+      albums.retain(|album_id| genre_albums.contains(album_id));
+      albums.sort_by_key(|album_id| library.albums.get(*album_id).unwrap().name.to_lowercase());
+    }
+    // By the average of the album's rated songs
+    "highest" => {
+      // The sum and count of each album's ratings
+      let mut ratings: HashMap<&String, (u32, u32)> = HashMap::new();
+      for song in library.songs.values().filter(|song| song.rating > 0) {
+        let album_ratings = ratings.entry(&song.album).or_default();
+        album_ratings.0 += song.rating as u32 * 100;
+        album_ratings.1 += 1;
+      }
+      let get_rating =
+        |album_id: &String| ratings.get(album_id).map_or(0, |(sum, count)| sum / count);
+      albums.retain(|album_id| get_rating(album_id) > 0);
+      albums.sort_by_key(|album_id| std::cmp::Reverse(get_rating(album_id)));
+    }
+    // Only songs can be favorites, not albums
+    _ => albums.clear(),
+  }
+  Ok(
+    albums
+      .into_iter()
+      .skip(params.number("offset", 0))
+      .take(params.number("size", 10))
+      .filter_map(|album_id| album_to_json(album_id, library))
+      .collect(),
+  )
+}
+
+fn read_file(path: &FilePath, headers: &HeaderMap) -> io::Result<Response> {
+  let size = fs::metadata(path)?.len();
+  // Only single ranges are supported, which is all players use for seeking
+  let range = headers
+    .get(header::RANGE)
+    .and_then(|range| range.to_str().ok())
+    .and_then(|range| range.strip_prefix("bytes="))
+    .and_then(|range| range.split_once('-'))
+    .and_then(|(start, end)| {
+      let start: u64 = start.parse().ok()?;
+      let end = end.parse().unwrap_or(u64::MAX).min(size.saturating_sub(1));
+      (start <= end).then(|| (start, end))
+    });
+  let (start, end) = range.unwrap_or((0, size.saturating_sub(1)));
+  let length = if size == 0 { 0 } else { end + 1 - start };
+  let file_path = path.to_path_buf();
+  // Sent in chunks as the client reads them, so whole albums aren't held in memory
+  let body = StreamBody::new(stream! {
+    let mut file = match tokio::fs::File::open(&file_path).await {
+      Ok(file) => file,
+      Err(e) => {
+        yield Err(e);
+        return;
+      }
+    };
+    if let Err(e) = file.seek(SeekFrom::Start(start)).await {
+      yield Err(e);
+      return;
+    }
+    let mut file = file.take(length);
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+      match file.read(&mut buffer).await {
+        Ok(0) => break,
+        Ok(n) => yield Ok::<Bytes, io::Error>(Bytes::copy_from_slice(&buffer[..n])),
+        Err(e) => {
+          yield Err(e);
+          break;
+        }
+      }
+    }
+  });
+  let mut response = (
+    [
+      (header::CONTENT_TYPE, get_content_type(path).to_string()),
+      (header::ACCEPT_RANGES, "bytes".to_string()),
+      (header::CONTENT_LENGTH, length.to_string()),
+    ],
+    body,
+  )
+    .into_response();
+  if range.is_some() {
+    *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+    response.headers_mut().insert(
+      header::CONTENT_RANGE,
+      format!("bytes {}-{}/{}", start, end, size).parse().unwrap(),
+    );
+  }
+  Ok(response)
+}
+
+fn transcode(path: &FilePath, format: &str, bit_rate: u32) -> io::Result<Response> {
+  let (codec, container, content_type) = match format {
+    "opus" => ("libopus", "ogg", "audio/ogg"),
+    "ogg" => ("libvorbis", "ogg", "audio/ogg"),
+    _ => ("libmp3lame", "mp3", "audio/mpeg"),
+  };
+  let mut ffmpeg = Command::new("ffmpeg")
+    .args(["-v", "quiet", "-i"])
+    .arg(path)
+    .args([
+      "-map",
+      "0:a:0",
+      "-c:a",
+      codec,
+      "-b:a",
+      &format!("{}k", bit_rate),
+      "-f",
+      container,
+      "-",
+    ])
+    .stdin(Stdio::null())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::null())
+    .kill_on_drop(true)
+    .spawn()?;
+  let mut stdout = ffmpeg.stdout.take().unwrap();
+  let body = StreamBody::new(stream! {
+    // ffmpeg gets killed once the client stops listening and the stream is dropped
+    let _ffmpeg = ffmpeg;
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+      match stdout.read(&mut buffer).await {
+        Ok(0) => break,
+        Ok(n) => yield Ok::<Bytes, io::Error>(Bytes::copy_from_slice(&buffer[..n])),
+        Err(e) => {
+          yield Err(e);
+          break;
+        }
+      }
+    }
+  });
+  Ok(([(header::CONTENT_TYPE, content_type)], body).into_response())
+}
+
+fn stream_song(
+  ctx: &Context,
+  params: &Params,
+  headers: &HeaderMap,
+  allow_transcoding: bool,
+) -> Result<Reply, ApiError> {
+  let path = ctx
+    .library
+    .lock()
+    .unwrap()
+    .songs
+    .get(params.require("id")?)
+    .map(|song| song.path.clone())
+    .ok_or_else(|| ApiError::not_found("Song"))?;
+  let format = params.get("format").filter(|format| *format != "raw");
+  let max_bit_rate: u32 = params.number("maxBitRate", 0);
+  let response = match allow_transcoding && (format.is_some() || max_bit_rate > 0) {
+    true => transcode(
+      &path,
+      format.unwrap_or("mp3"),
+      match max_bit_rate {
+        0 => 192,
+        max_bit_rate => max_bit_rate,
+      },
+    ),
+    false => read_file(&path, headers),
+  };
+  response
+    .map(Reply::Raw)
+    .map_err(|e| ApiError::new(ERROR_GENERIC, e.to_string()))
+}
+
+fn handle_method(
+  ctx: &Context,
+  method: &str,
+  params: &Params,
+  headers: &HeaderMap,
+) -> Result<Reply, ApiError> {
+  let (config, music_folders) = {
+    let config = ctx.config.lock().unwrap();
+    (config.subsonic.clone(), config.music_folders.clone())
+  };
+  if !is_authorized(&config, params) {
+    return Err(ApiError::new(
+      ERROR_WRONG_CREDENTIALS,
+      "Wrong username or password",
+    ));
+  }
+  match method {
+    "stream" => return stream_song(ctx, params, headers, config.transcoding),
+    "download" => return stream_song(ctx, params, headers, false),
+    _ => {}
+  }
+  let mut library = ctx.library.lock().unwrap();
+  Ok(Reply::Data(match method {
+    "ping" => json!({}),
+    "getLicense" => json!({ "license": { "valid": true } }),
+    "getOpenSubsonicExtensions" => json!({ "openSubsonicExtensions": [] }),
+    "getMusicFolders" => json!({
+      "musicFolders": {
+        "musicFolder": music_folders
+          .iter()
+          .enumerate()
+          .map(|(i, folder)| json!({
+            "id": i,
+            "name": folder.file_name().unwrap_or_default().to_string_lossy(),
+          }))
+          .collect::<Vec<Value>>(),
+      }
+    }),
+    "getUser" => json!({
+      "user": {
+        "username": config.username,
+        "scrobblingEnabled": true,
+        "adminRole": false,
+        "settingsRole": false,
+        "downloadRole": true,
+        "uploadRole": false,
+        "playlistRole": true,
+        "coverArtRole": true,
+        "commentRole": false,
+        "podcastRole": false,
+        "streamRole": true,
+        "jukeboxRole": false,
+        "shareRole": false,
+        "folder": [0],
+      }
+    }),
+    "getArtists" => json!({
+      "artists": {
+        "ignoredArticles": IGNORED_ARTICLES.join(" "),
+        "index": get_artist_index(&library),
+      }
+    }),
+    "getIndexes" => json!({
+      "indexes": {
+        "ignoredArticles": IGNORED_ARTICLES.join(" "),
+        "lastModified": 0,
+        "index": get_artist_index(&library),
+      }
+    }),
+    "getArtist" => {
+      let artist_id = params.require("id")?;
+      let mut artist =
+        artist_to_json(artist_id, &library).ok_or_else(|| ApiError::not_found("Artist"))?;
+      artist["album"] = get_artist_albums(&library, artist_id)
+        .iter()
+        .filter_map(|(album_id, _)| album_to_json(album_id, &library))
+        .collect();
+      json!({ "artist": artist })
+    }
+    "getAlbum" => {
+      let album_id = params.require("id")?;
+      let mut album =
+        album_to_json(album_id, &library).ok_or_else(|| ApiError::not_found("Album"))?;
+      album["song"] = get_album_songs(&library, album_id)
+        .iter()
+        .filter_map(|(song_id, _)| song_to_json(song_id, &library))
+        .collect();
+      json!({ "album": album })
+    }
+    "getSong" => json!({
+      "song": song_to_json(params.require("id")?, &library).ok_or_else(|| ApiError::not_found("Song"))?,
+    }),
+    "getAlbumList" => json!({ "albumList": { "album": get_album_list(&library, params)? } }),
+    "getAlbumList2" => json!({ "albumList2": { "album": get_album_list(&library, params)? } }),
+    "getRandomSongs" => {
+      let mut song_ids: Vec<&String> = library.songs.keys().collect();
+      song_ids.shuffle(&mut thread_rng());
+      json!({
+        "randomSongs": {
+          "song": song_ids
+            .into_iter()
+            .take(params.number("size", 10))
+            .filter_map(|song_id| song_to_json(song_id, &library))
+            .collect::<Vec<Value>>(),
+        }
+      })
+    }
+    "search2" => json!({ "searchResult2": search(&library, params) }),
+    "search3" => json!({ "searchResult3": search(&library, params) }),
+    "getGenres" => json!({
+      "genres": {
+        "genre": library
+          .scope_index
+          .get_genres()
+          .into_iter()
+          .map(|genre| {
+            let songs = get_scope_songs(&library, &PlayerScope::Genre(genre.name.clone()));
+            let albums: HashSet<&String> = songs
+              .iter()
+              .map(|song_id| &library.songs.get(*song_id).unwrap().album)
+              .collect();
+            json!({
+              "value": genre.name,
+              "songCount": songs.len(),
+              "albumCount": albums.len(),
+            })
+          })
+          .collect::<Vec<Value>>(),
+      }
+    }),
+    "getSongsByGenre" => {
+      let genre = PlayerScope::Genre(params.require("genre")?.to_string());
+      let mut songs: Vec<(String, &String)> = get_scope_songs(&library, &genre)
+        .into_iter()
+        .map(|song_id| (fold(&library.songs.get(song_id).unwrap().title), song_id))
+        .collect();
+      songs.sort();
+      json!({
+        "songsByGenre": {
+          "song": paginate(songs, params, "count", "offset", 10)
+            .into_iter()
+            .filter_map(|(_, song_id)| song_to_json(song_id, &library))
+            .collect::<Vec<Value>>(),
+        }
+      })
+    }
+    "getStarred" => json!({ "starred": {} }),
+    "getStarred2" => json!({ "starred2": {} }),
+    "getCoverArt" => {
+      // Songs share the cover of their album
+      let id = params.require("id")?;
+      let album_id = library
+        .songs
+        .get(id)
+        .map(|song| song.album.as_str())
+        .unwrap_or(id);
+      let cover_art =
+        get_original_cover(&library, album_id).ok_or_else(|| ApiError::not_found("Cover art"))?;
+      return read_file(&cover_art, headers)
+        .map(Reply::Raw)
+        .map_err(|_| ApiError::not_found("Cover art"));
+    }
+    // Now playing notifications aren't recorded, only finished plays
+    "scrobble" => {
+      if params.get("submission") != Some("false") {
+        // The time is in milliseconds
+        let timestamp = params
+          .get("time")
+          .and_then(|time| time.parse::<u64>().ok())
+          .map(|time| (time / 1000) as u32)
+          .unwrap_or_else(get_current_time);
+        for song_id in params.get_all("id") {
+          let duration = match library.songs.get(song_id) {
+            Some(song) => song.duration,
+            None => continue,
+          };
+          history::record(
+            &mut library,
+            song_id,
+            HistoryEventKind::Play,
+            timestamp,
+            duration,
+            HistoryScope::Subsonic,
+          );
+        }
+      }
+      json!({})
+    }
+    "getPlaylists" => {
+      let mut playlists: Vec<(&String, &String)> = library
+        .playlists
+        .iter()
+        .map(|(id, playlist)| (&playlist.name, id))
+        .collect();
+      playlists.sort();
+      json!({
+        "playlists": {
+          "playlist": playlists
+            .into_iter()
+            .filter_map(|(_, id)| playlist_to_json(id, &library, &config.username, false))
+            .collect::<Vec<Value>>(),
+        }
+      })
+    }
+    "getPlaylist" => json!({
+      "playlist": playlist_to_json(params.require("id")?, &library, &config.username, true)
+        .ok_or_else(|| ApiError::not_found("Playlist"))?,
+    }),
+    "createPlaylist" => {
+      let song_ids: Vec<String> = params
+        .get_all("songId")
+        .into_iter()
+        .filter(|song_id| library.songs.contains_key(*song_id))
+        .map(|song_id| song_id.to_string())
+        .collect();
+      // With a playlist id the playlist gets replaced instead
+      let playlist_id = match params.get("playlistId") {
+        Some(playlist_id) => {
+          let playlist = library
+            .playlists
+            .get_mut(playlist_id)
+            .ok_or_else(|| ApiError::not_found("Playlist"))?;
+          playlist.songs = song_ids;
+          if let Some(name) = params.get("name") {
+            playlist.name = name.to_string();
+          }
+          write_playlists(&library);
+          playlist_id.to_string()
+        }
+        None => create_playlist(&mut library, params.require("name")?.to_string(), song_ids),
+      };
+      library.mark_changed();
+      json!({
+        "playlist": playlist_to_json(&playlist_id, &library, &config.username, true),
+      })
+    }
+    "updatePlaylist" => {
+      let song_ids: Vec<String> = params
+        .get_all("songIdToAdd")
+        .into_iter()
+        .filter(|song_id| library.songs.contains_key(*song_id))
+        .map(|song_id| song_id.to_string())
+        .collect();
+      let mut indexes_to_remove: Vec<usize> = params
+        .get_all("songIndexToRemove")
+        .into_iter()
+        .filter_map(|index| index.parse().ok())
+        .collect();
+      indexes_to_remove.sort();
+      let playlist = library
+        .playlists
+        .get_mut(params.require("playlistId")?)
+        .ok_or_else(|| ApiError::not_found("Playlist"))?;
+      if let Some(name) = params.get("name") {
+        playlist.name = name.to_string();
+      }
+      // Removing from the end keeps the earlier indexes valid
+      for index in indexes_to_remove.into_iter().rev() {
+        if index < playlist.songs.len() {
+          playlist.songs.remove(index);
+        }
+      }
+      playlist.songs.extend(song_ids);
+      write_playlists(&library);
+      library.mark_changed();
+      json!({})
+    }
+    "deletePlaylist" => {
+      library
+        .playlists
+        .remove(params.require("id")?)
+        .ok_or_else(|| ApiError::not_found("Playlist"))?;
+      write_playlists(&library);
+      library.mark_changed();
+      json!({})
+    }
+    _ => {
+      return Err(ApiError::new(
+        ERROR_NOT_FOUND,
+        format!("Unknown method: {}", method),
+      ))
+    }
+  }))
+}
+
+fn escape_xml(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace('\'', "&apos;")
+}
+
+fn scalar_to_string(value: &Value) -> Option<String> {
+  match value {
+    Value::String(text) => Some(text.clone()),
+    Value::Number(number) => Some(number.to_string()),
+    Value::Bool(boolean) => Some(boolean.to_string()),
+    _ => None,
+  }
+}
+
+// Subsonic XML uses attributes for fields and repeated elements for lists,
+// which is exactly how the JSON responses are shaped
+fn write_xml(out: &mut String, name: &str, value: &Value, attributes: &str) {
+  match value {
+    Value::Array(items) => {
+      for item in items {
+        write_xml(out, name, item, "");
+      }
+    }
+    Value::Object(fields) => {
+      out.push_str(&format!("<{}{}", name, attributes));
+      for (key, field) in fields {
+        if let Some(text) = scalar_to_string(field) {
+          out.push_str(&format!(" {}=\"{}\"", key, escape_xml(&text)));
+        }
+      }
+      out.push('>');
+      for (key, field) in fields {
+        if scalar_to_string(field).is_none() {
+          write_xml(out, key, field, "");
+        }
+      }
+      out.push_str(&format!("</{}>", name));
+    }
+    Value::Null => {}
+    scalar => out.push_str(&format!(
+      "<{0}>{1}</{0}>",
+      name,
+      escape_xml(&scalar_to_string(scalar).unwrap())
+    )),
+  }
+}
+
+fn to_response(result: Result<Value, ApiError>, format: Option<&str>) -> Response {
+  let mut body = Map::new();
+  body.insert(
+    "status".to_string(),
+    json!(if result.is_ok() { "ok" } else { "failed" }),
+  );
+  body.insert("version".to_string(), json!(API_VERSION));
+  body.insert("type".to_string(), json!("ronix"));
+  body.insert(
+    "serverVersion".to_string(),
+    json!(env!("CARGO_PKG_VERSION")),
+  );
+  body.insert("openSubsonic".to_string(), json!(true));
+  match result {
+    Ok(Value::Object(fields)) => body.extend(fields),
+    Ok(_) => {}
+    Err(e) => {
+      body.insert(
+        "error".to_string(),
+        json!({ "code": e.code, "message": e.message }),
+      );
+    }
+  }
+  match format {
+    Some("json") => (
+      [(header::CONTENT_TYPE, "application/json")],
+      json!({ "subsonic-response": body }).to_string(),
+    )
+      .into_response(),
+    _ => {
+      let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+      write_xml(
+        &mut xml,
+        "subsonic-response",
+        &Value::Object(body),
+        " xmlns=\"http://subsonic.org/restapi\"",
+      );
+      ([(header::CONTENT_TYPE, "text/xml; charset=utf-8")], xml).into_response()
+    }
+  }
+}
+
+async fn handle_request(
+  State(ctx): State<Context>,
+  Path(method): Path<String>,
+  request_method: Method,
+  headers: HeaderMap,
+  Query(query): Query<Vec<(String, String)>>,
+  form: Option<Form<Vec<(String, String)>>>,
+) -> Response {
+  let mut params = query;
+  // For GET requests the form is read from the query string, which is already in params
+  if let Some(Form(form)) = form.filter(|_| request_method == Method::POST) {
+    params.extend(form);
+  }
+  let params = Params(params);
+  let method = method.trim_end_matches(".view");
+  match handle_method(&ctx, method, &params, &headers) {
+    Ok(Reply::Raw(response)) => response,
+    Ok(Reply::Data(data)) => to_response(Ok(data), params.get("f")),
+    Err(e) => to_response(Err(e), params.get("f")),
+  }
+}
+
+// Stops the running Subsonic server and starts it again with the current config
+pub fn start(ctx: &Context) {
+  let mut subsonic_server = ctx.subsonic_server.lock().unwrap();
+  if let Some(server) = subsonic_server.take() {
+    server.abort();
+  }
+  let subsonic = ctx.config.lock().unwrap().subsonic.clone();
+  if !subsonic.enabled {
+    return;
+  }
+  let ctx = ctx.clone();
+  *subsonic_server = Some(tauri::async_runtime::spawn(async move {
+    let app = axum::Router::new()
+      .route("/rest/:method", get(handle_request).post(handle_request))
+      .with_state(ctx);
+    let result = axum::Server::bind(&SocketAddr::from(([0, 0, 0, 0], subsonic.port)))
+      .serve(app.into_make_service())
+      .await;
+    if let Err(e) = result {
+      println!("Failed to serve Subsonic API: {}", e);
+    }
+  }));
+}
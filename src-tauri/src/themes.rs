@@ -0,0 +1,228 @@
+use crate::Context;
+use directories::ProjectDirs;
+use futures_util::StreamExt;
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use std::{
+  error::Error,
+  fs::{self, create_dir_all},
+  path::{Path, PathBuf},
+};
+use zbus::{
+  dbus_proxy,
+  zvariant::{OwnedValue, Value},
+  Connection,
+};
+
+const APPEARANCE_NAMESPACE: &str = "org.freedesktop.appearance";
+const COLOR_SCHEME_KEY: &str = "color-scheme";
+
+// All colors are #rrggbb
+#[derive(Serialize, Deserialize, Clone, Type)]
+pub struct ThemeColors {
+  pub background: String,
+  // Cards, menus and the player bar
+  pub surface: String,
+  pub text: String,
+  pub accent: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Type)]
+pub struct Theme {
+  pub name: String,
+  pub light: ThemeColors,
+  pub dark: ThemeColors,
+}
+
+// The id is the file name, without .json
+#[derive(Serialize, Type)]
+pub struct ThemeFile {
+  id: String,
+  theme: Theme,
+}
+
+#[derive(Deserialize, Type)]
+pub struct SaveThemeInput {
+  // Overwrites this theme, or creates a new one without it
+  id: Option<String>,
+  theme: Theme,
+}
+
+#[derive(Serialize, Type)]
+pub enum SaveThemeResult {
+  // The theme's id
+  Saved(String),
+  // Why it couldn't be saved, to show in the settings
+  Failed(String),
+}
+
+#[dbus_proxy(
+  interface = "org.freedesktop.portal.Settings",
+  default_service = "org.freedesktop.portal.Desktop",
+  default_path = "/org/freedesktop/portal/desktop"
+)]
+trait PortalSettings {
+  fn read(&self, namespace: &str, key: &str) -> zbus::Result<OwnedValue>;
+
+  #[dbus_proxy(signal)]
+  fn setting_changed(&self, namespace: &str, key: &str, value: Value<'_>) -> zbus::Result<()>;
+}
+
+pub fn get_themes_dir() -> PathBuf {
+  let themes_dir = ProjectDirs::from("dev", "ronanru", "ronix")
+    .unwrap()
+    .config_dir()
+    .join("themes");
+  create_dir_all(&themes_dir).unwrap();
+  themes_dir
+}
+
+fn is_hex_color(color: &str) -> bool {
+  color.len() == 7 && color.starts_with('#') && color[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn validate_colors(colors: &ThemeColors) -> bool {
+  [
+    &colors.background,
+    &colors.surface,
+    &colors.text,
+    &colors.accent,
+  ]
+  .iter()
+  .all(|color| is_hex_color(color))
+}
+
+fn validate_theme(theme: &Theme) -> Result<(), String> {
+  if theme.name.trim().is_empty() {
+    return Err("The theme has no name".to_string());
+  }
+  if !validate_colors(&theme.light) || !validate_colors(&theme.dark) {
+    return Err("The colors have to be like #1a2b3c".to_string());
+  }
+  Ok(())
+}
+
+// Ids are only letters, digits and dashes, so they can't point outside the themes folder
+fn is_valid_id(id: &str) -> bool {
+  !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+fn get_theme_path(id: &str) -> Option<PathBuf> {
+  is_valid_id(id).then(|| get_themes_dir().join(id).with_extension("json"))
+}
+
+// Made from the name, with a number added when it's taken
+fn create_id(name: &str) -> String {
+  let slug = name
+    .to_lowercase()
+    .split(|c: char| !c.is_ascii_alphanumeric())
+    .filter(|part| !part.is_empty())
+    .collect::<Vec<_>>()
+    .join("-");
+  let slug = if slug.is_empty() {
+    "theme".to_string()
+  } else {
+    slug
+  };
+  let themes_dir = get_themes_dir();
+  let mut id = slug.clone();
+  let mut number = 2;
+  while themes_dir.join(&id).with_extension("json").exists() {
+    id = format!("{}-{}", slug, number);
+    number += 1;
+  }
+  id
+}
+
+fn read_theme_file(path: &Path) -> Result<Theme, Box<dyn Error>> {
+  let theme: Theme = serde_json::from_slice(&fs::read(path)?)?;
+  validate_theme(&theme)?;
+  Ok(theme)
+}
+
+// Files that can't be read are left out
+pub fn get_themes() -> Vec<ThemeFile> {
+  let entries = match fs::read_dir(get_themes_dir()) {
+    Ok(entries) => entries,
+    Err(_) => return Vec::new(),
+  };
+  let mut themes: Vec<ThemeFile> = entries
+    .filter_map(|entry| {
+      let path = entry.ok()?.path();
+      if path.extension()? != "json" {
+        return None;
+      }
+      Some(ThemeFile {
+        id: path.file_stem()?.to_str()?.to_string(),
+        theme: read_theme_file(&path).ok()?,
+      })
+    })
+    .collect();
+  themes.sort_by(|a, b| a.theme.name.cmp(&b.theme.name));
+  themes
+}
+
+// Returns the theme's id
+pub fn save_theme(input: SaveThemeInput) -> Result<String, String> {
+  validate_theme(&input.theme)?;
+  let id = match input.id {
+    Some(id) if is_valid_id(&id) => id,
+    Some(_) => return Err("Invalid theme id".to_string()),
+    None => create_id(&input.theme.name),
+  };
+  let contents = serde_json::to_string_pretty(&input.theme).unwrap();
+  fs::write(get_theme_path(&id).unwrap(), contents)
+    .map_err(|e| format!("Failed to save the theme: {}", e))?;
+  Ok(id)
+}
+
+pub fn delete_theme(id: &str) {
+  if let Some(path) = get_theme_path(id) {
+    let _ = fs::remove_file(path);
+  }
+}
+
+pub fn import_theme(path: &Path) -> Result<String, Box<dyn Error>> {
+  let theme = read_theme_file(path)?;
+  Ok(save_theme(SaveThemeInput { id: None, theme })?)
+}
+
+pub fn export_theme(id: &str, path: &Path) -> Result<(), Box<dyn Error>> {
+  let theme_path = get_theme_path(id).ok_or("Invalid theme id")?;
+  fs::copy(theme_path, path)?;
+  Ok(())
+}
+
+// The portal sends 1 for dark, 2 for light and 0 without a preference, wrapped in a variant
+fn parse_color_scheme(value: &Value) -> Option<bool> {
+  match value {
+    Value::Value(inner) => parse_color_scheme(inner),
+    Value::U32(1) => Some(true),
+    Value::U32(2) => Some(false),
+    _ => None,
+  }
+}
+
+async fn watch_color_scheme(ctx: &Context) -> zbus::Result<()> {
+  let connection = Connection::session().await?;
+  let proxy = PortalSettingsProxy::new(&connection).await?;
+  let mut changes = proxy.receive_setting_changed().await?;
+  let value = proxy.read(APPEARANCE_NAMESPACE, COLOR_SCHEME_KEY).await?;
+  *ctx.system_dark_mode.lock().unwrap() = parse_color_scheme(&*value);
+  while let Some(signal) = changes.next().await {
+    let args = signal.args()?;
+    if *args.namespace() == APPEARANCE_NAMESPACE && *args.key() == COLOR_SCHEME_KEY {
+      *ctx.system_dark_mode.lock().unwrap() = parse_color_scheme(args.value());
+    }
+  }
+  Ok(())
+}
+
+// Keeps `system_dark_mode` up to date with the desktop's light or dark preference
+pub fn start(ctx: Context) {
+  tauri::async_runtime::spawn(async move {
+    if let Err(e) = watch_color_scheme(&ctx).await {
+      println!("Failed to read the desktop color scheme: {}", e);
+    }
+  });
+}
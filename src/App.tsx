@@ -16,19 +16,32 @@ import Controls from './components/controls';
 import DownloadSongModal from './components/downloadSongModal';
 import Menu from './components/menu';
 import Button from './components/ui/button';
-import { config, generateCssVariables } from './config';
+import { config, generateConfigCssVariables } from './config';
 import { SearchPageData, currentPage, goBack, navigate } from './router';
 import AlbumList from './views/albumList';
 import AlbumPage from './views/albumPage';
 import ArtistPage from './views/artistPage';
 import ArtistList from './views/artistsList';
+import FolderPage from './views/folderPage';
+import GenreList from './views/genreList';
 import Loading from './views/loading';
+import ScopePage from './views/scopePage';
 import SearchPage from './views/searchPage';
 import Settings from './views/settings';
 import SongList from './views/songList';
+import YearList from './views/yearList';
 
 const Welcome = lazy(() => import('./views/welcome'));
 
+const listPages = [
+  'songs',
+  'albums',
+  'artists',
+  'genres',
+  'years',
+  'folders',
+] as const;
+
 const App: Component = () => {
   let searchInput: HTMLInputElement;
 
@@ -60,11 +73,7 @@ const App: Component = () => {
   createEffect(() => {
     const conf = config();
     if (!conf) return;
-    const cssVars = generateCssVariables(
-      conf.main_color,
-      conf.accent_color,
-      conf.dark_mode,
-    );
+    const cssVars = generateConfigCssVariables(conf);
     for (const key in cssVars) {
       /* eslint-disable @typescript-eslint/no-explicit-any */
       if (key.startsWith('--'))
@@ -91,7 +100,9 @@ const App: Component = () => {
         />
         <header class="flex w-full items-center gap-4 p-4">
           <Show
-            when={!['songs', 'albums', 'artists'].includes(currentPage().name)}
+            when={
+              !(listPages as readonly string[]).includes(currentPage().name)
+            }
           >
             <Button
               role="link"
@@ -126,10 +137,10 @@ const App: Component = () => {
           </Show>
         </header>
         <Show
-          when={['songs', 'albums', 'artists'].includes(currentPage().name)}
+          when={(listPages as readonly string[]).includes(currentPage().name)}
         >
-          <nav class="mb-4 grid w-full grid-cols-3 gap-4 px-4">
-            <For each={['songs', 'albums', 'artists'] as const}>
+          <nav class="mb-4 grid w-full grid-cols-3 gap-4 px-4 md:grid-cols-6">
+            <For each={listPages}>
               {(name) => (
                 <Button
                   variant={currentPage().name === name ? 'light' : 'default'}
@@ -158,12 +169,42 @@ const App: Component = () => {
               <Match when={currentPage().name === 'artists'}>
                 <ArtistList />
               </Match>
+              <Match when={currentPage().name === 'genres'}>
+                <GenreList />
+              </Match>
+              <Match when={currentPage().name === 'years'}>
+                <YearList />
+              </Match>
+              <Match when={currentPage().name === 'folders'}>
+                <FolderPage />
+              </Match>
               <Match when={currentPage().name === 'artist'}>
                 <ArtistPage artistId={currentPage().data as string} />
               </Match>
               <Match when={currentPage().name === 'album'}>
                 <AlbumPage albumId={currentPage().data as string} />
               </Match>
+              <Match when={currentPage().name === 'genre'}>
+                <ScopePage
+                  title={currentPage().data as string}
+                  scope={{ Genre: currentPage().data as string }}
+                />
+              </Match>
+              <Match when={currentPage().name === 'year'}>
+                <ScopePage
+                  title={`${currentPage().data}`}
+                  scope={{ Year: currentPage().data as number }}
+                />
+              </Match>
+              <Match when={currentPage().name === 'decade'}>
+                <ScopePage
+                  title={`${currentPage().data}s`}
+                  scope={{ Decade: currentPage().data as number }}
+                />
+              </Match>
+              <Match when={currentPage().name === 'folder'}>
+                <FolderPage path={currentPage().data as string} />
+              </Match>
               <Match when={currentPage().name === 'search'}>
                 <SearchPage
                   query={(currentPage().data as SearchPageData).query}
@@ -1,7 +1,18 @@
-import { createClient } from '@rspc/client';
+import { WebsocketTransport, createClient } from '@rspc/client';
 import { TauriTransport } from '@rspc/tauri';
 import { Procedures } from './gen/tauri-types';
 
+declare global {
+  interface Window {
+    __RONIX_DAEMON_URL__?: string | null;
+  }
+}
+
+// Set by the backend when this window is attached to a headless daemon
+const daemonUrl = window.__RONIX_DAEMON_URL__;
+
 export const api = createClient<Procedures>({
-  transport: new TauriTransport(),
+  transport: daemonUrl
+    ? new WebsocketTransport(daemonUrl)
+    : new TauriTransport(),
 });
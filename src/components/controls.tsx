@@ -1,4 +1,6 @@
 import { api } from '@/api';
+import { setPalette } from '@/config';
+import { getCoverUrl } from '@/covers';
 import { CurrentSongData, RepeatMode } from '@/gen/tauri-types';
 import { library } from '@/library';
 import { navigate } from '@/router';
@@ -34,6 +36,7 @@ const Controls: Component = () => {
     paused_at: Infinity,
     song_started_at: Infinity,
     volume: 0.5,
+    palette: null,
   });
 
   let animationFrame: number | null = null;
@@ -73,7 +76,10 @@ const Controls: Component = () => {
   onMount(() => {
     // eslint-disable-next-line @typescript-eslint/no-explicit-any
     api.addSubscription(['player.currentSong'] as any, {
-      onData: setCurrentSongData,
+      onData: (data: CurrentSongData) => {
+        setCurrentSongData(data);
+        setPalette(data.palette);
+      },
     });
     update();
   });
@@ -177,7 +183,10 @@ const Controls: Component = () => {
         <div class="grid grid-cols-[1fr,10.5rem] items-center gap-4 p-4 md:grid-cols-[1fr,10.5rem,1fr]">
           <div class="flex items-center gap-4 overflow-hidden md:col-span-1">
             <CoverArt
-              src={currentAlbum().cover_art}
+              src={
+                currentAlbum().cover_art &&
+                getCoverUrl(currentSong().album, 'small')
+              }
               class="h-14 w-14 rounded-xl"
             />
             <div class="overflow-hidden">
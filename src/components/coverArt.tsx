@@ -1,4 +1,3 @@
-import { convertFileSrc } from '@tauri-apps/api/tauri';
 import { cx } from 'class-variance-authority';
 import { MusicIcon } from 'lucide-solid';
 import { Show, type Component } from 'solid-js';
@@ -22,7 +21,7 @@ const CoverArt: Component<{
       }
     >
       <img
-        src={convertFileSrc(props.src as string)}
+        src={props.src as string}
         alt=""
         class={props.class}
       />
@@ -0,0 +1,103 @@
+import { api } from '@/api';
+import { SortKey, SortKind } from '@/gen/tauri-types';
+import { library } from '@/library';
+import { ArrowDownIcon, ArrowUpIcon } from 'lucide-solid';
+import {
+  For,
+  Show,
+  createEffect,
+  createSignal,
+  type Component,
+} from 'solid-js';
+import Button from './ui/button';
+
+const sortKeys: Record<SortKind, [SortKey, string][]> = {
+  Artists: [
+    ['Name', 'Name'],
+    ['Year', 'Year'],
+    ['DateAdded', 'Date added'],
+    ['PlayCount', 'Play count'],
+    ['LastPlayed', 'Last played'],
+    ['Duration', 'Duration'],
+  ],
+  Albums: [
+    ['Name', 'Name'],
+    ['Artist', 'Artist'],
+    ['Year', 'Year'],
+    ['DateAdded', 'Date added'],
+    ['PlayCount', 'Play count'],
+    ['LastPlayed', 'Last played'],
+    ['Duration', 'Duration'],
+  ],
+  Songs: [
+    ['Name', 'Title'],
+    ['Artist', 'Artist'],
+    ['Album', 'Album'],
+    ['Year', 'Year'],
+    ['DateAdded', 'Date added'],
+    ['PlayCount', 'Play count'],
+    ['LastPlayed', 'Last played'],
+    ['Duration', 'Duration'],
+    ['Rating', 'Rating'],
+  ],
+};
+
+// The order comes from the backend, which knows about sort tags and articles
+export const createSortOrder = (kind: SortKind, isEnabled: () => boolean) => {
+  const [key, setKey] = createSignal<SortKey>('Name');
+  const [descending, setDescending] = createSignal(false);
+  const [order, setOrder] = createSignal<string[] | null>(null);
+
+  createEffect(() => {
+    if (!isEnabled() || !library()) return;
+    api
+      .query([
+        'library.sorted',
+        { kind, key: key(), descending: descending(), ids: null },
+      ])
+      .then(setOrder);
+  });
+
+  const sort = <T extends { id: string }>(items: T[]) => {
+    const ids = order();
+    if (!ids) return items;
+    const positions = new Map(ids.map((id, i) => [id, i]));
+    return [...items].sort(
+      (a, b) => (positions.get(a.id) ?? 0) - (positions.get(b.id) ?? 0),
+    );
+  };
+
+  return { kind, key, setKey, descending, setDescending, sort };
+};
+
+export type SortOrder = ReturnType<typeof createSortOrder>;
+
+const SortSelect: Component<{ order: SortOrder }> = (props) => (
+  <div class="mb-4 flex items-center justify-end gap-2">
+    <label for={`sort-${props.order.kind}`}>Sort by</label>
+    <select
+      id={`sort-${props.order.kind}`}
+      class="rounded-md bg-primary-800 px-2 py-1"
+      onChange={(e) => props.order.setKey(e.currentTarget.value as SortKey)}
+    >
+      <For each={sortKeys[props.order.kind]}>
+        {([key, name]) => (
+          <option value={key} selected={props.order.key() === key}>
+            {name}
+          </option>
+        )}
+      </For>
+    </select>
+    <Button
+      size="icon"
+      aria-label={props.order.descending() ? 'Descending' : 'Ascending'}
+      onClick={() => props.order.setDescending(!props.order.descending())}
+    >
+      <Show when={props.order.descending()} fallback={<ArrowUpIcon />}>
+        <ArrowDownIcon />
+      </Show>
+    </Button>
+  </div>
+);
+
+export default SortSelect;
@@ -1,11 +1,11 @@
-import { createResource, type JSX } from 'solid-js';
+import { createResource, createSignal, type JSX } from 'solid-js';
 import colors from 'tailwindcss/colors';
 import type { DefaultColors } from 'tailwindcss/types/generated/colors';
 import { api } from './api';
-import { Config } from './gen/tauri-types';
+import { Config, Palette, ThemeColors, ThemeFile } from './gen/tauri-types';
 import { refetchLibrary } from './library';
 
-const [config, { mutate }] = createResource<Config>(() =>
+const [config, { mutate, refetch: refetchConfig }] = createResource<Config>(() =>
   api.query(['config.get']),
 );
 
@@ -17,6 +17,26 @@ const setConfig = async (newConfig: Config) => {
   mutate(newConfig);
 };
 
+const [themes, { refetch: refetchThemes }] = createResource<ThemeFile[]>(() =>
+  api.query(['config.themes']),
+);
+
+// The desktop's light or dark preference, null without one
+const [systemDarkMode, setSystemDarkMode] = createSignal<boolean | null>(null);
+
+// eslint-disable-next-line @typescript-eslint/no-explicit-any
+api.addSubscription(['config.systemDarkMode'] as any, {
+  onData: setSystemDarkMode,
+});
+
+const isDarkMode = (conf: Config) =>
+  conf.follow_system_theme
+    ? systemDarkMode() ?? conf.dark_mode
+    : conf.dark_mode;
+
+// Colors of the playing song's cover, sent by the player when the dynamic accent is on
+const [palette, setPalette] = createSignal<Palette | null>(null);
+
 const tailwindColors = [
   950, 900, 800, 700, 600, 500, 400, 300, 200, 100, 50,
 ] as const;
@@ -29,11 +49,79 @@ const getColorNumber = (color: number, darkMode: boolean) => {
   ) as TailwindColor;
 };
 
-export const generateCssVariables = (
+// Lightness of each shade, close to how Tailwind's palettes go
+const shadeLightness: Record<TailwindColor, number> = {
+  50: 97,
+  100: 94,
+  200: 86,
+  300: 77,
+  400: 66,
+  500: 55,
+  600: 45,
+  700: 37,
+  800: 30,
+  900: 24,
+  950: 14,
+};
+
+// How far each shade of a custom theme goes from its surface color to its text color
+const shadeMix: Record<TailwindColor, number> = {
+  50: 1,
+  100: 0.92,
+  200: 0.84,
+  300: 0.74,
+  400: 0.62,
+  500: 0.5,
+  600: 0.38,
+  700: 0.25,
+  800: 0.12,
+  900: 0,
+  950: 0,
+};
+
+const parseHex = (hex: string) =>
+  [1, 3, 5].map((i) => parseInt(hex.slice(i, i + 2), 16));
+
+const mixColors = (from: string, to: string, amount: number) => {
+  const [fromRgb, toRgb] = [parseHex(from), parseHex(to)];
+  const mixed = fromRgb.map((c, i) =>
+    Math.round(c + (toRgb[i] - c) * amount),
+  );
+  return `rgb(${mixed.join(' ')})`;
+};
+
+// Keeps the hue and saturation of a #rrggbb color
+const generateShades = (hex: string): Record<TailwindColor, string> => {
+  const [r, g, b] = parseHex(hex).map((c) => c / 255);
+  const max = Math.max(r, g, b);
+  const min = Math.min(r, g, b);
+  const lightness = (max + min) / 2;
+  const saturation =
+    max === min ? 0 : (max - min) / (1 - Math.abs(2 * lightness - 1));
+  let hue = 0;
+  if (max !== min) {
+    if (max === r) hue = ((g - b) / (max - min) + 6) % 6;
+    else if (max === g) hue = (b - r) / (max - min) + 2;
+    else hue = (r - g) / (max - min) + 4;
+  }
+  const h = Math.round(hue * 60);
+  const s = Math.round(saturation * 100);
+  const shades = {} as Record<TailwindColor, string>;
+  for (const color of tailwindColors) {
+    shades[color] = `hsl(${h} ${s}% ${shadeLightness[color]}%)`;
+  }
+  return shades;
+};
+
+const generateCssVariables = (
   mainColor: string,
   accentColor: string,
   darkMode: boolean,
+  dynamicAccent?: string | null,
 ): JSX.CSSProperties => {
+  const accentShades = dynamicAccent
+    ? generateShades(dynamicAccent)
+    : colors[accentColor.toLowerCase() as keyof DefaultColors];
   const styles: JSX.CSSProperties = {
     '--color-white': darkMode ? '#ffffff' : '#000000',
     'color-scheme': darkMode ? 'dark' : 'light',
@@ -44,11 +132,59 @@ export const generateCssVariables = (
         getColorNumber(i, darkMode)
       ];
     styles[`--color-accent-${tailwindColors[i]}`] =
-      colors[accentColor.toLowerCase() as keyof DefaultColors][
-        getColorNumber(i, darkMode)
-      ];
+      accentShades[getColorNumber(i, darkMode)];
+  }
+  return styles;
+};
+
+// The theme's colors are used as they are, only the accent shades follow the color scheme
+const generateThemeCssVariables = (
+  themeColors: ThemeColors,
+  darkMode: boolean,
+  dynamicAccent?: string | null,
+): JSX.CSSProperties => {
+  const accentShades = generateShades(dynamicAccent || themeColors.accent);
+  const styles: JSX.CSSProperties = {
+    '--color-white': themeColors.text,
+    'color-scheme': darkMode ? 'dark' : 'light',
+  };
+  for (let i = 0; i < tailwindColors.length; i++) {
+    const color = tailwindColors[i];
+    styles[`--color-primary-${color}`] =
+      color === 950
+        ? themeColors.background
+        : mixColors(themeColors.surface, themeColors.text, shadeMix[color]);
+    styles[`--color-accent-${color}`] =
+      accentShades[getColorNumber(i, darkMode)];
   }
   return styles;
 };
 
-export { config, setConfig };
+export const generateConfigCssVariables = (conf: Config): JSX.CSSProperties => {
+  const darkMode = isDarkMode(conf);
+  const dynamicAccent = conf.dynamic_accent ? palette()?.vibrant : null;
+  const theme = themes()?.find((theme) => theme.id === conf.theme)?.theme;
+  return theme
+    ? generateThemeCssVariables(
+        darkMode ? theme.dark : theme.light,
+        darkMode,
+        dynamicAccent,
+      )
+    : generateCssVariables(
+        conf.main_color,
+        conf.accent_color,
+        darkMode,
+        dynamicAccent,
+      );
+};
+
+export {
+  config,
+  palette,
+  refetchConfig,
+  refetchThemes,
+  setConfig,
+  setPalette,
+  systemDarkMode,
+  themes,
+};
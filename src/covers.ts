@@ -0,0 +1,15 @@
+import { convertFileSrc } from '@tauri-apps/api/tauri';
+
+export type CoverSize = 'small' | 'large' | 'original';
+
+// Windows attached to a daemon get covers from its local server instead of the cover protocol,
+// with the same token as the websocket
+const daemonUrl = window.__RONIX_DAEMON_URL__
+  ? new URL(window.__RONIX_DAEMON_URL__)
+  : null;
+const daemonToken = daemonUrl?.searchParams.get('token');
+
+export const getCoverUrl = (albumId: string, size: CoverSize) =>
+  daemonUrl
+    ? `http://${daemonUrl.host}/covers/${albumId}?size=${size}&token=${daemonToken}`
+    : `${convertFileSrc(albumId, 'cover')}?size=${size}`;
@@ -4,30 +4,69 @@ export type Procedures = {
     queries: 
         { key: "config.get", input: never, result: Config } | 
         { key: "config.getDefaultMusicFolder", input: never, result: string } | 
+        { key: "config.getRemoteUrl", input: never, result: string | null } | 
         { key: "config.pickFolder", input: never, result: string | null } | 
+        { key: "config.scrobblingError", input: never, result: string | null } | 
+        { key: "config.themes", input: never, result: ThemeFile[] } | 
         { key: "download.download", input: string, result: string } | 
+        { key: "library.albumSongs", input: string, result: LibraryPart } | 
+        { key: "library.albums", input: PageInput, result: Page<Album> } | 
+        { key: "library.artistSongs", input: string, result: LibraryPart } | 
+        { key: "library.artists", input: PageInput, result: Page<Artist> } | 
+        { key: "library.byIds", input: IdsInput, result: LibraryPart } | 
+        { key: "library.folder", input: string | null, result: FolderListing } | 
+        { key: "library.genres", input: never, result: GenreEntry[] } | 
         { key: "library.get", input: never, result: Library } | 
-        { key: "library.search", input: SearchInput, result: SearchResults },
+        { key: "library.history", input: HistoryInput, result: HistoryPage } | 
+        { key: "library.playlists", input: never, result: { [key: string]: Playlist } } | 
+        { key: "library.scopeSongs", input: PlayerScope, result: string[] } | 
+        { key: "library.search", input: SearchInput, result: SearchResults } | 
+        { key: "library.songs", input: PageInput, result: Page<Song> } | 
+        { key: "library.sorted", input: SortInput, result: string[] } | 
+        { key: "library.years", input: never, result: YearEntry[] } | 
+        { key: "stats.get", input: StatsInput, result: Stats } | 
+        { key: "stats.yearReport", input: number, result: YearReport },
     mutations: 
+        { key: "config.deleteTheme", input: string, result: null } | 
+        { key: "config.exportTheme", input: string, result: string } | 
+        { key: "config.importTheme", input: never, result: string } | 
+        { key: "config.lastFmLogin", input: LastFmLoginInput, result: string } | 
+        { key: "config.resetRemoteToken", input: never, result: null } | 
+        { key: "config.saveTheme", input: SaveThemeInput, result: SaveThemeResult } | 
         { key: "config.set", input: Config, result: null } | 
+        { key: "import.history", input: HistorySource, result: ImportResult } | 
+        { key: "import.playlists", input: PlaylistSource, result: PlaylistImportResult } | 
         { key: "library.deleteSong", input: string, result: string } | 
         { key: "library.editSong", input: EditSongInput, result: string } | 
         { key: "library.refresh", input: never, result: null } | 
+        { key: "library.setFavorite", input: SetFavoriteInput, result: string } | 
+        { key: "library.setRating", input: SetRatingInput, result: string } | 
         { key: "player.nextSong", input: never, result: null } | 
         { key: "player.playSong", input: PlaySongInput, result: null } | 
         { key: "player.previousSong", input: never, result: null } | 
+        { key: "player.queueSongs", input: string[], result: null } | 
         { key: "player.seek", input: number, result: null } | 
         { key: "player.setVolume", input: number, result: number } | 
         { key: "player.togglePause", input: never, result: null } | 
         { key: "player.toggleRepeatMode", input: never, result: RepeatMode } | 
-        { key: "player.toggleShuffle", input: never, result: boolean },
+        { key: "player.toggleShuffle", input: never, result: boolean } | 
+        { key: "playlist.addSongs", input: AddSongsInput, result: string } | 
+        { key: "playlist.create", input: string, result: string } | 
+        { key: "playlist.createSmart", input: SmartPlaylistInput, result: string } | 
+        { key: "playlist.delete", input: string, result: string } | 
+        { key: "playlist.removeSong", input: RemoveSongInput, result: string } | 
+        { key: "playlist.rename", input: RenamePlaylistInput, result: string } | 
+        { key: "playlist.setQuery", input: SetQueryInput, result: string } | 
+        { key: "stats.exportYearReport", input: ExportReportInput, result: string },
     subscriptions: 
+        { key: "config.systemDarkMode", input: never, result: boolean | null } | 
+        { key: "library.changes", input: never, result: LibraryChanges } | 
         { key: "player.currentSong", input: never, result: CurrentSongData }
 };
 
 export type RepeatMode = "None" | "One" | "All"
 
-export type Song = { title: string; path: string; duration: number; album: string }
+export type Song = { title: string; sort_title: string | null; path: string; duration: number; album: string; genre: string | null; year: number | null; added_at: number; play_count: number; skip_count: number; last_played: number | null; rating: number; is_favorite: boolean }
 
 export type PlaySongInput = { song_id: string; scope: PlayerScope }
 
@@ -35,22 +74,132 @@ export type MainColor = "Slate" | "Gray" | "Zinc" | "Neutral" | "Stone"
 
 export type SearchMode = "Library" | "Songs"
 
-export type SearchInput = { query: string; mode: SearchMode }
+export type SearchInput = { query: string; mode: SearchMode; min_rating?: number | null; favorites_only?: boolean | null; offset?: number | null; limit?: number | null }
 
-export type SearchResults = { artists: string[] | null; albums: string[] | null; songs: string[] }
+export type SearchResults = { artists: SearchHit[] | null; albums: SearchHit[] | null; songs: SearchHit[]; total_songs: number }
 
-export type Library = { artists: { [key: string]: Artist }; albums: { [key: string]: Album }; songs: { [key: string]: Song } }
+export type Library = { artists: { [key: string]: Artist }; albums: { [key: string]: Album }; songs: { [key: string]: Song }; playlists: { [key: string]: Playlist } }
+
+export type Playlist = { name: string; songs: string[]; query: string | null }
+
+export type AddSongsInput = { id: string; song_ids: string[] }
+
+export type RemoveSongInput = { id: string; index: number }
+
+export type RenamePlaylistInput = { id: string; name: string }
 
 export type EditSongInput = { id: string; title: string; album: string; artist: string }
 
-export type CurrentSongData = { current_song: string | null; song_started_at: number; paused_at: number | null; volume: number }
+export type CurrentSongData = { current_song: string | null; song_started_at: number; paused_at: number | null; volume: number; palette: Palette | null }
 
-export type Album = { name: string; cover_art: string | null; artist: string }
+export type Album = { name: string; sort_name: string | null; cover_art: string | null; artist: string; play_count: number; last_played: number | null }
 
-export type Config = { music_folders: string[]; dark_mode: boolean; main_color: MainColor; accent_color: AccentColor }
+export type Config = { music_folders: string[]; dark_mode: boolean; main_color: MainColor; accent_color: AccentColor; fade_durations: FadeDurations; daemon_port: number; remote: RemoteConfig; mpd: MpdConfig; subsonic: SubsonicConfig; broadcast: BroadcastConfig; scrobbling: ScrobblingConfig; write_rating_tags: boolean; search_transliteration: boolean; sort_articles: string[]; cover_art_names: string[]; cover_cache_size: number; dynamic_accent: boolean; theme: string | null; follow_system_theme: boolean }
 
-export type PlayerScope = "Library" | { Album: string } | { Artist: string }
+export type FadeDurations = { pause_resume: number; skip: number; stop: number }
 
-export type Artist = { name: string }
+export type PlayerScope = "Library" | { Album: string } | { Artist: string } | { Playlist: string } | "Favorites" | { Rating: number } | { Genre: string } | { Year: number } | { Decade: number } | { Folder: string }
+
+export type Artist = { name: string; sort_name: string | null; play_count: number; last_played: number | null }
 
 export type AccentColor = "Red" | "Orange" | "Amber" | "Yellow" | "Lime" | "Green" | "Emerald" | "Teal" | "Cyan" | "Blue" | "Indigo" | "Violet" | "Purple" | "Fuchsia" | "Pink" | "Rose"
+
+export type RemoteConfig = { enabled: boolean; port: number; token: string }
+
+export type MpdConfig = { enabled: boolean; port: number; password: string }
+
+export type SubsonicConfig = { enabled: boolean; port: number; username: string; password: string; transcoding: boolean }
+
+export type BroadcastConfig = { enabled: boolean; port: number; format: BroadcastFormat }
+
+export type BroadcastFormat = "Mp3" | "Opus"
+
+export type ScrobblingConfig = { listenbrainz_enabled: boolean; listenbrainz_url: string; listenbrainz_token: string; lastfm_enabled: boolean; lastfm_url: string; lastfm_api_key: string; lastfm_api_secret: string; lastfm_session_key: string }
+
+export type LastFmLoginInput = { username: string; password: string }
+
+export type HistoryInput = { offset: number; limit: number; from: number | null; to: number | null }
+
+export type HistoryPage = { entries: HistoryEntry[]; total: number }
+
+export type HistoryEntry = { song_id: string | null; path: string; kind: HistoryEventKind; timestamp: number; listened: number; scope: HistoryScope }
+
+export type HistoryEventKind = "Play" | "Skip"
+
+export type HistoryScope = "Library" | { Album: string } | { Artist: string } | { Playlist: string } | "Favorites" | { Rating: number } | { Genre: string } | { Year: number } | { Decade: number } | { Folder: string } | "Subsonic" | { Imported: string }
+
+export type SetRatingInput = { song_id: string; rating: number }
+
+export type SetFavoriteInput = { song_id: string; is_favorite: boolean }
+
+export type StatsInput = { from: number | null; to: number | null; limit: number }
+
+export type Stats = { plays: number; skips: number; listened: number; top_songs: TopEntry[]; top_albums: TopEntry[]; top_artists: TopEntry[]; top_genres: TopEntry[]; listening_per_day: DayListening[]; listening_per_hour_of_week: number[]; longest_streak: number; current_streak: number; discovery: Discovery }
+
+export type TopEntry = { id: string | null; name: string; artist: string | null; plays: number; listened: number }
+
+export type DayListening = { date: string; listened: number }
+
+export type Discovery = { new_songs: number; new_albums: number; new_artists: number; new_song_ratio: number }
+
+export type YearReport = { year: number; generated_at: string; stats: Stats }
+
+export type ExportReportInput = { year: number; format: ReportFormat }
+
+export type ReportFormat = "Json" | "Html"
+
+export type HistorySource = "Spotify" | "LastFm" | "ListenBrainz"
+
+export type ImportResult = { message: string; imported: number; duplicates: number; unmatched: UnmatchedEntry[] }
+
+export type UnmatchedEntry = { artist: string; album: string | null; title: string; count: number }
+
+export type PlaylistSource = "Exportify" | "Rhythmbox" | "Clementine" | "Strawberry" | "ITunes"
+
+export type PlaylistImportResult = { message: string; playlists: ImportedPlaylistSummary[]; missing: MissingTrack[] }
+
+export type ImportedPlaylistSummary = { id: string; name: string; songs: number; missing: number }
+
+export type MissingTrack = { playlist: string; artist: string; album: string | null; title: string; download_query: string }
+
+export type SmartPlaylistInput = { name: string; query: string }
+
+export type SetQueryInput = { id: string; query: string }
+
+export type SearchHit = { id: string; score: number }
+
+export type SortKey = "Name" | "Artist" | "Album" | "Year" | "DateAdded" | "PlayCount" | "LastPlayed" | "Duration" | "Rating"
+
+export type SortKind = "Artists" | "Albums" | "Songs"
+
+export type SortInput = { kind: SortKind; key: SortKey; descending: boolean; ids: string[] | null }
+
+export type PageInput = { after: string | null; limit: number }
+
+export type Page<T> = { items: { [key: string]: T }; next: string | null; total: number }
+
+export type IdsInput = { artists: string[]; albums: string[]; songs: string[] }
+
+export type LibraryPart = { artists: { [key: string]: Artist }; albums: { [key: string]: Album }; songs: { [key: string]: Song }; playlists: { [key: string]: Playlist } }
+
+export type LibraryChanges = { changed: LibraryPart; removed_artists: string[]; removed_albums: string[]; removed_songs: string[]; removed_playlists: string[] }
+
+export type GenreEntry = { name: string; song_count: number }
+
+export type YearEntry = { year: number; song_count: number }
+
+export type FolderEntry = { path: string; name: string; song_count: number }
+
+export type FolderListing = { folders: FolderEntry[]; songs: string[] }
+
+export type Palette = { dominant: string; vibrant: string }
+
+export type ThemeColors = { background: string; surface: string; text: string; accent: string }
+
+export type Theme = { name: string; light: ThemeColors; dark: ThemeColors }
+
+export type ThemeFile = { id: string; theme: Theme }
+
+export type SaveThemeInput = { id: string | null; theme: Theme }
+
+export type SaveThemeResult = { Saved: string } | { Failed: string }
@@ -1,6 +1,13 @@
-import { createResource } from 'solid-js';
+import { createSignal } from 'solid-js';
+import { createStore, produce, reconcile } from 'solid-js/store';
 import { api } from './api';
-import type { Library, Song } from './gen/tauri-types';
+import type {
+  Library,
+  LibraryChanges,
+  Page,
+  PageInput,
+  Song,
+} from './gen/tauri-types';
 
 export type FullSong = Song & {
   id: string;
@@ -9,10 +16,96 @@ export type FullSong = Song & {
   cover_art: string | null;
 };
 
-const [library, { refetch }] = createResource<Library>(() =>
-  api.query(['library.get']),
+// The library is loaded a page at a time, then kept in sync with the changes the backend sends
+const PAGE_SIZE = 2000;
+
+const [cache, setCache] = createStore<Library>({
+  artists: {},
+  albums: {},
+  songs: {},
+  playlists: {},
+});
+const [isLoaded, setIsLoaded] = createSignal(false);
+// Bumped on every change, so everything reading the library updates like before
+const [revision, setRevision] = createSignal(0);
+
+const loadPages = async <T>(
+  getPage: (input: PageInput) => Promise<Page<T>>,
+) => {
+  const items: { [key: string]: T } = {};
+  let after: string | null = null;
+  do {
+    const page: Page<T> = await getPage({ after, limit: PAGE_SIZE });
+    Object.assign(items, page.items);
+    after = page.next;
+  } while (after);
+  return items;
+};
+
+// Changes that arrive while pages are loading are applied after them
+let loadingCount = 0;
+let pendingChanges: LibraryChanges[] = [];
+
+const applyChanges = (changes: LibraryChanges) => {
+  setCache(
+    produce((library) => {
+      Object.assign(library.artists, changes.changed.artists);
+      Object.assign(library.albums, changes.changed.albums);
+      Object.assign(library.songs, changes.changed.songs);
+      Object.assign(library.playlists, changes.changed.playlists);
+      for (const id of changes.removed_artists) delete library.artists[id];
+      for (const id of changes.removed_albums) delete library.albums[id];
+      for (const id of changes.removed_songs) delete library.songs[id];
+      for (const id of changes.removed_playlists) delete library.playlists[id];
+    }),
+  );
+  setRevision((revision) => revision + 1);
+};
+
+export const refetchLibrary = async () => {
+  loadingCount++;
+  try {
+    const [artists, albums, songs, playlists] = await Promise.all([
+      loadPages((input) => api.query(['library.artists', input])),
+      loadPages((input) => api.query(['library.albums', input])),
+      loadPages((input) => api.query(['library.songs', input])),
+      api.query(['library.playlists']),
+    ]);
+    setCache(reconcile({ artists, albums, songs, playlists }));
+  } finally {
+    loadingCount--;
+    if (loadingCount === 0) {
+      pendingChanges.forEach(applyChanges);
+      pendingChanges = [];
+    }
+  }
+  setIsLoaded(true);
+  setRevision((revision) => revision + 1);
+};
+
+// The first changes are empty and sent once the backend tracks changes,
+// so loading the pages after them doesn't miss anything changed in between
+let isSubscribed = false;
+api.addSubscription(
+  // eslint-disable-next-line @typescript-eslint/no-explicit-any
+  ['library.changes'] as any,
+  {
+    onData: (changes: LibraryChanges) => {
+      if (!isSubscribed) {
+        isSubscribed = true;
+        refetchLibrary();
+      } else if (loadingCount > 0) {
+        pendingChanges.push(changes);
+      } else {
+        applyChanges(changes);
+      }
+    },
+  },
 );
 
-export const refetchLibrary = refetch;
+const library = (): Library | undefined => {
+  revision();
+  return isLoaded() ? cache : undefined;
+};
 
 export { library };
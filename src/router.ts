@@ -4,6 +4,9 @@ export const MainPages = [
   'songs',
   'artists',
   'albums',
+  'genres',
+  'years',
+  'folders',
   'search',
   'settings',
   'about',
@@ -31,6 +34,18 @@ export type PageData =
   | {
       name: 'album';
       data: string;
+    }
+  | {
+      name: 'genre';
+      data: string;
+    }
+  | {
+      name: 'year' | 'decade';
+      data: number;
+    }
+  | {
+      name: 'folder';
+      data: string;
     };
 
 const [currentPage, setCurrentPage] = createSignal<PageData>({
@@ -1,4 +1,4 @@
-import { PencilIcon, Trash2Icon } from 'lucide-solid';
+import { HeartIcon, PencilIcon, Trash2Icon } from 'lucide-solid';
 import { Show, type Component } from 'solid-js';
 import CoverArt from './components/coverArt';
 import Button from './components/ui/button';
@@ -12,8 +12,10 @@ const SongButton: Component<{
   noCoverArt?: boolean;
   onClick?: (e: MouseEvent) => void;
   isManager?: boolean;
+  isFavorite?: boolean;
   onDelete?: () => void;
   onEdit?: () => void;
+  onToggleFavorite?: () => void;
 }> = (props) => {
   return (
     <button
@@ -41,6 +43,16 @@ const SongButton: Component<{
           </p>
         }
       >
+        <Button
+          variant={props.isFavorite ? 'accent' : 'default'}
+          size="icon"
+          aria-label={
+            props.isFavorite ? 'Remove from favorites' : 'Add to favorites'
+          }
+          onClick={props.onToggleFavorite}
+        >
+          <HeartIcon />
+        </Button>
         <Button variant="accent" size="icon" onClick={props.onEdit}>
           <PencilIcon />
         </Button>
@@ -1,3 +1,5 @@
+import SortSelect, { createSortOrder } from '@/components/sortSelect';
+import { getCoverUrl } from '@/covers';
 import { library } from '@/library';
 import { navigate } from '@/router';
 import { For, Show, type Component } from 'solid-js';
@@ -8,6 +10,8 @@ const AlbumList: Component<{
   ids?: string[];
   noSort?: boolean;
 }> = (props) => {
+  const order = createSortOrder('Albums', () => !props.noSort);
+
   const albums = () => {
     let entries = Object.entries(library()?.albums || {});
     if (props.artist)
@@ -22,8 +26,7 @@ const AlbumList: Component<{
         artist: artist.name,
       };
     });
-    if (!props.noSort)
-      albums = albums.sort((a, b) => a.name.localeCompare(b.name));
+    if (!props.noSort) albums = order.sort(albums);
     return albums;
   };
 
@@ -32,6 +35,9 @@ const AlbumList: Component<{
       when={albums().length}
       fallback={<p class="text-center">No albums found</p>}
     >
+      <Show when={!props.noSort}>
+        <SortSelect order={order} />
+      </Show>
       <div class="grid grid-cols-[repeat(auto-fill,minmax(15rem,1fr))] gap-4">
         <For each={albums()}>
           {(album) => (
@@ -45,7 +51,10 @@ const AlbumList: Component<{
                 })
               }
             >
-              <CoverArt src={album.cover_art} class="rounded-lg" />
+              <CoverArt
+                src={album.cover_art && getCoverUrl(album.id, 'small')}
+                class="rounded-lg"
+              />
               <p class="truncate text-center font-bold">{album.name}</p>
               <Show when={!props.artist}>
                 <button
@@ -1,6 +1,6 @@
+import { getCoverUrl } from '@/covers';
 import { library } from '@/library';
 import { navigate } from '@/router';
-import { convertFileSrc } from '@tauri-apps/api/tauri';
 import { Show, type Component } from 'solid-js';
 import SongList from './songList';
 
@@ -12,7 +12,7 @@ const AlbumPage: Component<{ albumId: string }> = (props) => {
     <div>
       <Show when={album()?.cover_art}>
         <img
-          src={convertFileSrc(album()?.cover_art as string)}
+          src={getCoverUrl(props.albumId, 'large')}
           alt=""
           class="h-64 w-full rounded-lg object-cover object-center"
         />
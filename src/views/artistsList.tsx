@@ -1,3 +1,4 @@
+import SortSelect, { createSortOrder } from '@/components/sortSelect';
 import { library } from '@/library';
 import { navigate } from '@/router';
 import { For, Show, type Component } from 'solid-js';
@@ -6,6 +7,8 @@ const ArtistList: Component<{
   ids?: string[];
   noSort?: boolean;
 }> = (props) => {
+  const order = createSortOrder('Artists', () => !props.noSort);
+
   const artists = () => {
     let entries = Object.entries(library()?.artists || {});
     if (props.ids) entries = entries.filter(([id]) => props.ids!.includes(id));
@@ -13,8 +16,7 @@ const ArtistList: Component<{
       id,
       ...artist,
     }));
-    if (!props.noSort)
-      artists = artists.sort((a, b) => a.name.localeCompare(b.name));
+    if (!props.noSort) artists = order.sort(artists);
     return artists;
   };
 
@@ -23,6 +25,9 @@ const ArtistList: Component<{
       when={artists().length}
       fallback={<p class="text-center">No artists found</p>}
     >
+      <Show when={!props.noSort}>
+        <SortSelect order={order} />
+      </Show>
       <div class="grid grid-cols-[repeat(auto-fill,minmax(15rem,1fr))] gap-4">
         <For each={artists()}>
           {(artist) => (
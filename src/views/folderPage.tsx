@@ -0,0 +1,56 @@
+import { api } from '@/api';
+import { FolderListing } from '@/gen/tauri-types';
+import { library } from '@/library';
+import { navigate } from '@/router';
+import { FolderIcon } from 'lucide-solid';
+import { For, Show, createEffect, createSignal, type Component } from 'solid-js';
+import SongList from './songList';
+
+// Without a path, lists the music folders
+const FolderPage: Component<{ path?: string }> = (props) => {
+  const [listing, setListing] = createSignal<FolderListing | null>(null);
+
+  createEffect(() => {
+    if (!library()) return;
+    api.query(['library.folder', props.path ?? null]).then(setListing);
+  });
+
+  return (
+    <div>
+      <Show when={props.path}>
+        <h1 class="mt-16 break-all text-5xl font-bold">
+          {props.path!.split(/[\\/]/).pop()}
+        </h1>
+        <p class="mt-2 break-all opacity-70">{props.path}</p>
+      </Show>
+      <Show when={listing()?.folders.length}>
+        <section aria-label="Folders" class="mt-8">
+          <div class="flex flex-col gap-2">
+            <For each={listing()!.folders}>
+              {(folder) => (
+                <button
+                  role="link"
+                  class="flex items-center gap-4 rounded-lg bg-primary-900 p-4 text-left transition-colors hover:bg-primary-800"
+                  onClick={() => navigate({ name: 'folder', data: folder.path })}
+                >
+                  <FolderIcon />
+                  <p class="flex-1 truncate">{folder.name}</p>
+                  <p class="text-sm opacity-70">
+                    {folder.song_count} songs
+                  </p>
+                </button>
+              )}
+            </For>
+          </div>
+        </section>
+      </Show>
+      <Show when={props.path && listing()?.songs.length}>
+        <section aria-label="Songs" class="mt-8">
+          <SongList ids={listing()!.songs} scope={{ Folder: props.path! }} />
+        </section>
+      </Show>
+    </div>
+  );
+};
+
+export default FolderPage;
@@ -0,0 +1,38 @@
+import { api } from '@/api';
+import { GenreEntry } from '@/gen/tauri-types';
+import { library } from '@/library';
+import { navigate } from '@/router';
+import { For, Show, createEffect, createSignal, type Component } from 'solid-js';
+
+const GenreList: Component = () => {
+  const [genres, setGenres] = createSignal<GenreEntry[]>([]);
+
+  createEffect(() => {
+    if (!library()) return;
+    api.query(['library.genres']).then(setGenres);
+  });
+
+  return (
+    <Show
+      when={genres().length}
+      fallback={<p class="text-center">No genres found</p>}
+    >
+      <div class="grid grid-cols-[repeat(auto-fill,minmax(15rem,1fr))] gap-4">
+        <For each={genres()}>
+          {(genre) => (
+            <button
+              role="link"
+              class="items-center overflow-hidden rounded-lg bg-primary-900 p-4 transition-colors hover:bg-primary-800"
+              onClick={() => navigate({ name: 'genre', data: genre.name })}
+            >
+              <p class="truncate">{genre.name}</p>
+              <p class="text-sm opacity-70">{genre.song_count} songs</p>
+            </button>
+          )}
+        </For>
+      </div>
+    </Show>
+  );
+};
+
+export default GenreList;
@@ -0,0 +1,27 @@
+import { api } from '@/api';
+import { PlayerScope } from '@/gen/tauri-types';
+import { library } from '@/library';
+import { createEffect, createSignal, type Component } from 'solid-js';
+import SongList from './songList';
+
+// A genre, year or decade
+const ScopePage: Component<{ title: string; scope: PlayerScope }> = (props) => {
+  const [songs, setSongs] = createSignal<string[]>([]);
+
+  createEffect(() => {
+    if (!library()) return;
+    api.query(['library.scopeSongs', props.scope]).then(setSongs);
+  });
+
+  return (
+    <div>
+      <h1 class="mt-16 text-5xl font-bold">{props.title}</h1>
+      <section aria-label="Songs" class="mt-8">
+        <h2 class="mb-4 text-2xl font-bold">Songs</h2>
+        <SongList ids={songs()} scope={props.scope} />
+      </section>
+    </div>
+  );
+};
+
+export default ScopePage;
@@ -1,4 +1,5 @@
 import { api } from '@/api';
+import Button from '@/components/ui/button';
 import { SearchResults } from '@/gen/tauri-types';
 import { Show, createEffect, createSignal, on, type Component } from 'solid-js';
 import AlbumList from './albumList';
@@ -6,26 +7,31 @@ import ArtistList from './artistsList';
 import Loading from './loading';
 import SongList from './songList';
 
+// Results are fetched a page at a time so typing stays fast on big libraries
+const PAGE_SIZE = 100;
+
 const SearchPage: Component<{ query: string; isManager: boolean }> = (
   props,
 ) => {
   const [searchResults, setSearchResults] = createSignal<SearchResults | null>(
     null,
   );
+  const [limit, setLimit] = createSignal(PAGE_SIZE);
+
+  createEffect(on(() => props.query, () => setLimit(PAGE_SIZE)));
 
   createEffect(
-    on(
-      () => props.query,
-      (query) =>
-        api
-          .query([
-            'library.search',
-            {
-              mode: props.isManager ? 'Songs' : 'Library',
-              query,
-            },
-          ])
-          .then(setSearchResults),
+    on([() => props.query, limit], ([query, limit]) =>
+      api
+        .query([
+          'library.search',
+          {
+            mode: props.isManager ? 'Songs' : 'Library',
+            query,
+            limit,
+          },
+        ])
+        .then(setSearchResults),
     ),
   );
 
@@ -35,23 +41,36 @@ const SearchPage: Component<{ query: string; isManager: boolean }> = (
       <Show when={searchResults()?.artists}>
         <section aria-label="Artists" class="mt-8">
           <h2 class="mb-4 text-2xl font-bold">Artists</h2>
-          <ArtistList ids={searchResults()!.artists!} noSort />
+          <ArtistList
+            ids={searchResults()!.artists!.map((hit) => hit.id)}
+            noSort
+          />
         </section>
       </Show>
       <Show when={searchResults()?.albums}>
         <section aria-label="Albums" class="mt-8">
           <h2 class="mb-4 text-2xl font-bold">Albums</h2>
-          <AlbumList ids={searchResults()!.albums!} noSort />
+          <AlbumList
+            ids={searchResults()!.albums!.map((hit) => hit.id)}
+            noSort
+          />
         </section>
       </Show>
       <Show when={searchResults()?.songs}>
         <section aria-label="Songs" class="mt-8">
           <h2 class="mb-4 text-2xl font-bold">Songs</h2>
           <SongList
-            ids={searchResults()?.songs}
+            ids={searchResults()?.songs.map((hit) => hit.id)}
             noSort
             isManager={props.isManager}
           />
+          <Show
+            when={searchResults()!.total_songs > searchResults()!.songs.length}
+          >
+            <Button class="mt-4" onClick={() => setLimit(limit() + PAGE_SIZE)}>
+              Show more
+            </Button>
+          </Show>
         </section>
       </Show>
     </Show>
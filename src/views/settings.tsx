@@ -1,19 +1,184 @@
+import { api } from '@/api';
 import ColorInput from '@/components/colorInput';
 import FolderList from '@/components/folderList';
 import Button from '@/components/ui/button';
+import TextInput from '@/components/ui/textInput';
 import {
-  generateCssVariables,
+  generateConfigCssVariables,
   config as globalConfig,
+  refetchConfig,
+  refetchThemes,
   setConfig as setGlobalConfig,
+  themes,
 } from '@/config';
-import { Config } from '@/gen/tauri-types';
+import { refetchLibrary } from '@/library';
+import {
+  Config,
+  HistorySource,
+  ImportResult,
+  PlaylistImportResult,
+  PlaylistSource,
+  ReportFormat,
+  SaveThemeInput,
+  ThemeColors,
+} from '@/gen/tauri-types';
 import { goBack } from '@/router';
 import SongButton from '@/songButton';
-import { type Component } from 'solid-js';
+import {
+  For,
+  Show,
+  createResource,
+  createSignal,
+  type Component,
+} from 'solid-js';
 import { createStore } from 'solid-js/store';
 
+const themeColorLabels: Record<keyof ThemeColors, string> = {
+  background: 'Background',
+  surface: 'Surface',
+  text: 'Text',
+  accent: 'Accent',
+};
+
+const newTheme: SaveThemeInput = {
+  id: null,
+  theme: {
+    name: 'My Theme',
+    light: {
+      background: '#ffffff',
+      surface: '#f4f4f5',
+      text: '#000000',
+      accent: '#10b981',
+    },
+    dark: {
+      background: '#09090b',
+      surface: '#18181b',
+      text: '#ffffff',
+      accent: '#10b981',
+    },
+  },
+};
+
 const Settings: Component = () => {
   const [config, setConfig] = createStore<Config>(globalConfig()!);
+  const [remoteUrl, { refetch: refetchRemoteUrl }] = createResource(() =>
+    api.query(['config.getRemoteUrl']),
+  );
+
+  const unpairDevices = async () => {
+    await api.mutation(['config.resetRemoteToken']);
+    const newConfig = await refetchConfig();
+    setConfig('remote', 'token', newConfig!.remote.token);
+    refetchRemoteUrl();
+  };
+
+  const [scrobblingError] = createResource(() =>
+    api.query(['config.scrobblingError']),
+  );
+
+  const [lastFmUsername, setLastFmUsername] = createSignal('');
+  const [lastFmPassword, setLastFmPassword] = createSignal('');
+  const [lastFmStatus, setLastFmStatus] = createSignal('');
+
+  const [reportYear, setReportYear] = createSignal(new Date().getFullYear());
+  const [reportStatus, setReportStatus] = createSignal('');
+
+  const exportYearReport = async (format: ReportFormat) =>
+    setReportStatus(
+      await api.mutation([
+        'stats.exportYearReport',
+        { year: reportYear(), format },
+      ]),
+    );
+
+  const [importResult, setImportResult] = createSignal<ImportResult | null>(
+    null,
+  );
+
+  const importHistory = async (source: HistorySource) => {
+    setImportResult(null);
+    setImportResult(await api.mutation(['import.history', source]));
+    refetchLibrary();
+  };
+
+  const [playlistImportResult, setPlaylistImportResult] =
+    createSignal<PlaylistImportResult | null>(null);
+  const [downloadStatus, setDownloadStatus] = createStore<
+    Record<string, string>
+  >({});
+
+  const importPlaylists = async (source: PlaylistSource) => {
+    setPlaylistImportResult(null);
+    setPlaylistImportResult(await api.mutation(['import.playlists', source]));
+    refetchLibrary();
+  };
+
+  const downloadMissingTrack = async (query: string) => {
+    setDownloadStatus(query, 'Downloading...');
+    setDownloadStatus(query, await api.query(['download.download', query]));
+    refetchLibrary();
+  };
+
+  const [themeStatus, setThemeStatus] = createSignal('');
+  const [isEditingTheme, setIsEditingTheme] = createSignal(false);
+  const [themeDraft, setThemeDraft] = createStore<SaveThemeInput>(
+    structuredClone(newTheme),
+  );
+
+  const selectedTheme = () =>
+    themes()?.find((theme) => theme.id === config.theme);
+
+  const editTheme = (input: SaveThemeInput) => {
+    setThemeDraft(structuredClone(input));
+    setIsEditingTheme(true);
+  };
+
+  const saveTheme = async () => {
+    const result = await api.mutation([
+      'config.saveTheme',
+      { id: themeDraft.id, theme: themeDraft.theme },
+    ]);
+    if ('Failed' in result) {
+      setThemeStatus(result.Failed);
+      return;
+    }
+    setThemeStatus('');
+    await refetchThemes();
+    setConfig('theme', result.Saved);
+    setIsEditingTheme(false);
+  };
+
+  const deleteTheme = async (id: string) => {
+    await api.mutation(['config.deleteTheme', id]);
+    setConfig('theme', null);
+    setIsEditingTheme(false);
+    refetchThemes();
+  };
+
+  const importTheme = async () => {
+    setThemeStatus(await api.mutation(['config.importTheme']));
+    refetchThemes();
+  };
+
+  const exportTheme = async (id: string) =>
+    setThemeStatus(await api.mutation(['config.exportTheme', id]));
+
+  const logInToLastFm = async () => {
+    // The API key and secret are read from the saved config
+    await setGlobalConfig({ ...config });
+    setLastFmStatus(
+      await api.mutation([
+        'config.lastFmLogin',
+        { username: lastFmUsername(), password: lastFmPassword() },
+      ]),
+    );
+    const newConfig = await refetchConfig();
+    setConfig(
+      'scrobbling',
+      'lastfm_session_key',
+      newConfig!.scrobbling.lastfm_session_key,
+    );
+  };
 
   return (
     <div>
@@ -139,12 +304,119 @@ const Settings: Component = () => {
           },
         ]}
       />
+      <p class="my-4 text-xl font-semibold">Theme</p>
+      <div class="flex flex-wrap gap-2">
+        <Button
+          variant={config.theme === null ? 'default' : 'ghost'}
+          onClick={() => setConfig('theme', null)}
+        >
+          Main and Accent Colors
+        </Button>
+        <For each={themes()}>
+          {(theme) => (
+            <Button
+              variant={config.theme === theme.id ? 'default' : 'ghost'}
+              onClick={() => setConfig('theme', theme.id)}
+            >
+              {theme.theme.name}
+            </Button>
+          )}
+        </For>
+      </div>
+      <div class="mt-4 flex flex-wrap gap-2">
+        <Button onClick={() => editTheme(newTheme)}>New Theme</Button>
+        <Button onClick={importTheme}>Import</Button>
+        <Show when={selectedTheme()}>
+          {(theme) => (
+            <>
+              <Button onClick={() => editTheme(theme())}>Edit</Button>
+              <Button onClick={() => exportTheme(theme().id)}>Export</Button>
+              <Button
+                variant="danger"
+                onClick={() => deleteTheme(theme().id)}
+              >
+                Delete
+              </Button>
+            </>
+          )}
+        </Show>
+      </div>
+      <Show when={themeStatus()}>
+        <p class="mt-2">{themeStatus()}</p>
+      </Show>
+      <Show when={isEditingTheme()}>
+        <div class="my-4 flex flex-col gap-4">
+          <TextInput
+            label="Name"
+            value={themeDraft.theme.name}
+            onInput={(e) =>
+              setThemeDraft('theme', 'name', e.currentTarget.value)
+            }
+          />
+          <For each={['dark', 'light'] as const}>
+            {(variant) => (
+              <div>
+                <p class="mb-2 font-semibold">
+                  {variant === 'dark' ? 'Dark' : 'Light'}
+                </p>
+                <div class="flex flex-wrap gap-4">
+                  <For
+                    each={
+                      Object.keys(themeColorLabels) as (keyof ThemeColors)[]
+                    }
+                  >
+                    {(key) => (
+                      <label class="flex items-center gap-2">
+                        <input
+                          type="color"
+                          value={themeDraft.theme[variant][key]}
+                          onInput={(e) =>
+                            setThemeDraft(
+                              'theme',
+                              variant,
+                              key,
+                              e.currentTarget.value,
+                            )
+                          }
+                        />
+                        {themeColorLabels[key]}
+                      </label>
+                    )}
+                  </For>
+                </div>
+              </div>
+            )}
+          </For>
+          <div class="flex gap-2">
+            <Button onClick={saveTheme}>Save Theme</Button>
+            <Button variant="ghost" onClick={() => setIsEditingTheme(false)}>
+              Cancel
+            </Button>
+          </div>
+        </div>
+      </Show>
+      <label class="mt-4 flex items-center gap-2">
+        <input
+          type="checkbox"
+          checked={config.follow_system_theme}
+          onChange={(e) =>
+            setConfig('follow_system_theme', e.currentTarget.checked)
+          }
+        />
+        Follow the desktop's light or dark preference
+      </label>
+      <label class="flex items-center gap-2">
+        <input
+          type="checkbox"
+          checked={config.dynamic_accent}
+          onChange={(e) =>
+            setConfig('dynamic_accent', e.currentTarget.checked)
+          }
+        />
+        Take the accent color from the playing album's cover
+      </label>
       <div
-        style={generateCssVariables(
-          config.main_color,
-          config.accent_color,
-          config.dark_mode,
-        )}
+        style={generateConfigCssVariables(config)}
       >
         <div class="my-4 rounded-lg border-2 border-accent-600 bg-primary-950 px-4 py-2 text-primary-100">
           <p class="my-4 text-lg font-semibold">Appearance Preview</p>
@@ -156,7 +428,406 @@ const Settings: Component = () => {
           />
         </div>
       </div>
-      <div class="flex justify-end">
+      <p class="mb-4 text-xl font-semibold">Remote Control</p>
+      <label class="flex items-center gap-2">
+        <input
+          type="checkbox"
+          checked={config.remote.enabled}
+          onChange={(e) =>
+            setConfig('remote', 'enabled', e.currentTarget.checked)
+          }
+        />
+        Control Ronix from phones on your network
+      </label>
+      <Show when={config.remote.enabled}>
+        <div class="my-4 flex flex-col gap-4">
+          <TextInput
+            label="Port"
+            type="number"
+            value={config.remote.port}
+            onChange={(e) =>
+              setConfig('remote', 'port', parseInt(e.currentTarget.value))
+            }
+          />
+          <Show
+            when={remoteUrl()}
+            fallback={<p>Save to get a pairing link.</p>}
+          >
+            {(url) => (
+              <p>
+                Open this link on your phone to pair it:{' '}
+                <span class="select-all break-all font-semibold">{url()}</span>
+              </p>
+            )}
+          </Show>
+          <div>
+            <Button size="small" onClick={unpairDevices}>
+              Unpair All Devices
+            </Button>
+          </div>
+        </div>
+      </Show>
+      <p class="my-4 text-xl font-semibold">MPD Server</p>
+      <label class="flex items-center gap-2">
+        <input
+          type="checkbox"
+          checked={config.mpd.enabled}
+          onChange={(e) => setConfig('mpd', 'enabled', e.currentTarget.checked)}
+        />
+        Let MPD clients like ncmpcpp control Ronix
+      </label>
+      <Show when={config.mpd.enabled}>
+        <div class="my-4 flex flex-col gap-4">
+          <TextInput
+            label="Port"
+            type="number"
+            value={config.mpd.port}
+            onChange={(e) =>
+              setConfig('mpd', 'port', parseInt(e.currentTarget.value))
+            }
+          />
+          <TextInput
+            label="Password (leave empty to allow everyone)"
+            type="password"
+            value={config.mpd.password}
+            onChange={(e) =>
+              setConfig('mpd', 'password', e.currentTarget.value)
+            }
+          />
+        </div>
+      </Show>
+      <p class="my-4 text-xl font-semibold">Subsonic Server</p>
+      <label class="flex items-center gap-2">
+        <input
+          type="checkbox"
+          checked={config.subsonic.enabled}
+          onChange={(e) =>
+            setConfig('subsonic', 'enabled', e.currentTarget.checked)
+          }
+        />
+        Stream your library to Subsonic apps
+      </label>
+      <Show when={config.subsonic.enabled}>
+        <div class="my-4 flex flex-col gap-4">
+          <TextInput
+            label="Port"
+            type="number"
+            value={config.subsonic.port}
+            onChange={(e) =>
+              setConfig('subsonic', 'port', parseInt(e.currentTarget.value))
+            }
+          />
+          <TextInput
+            label="Username"
+            value={config.subsonic.username}
+            onChange={(e) =>
+              setConfig('subsonic', 'username', e.currentTarget.value)
+            }
+          />
+          <TextInput
+            label="Password"
+            type="password"
+            value={config.subsonic.password}
+            onChange={(e) =>
+              setConfig('subsonic', 'password', e.currentTarget.value)
+            }
+          />
+          <label class="flex items-center gap-2">
+            <input
+              type="checkbox"
+              checked={config.subsonic.transcoding}
+              onChange={(e) =>
+                setConfig('subsonic', 'transcoding', e.currentTarget.checked)
+              }
+            />
+            Transcode streams when apps ask for it (requires ffmpeg)
+          </label>
+        </div>
+      </Show>
+      <p class="my-4 text-xl font-semibold">Import Playlists</p>
+      <div class="flex flex-wrap gap-4">
+        <Button onClick={() => importPlaylists('Exportify')}>
+          Spotify (Exportify)
+        </Button>
+        <Button onClick={() => importPlaylists('Rhythmbox')}>Rhythmbox</Button>
+        <Button onClick={() => importPlaylists('Clementine')}>
+          Clementine
+        </Button>
+        <Button onClick={() => importPlaylists('Strawberry')}>
+          Strawberry
+        </Button>
+        <Button onClick={() => importPlaylists('ITunes')}>iTunes</Button>
+      </div>
+      <Show when={playlistImportResult()}>
+        <p class="mt-2">{playlistImportResult()!.message}</p>
+        <Show when={playlistImportResult()!.missing.length}>
+          <ul class="mt-2 max-h-48 space-y-1 overflow-y-auto text-sm">
+            <For each={playlistImportResult()!.missing}>
+              {(track) => (
+                <li class="flex items-center justify-between gap-4">
+                  <span class="opacity-70">
+                    {track.playlist}: {track.artist} - {track.title}
+                  </span>
+                  <Show
+                    when={downloadStatus[track.download_query]}
+                    fallback={
+                      <Button
+                        onClick={() =>
+                          downloadMissingTrack(track.download_query)
+                        }
+                      >
+                        Download
+                      </Button>
+                    }
+                  >
+                    {downloadStatus[track.download_query]}
+                  </Show>
+                </li>
+              )}
+            </For>
+          </ul>
+        </Show>
+      </Show>
+      <p class="my-4 text-xl font-semibold">Import Listening History</p>
+      <div class="flex flex-wrap gap-4">
+        <Button onClick={() => importHistory('Spotify')}>Spotify</Button>
+        <Button onClick={() => importHistory('LastFm')}>Last.fm</Button>
+        <Button onClick={() => importHistory('ListenBrainz')}>
+          ListenBrainz
+        </Button>
+      </div>
+      <Show when={importResult()}>
+        <p class="mt-2">{importResult()!.message}</p>
+        <Show when={importResult()!.unmatched.length}>
+          <ul class="mt-2 max-h-48 overflow-y-auto text-sm opacity-70">
+            <For each={importResult()!.unmatched}>
+              {(entry) => (
+                <li>
+                  {entry.artist} - {entry.title} ({entry.count})
+                </li>
+              )}
+            </For>
+          </ul>
+        </Show>
+      </Show>
+      <p class="my-4 text-xl font-semibold">Year in Review</p>
+      <div class="flex items-end gap-4">
+        <TextInput
+          label="Year"
+          type="number"
+          value={reportYear()}
+          onChange={(e) => setReportYear(parseInt(e.currentTarget.value))}
+        />
+        <Button onClick={() => exportYearReport('Html')}>Export HTML</Button>
+        <Button onClick={() => exportYearReport('Json')}>Export JSON</Button>
+      </div>
+      <Show when={reportStatus()}>
+        <p class="mt-2">{reportStatus()}</p>
+      </Show>
+      <p class="my-4 text-xl font-semibold">Search</p>
+      <label class="flex items-center gap-2">
+        <input
+          type="checkbox"
+          checked={config.search_transliteration}
+          onChange={(e) =>
+            setConfig('search_transliteration', e.currentTarget.checked)
+          }
+        />
+        Match names written in other scripts, like Cyrillic or Japanese, with
+        Latin letters
+      </label>
+      <div class="mt-4">
+        <TextInput
+          label="Words skipped at the start of names when sorting, separated by commas"
+          value={config.sort_articles.join(', ')}
+          onChange={(e) =>
+            setConfig(
+              'sort_articles',
+              e.currentTarget.value
+                .split(',')
+                .map((article) => article.trim())
+                .filter((article) => article),
+            )
+          }
+        />
+      </div>
+      <p class="my-4 text-xl font-semibold">Cover Art</p>
+      <TextInput
+        label="Images beside the songs used as cover art, most preferred first, separated by commas"
+        value={config.cover_art_names.join(', ')}
+        onChange={(e) =>
+          setConfig(
+            'cover_art_names',
+            e.currentTarget.value
+              .split(',')
+              .map((name) => name.trim())
+              .filter((name) => name),
+          )
+        }
+      />
+      <div class="mt-4">
+        <TextInput
+          label="Cover cache size in megabytes"
+          type="number"
+          min="16"
+          value={config.cover_cache_size}
+          onChange={(e) =>
+            setConfig('cover_cache_size', parseInt(e.currentTarget.value))
+          }
+        />
+      </div>
+      <p class="my-4 text-xl font-semibold">Ratings</p>
+      <label class="flex items-center gap-2">
+        <input
+          type="checkbox"
+          checked={config.write_rating_tags}
+          onChange={(e) =>
+            setConfig('write_rating_tags', e.currentTarget.checked)
+          }
+        />
+        Write ratings to the files' tags so other players can read them
+      </label>
+      <p class="my-4 text-xl font-semibold">Broadcast</p>
+      <label class="flex items-center gap-2">
+        <input
+          type="checkbox"
+          checked={config.broadcast.enabled}
+          onChange={(e) =>
+            setConfig('broadcast', 'enabled', e.currentTarget.checked)
+          }
+        />
+        Stream what's playing over HTTP
+      </label>
+      <Show when={config.broadcast.enabled}>
+        <div class="my-4 flex flex-col gap-4">
+          <TextInput
+            label="Port"
+            type="number"
+            value={config.broadcast.port}
+            onChange={(e) =>
+              setConfig('broadcast', 'port', parseInt(e.currentTarget.value))
+            }
+          />
+          <div class="flex gap-4">
+            <For each={['Mp3', 'Opus'] as const}>
+              {(format) => (
+                <label class="flex items-center gap-2">
+                  <input
+                    type="radio"
+                    name="broadcast-format"
+                    checked={config.broadcast.format === format}
+                    onChange={() => setConfig('broadcast', 'format', format)}
+                  />
+                  {format === 'Mp3' ? 'MP3' : 'Ogg Opus'}
+                </label>
+              )}
+            </For>
+          </div>
+        </div>
+      </Show>
+      <p class="my-4 text-xl font-semibold">Scrobbling</p>
+      <Show when={scrobblingError()}>
+        <p class="mb-4 text-red-500">{scrobblingError()}</p>
+      </Show>
+      <label class="flex items-center gap-2">
+        <input
+          type="checkbox"
+          checked={config.scrobbling.listenbrainz_enabled}
+          onChange={(e) =>
+            setConfig(
+              'scrobbling',
+              'listenbrainz_enabled',
+              e.currentTarget.checked,
+            )
+          }
+        />
+        Scrobble to ListenBrainz
+      </label>
+      <Show when={config.scrobbling.listenbrainz_enabled}>
+        <div class="my-4 flex flex-col gap-4">
+          <TextInput
+            label="User Token"
+            type="password"
+            value={config.scrobbling.listenbrainz_token}
+            onChange={(e) =>
+              setConfig(
+                'scrobbling',
+                'listenbrainz_token',
+                e.currentTarget.value,
+              )
+            }
+          />
+          <TextInput
+            label="Server URL"
+            value={config.scrobbling.listenbrainz_url}
+            onChange={(e) =>
+              setConfig('scrobbling', 'listenbrainz_url', e.currentTarget.value)
+            }
+          />
+        </div>
+      </Show>
+      <label class="mt-4 flex items-center gap-2">
+        <input
+          type="checkbox"
+          checked={config.scrobbling.lastfm_enabled}
+          onChange={(e) =>
+            setConfig('scrobbling', 'lastfm_enabled', e.currentTarget.checked)
+          }
+        />
+        Scrobble to Last.fm
+      </label>
+      <Show when={config.scrobbling.lastfm_enabled}>
+        <div class="my-4 flex flex-col gap-4">
+          <TextInput
+            label="API Key"
+            value={config.scrobbling.lastfm_api_key}
+            onChange={(e) =>
+              setConfig('scrobbling', 'lastfm_api_key', e.currentTarget.value)
+            }
+          />
+          <TextInput
+            label="API Secret"
+            type="password"
+            value={config.scrobbling.lastfm_api_secret}
+            onChange={(e) =>
+              setConfig(
+                'scrobbling',
+                'lastfm_api_secret',
+                e.currentTarget.value,
+              )
+            }
+          />
+          <TextInput
+            label="API URL"
+            value={config.scrobbling.lastfm_url}
+            onChange={(e) =>
+              setConfig('scrobbling', 'lastfm_url', e.currentTarget.value)
+            }
+          />
+          <Show
+            when={config.scrobbling.lastfm_session_key}
+            fallback={<p>Not logged in</p>}
+          >
+            <p>Logged in</p>
+          </Show>
+          <TextInput
+            label="Username"
+            value={lastFmUsername()}
+            onChange={(e) => setLastFmUsername(e.currentTarget.value)}
+          />
+          <TextInput
+            label="Password"
+            type="password"
+            value={lastFmPassword()}
+            onChange={(e) => setLastFmPassword(e.currentTarget.value)}
+          />
+          <div class="flex items-center gap-4">
+            <Button onClick={logInToLastFm}>Log In</Button>
+            <p>{lastFmStatus()}</p>
+          </div>
+        </div>
+      </Show>
+      <div class="mt-4 flex justify-end">
         <Button
           variant="accent"
           class="px-8"
@@ -1,7 +1,10 @@
 import { api } from '@/api';
 import Button from '@/components/ui/button';
+import SortSelect, { createSortOrder } from '@/components/sortSelect';
 import Modal from '@/components/ui/modal';
 import TextInput from '@/components/ui/textInput';
+import { getCoverUrl } from '@/covers';
+import { PlayerScope } from '@/gen/tauri-types';
 import { library, refetchLibrary } from '@/library';
 import SongButton from '@/songButton';
 import {
@@ -19,6 +22,8 @@ const SongList: Component<{
   ids?: string[];
   noSort?: boolean;
   isManager?: boolean;
+  // What plays after the song, when it isn't the album or artist
+  scope?: PlayerScope;
 }> = (props) => {
   const [songToEdit, setSongToEdit] = createSignal<string | null>(null);
   const [operation, setOperation] = createSignal<'EDIT' | 'DELETE'>('EDIT');
@@ -27,6 +32,8 @@ const SongList: Component<{
   const albumEditData = () => library()?.albums[songEditData()!.album!];
   const artistEditData = () => library()?.artists[albumEditData()!.artist!];
 
+  const order = createSortOrder('Songs', () => !props.noSort);
+
   const [isLoading, setIsLoading] = createSignal(false);
   const [returnText, setReturnText] = createSignal<string | null>(null);
 
@@ -47,13 +54,12 @@ const SongList: Component<{
         ...song,
         album_id: song.album,
         artist_id: album.artist,
-        cover_art: album.cover_art,
+        cover_art: album.cover_art && getCoverUrl(song.album, 'small'),
         artist: artist.name,
         id,
       };
     });
-    if (!props.noSort)
-      songs = songs.sort((a, b) => a.title.localeCompare(b.title));
+    if (!props.noSort) songs = order.sort(songs);
     return songs;
   };
 
@@ -110,16 +116,27 @@ const SongList: Component<{
                   e.preventDefault();
                   setIsLoading(true);
                   const formData = new FormData(e.currentTarget);
+                  const songId = songToEdit()!;
+                  // Saved first, the edit rescans the library
                   api
                     .mutation([
-                      'library.editSong',
+                      'library.setRating',
                       {
-                        album: formData.get('album') as string,
-                        title: formData.get('title') as string,
-                        artist: formData.get('artist') as string,
-                        id: songToEdit()!,
+                        song_id: songId,
+                        rating: Number(formData.get('rating')),
                       },
                     ])
+                    .then(() =>
+                      api.mutation([
+                        'library.editSong',
+                        {
+                          album: formData.get('album') as string,
+                          title: formData.get('title') as string,
+                          artist: formData.get('artist') as string,
+                          id: songId,
+                        },
+                      ]),
+                    )
                     .then((text) => {
                       setReturnText(text);
                       refetchLibrary();
@@ -141,6 +158,25 @@ const SongList: Component<{
                   label="Artist"
                   value={artistEditData()?.name}
                 />
+                <div>
+                  <label for="song-rating">Rating</label>
+                  <select
+                    id="song-rating"
+                    name="rating"
+                    class="mt-1 block w-full rounded-md bg-primary-800 px-2 py-1"
+                  >
+                    <For each={[0, 1, 2, 3, 4, 5]}>
+                      {(rating) => (
+                        <option
+                          value={rating}
+                          selected={songEditData()?.rating === rating}
+                        >
+                          {rating === 0 ? 'Not rated' : '★'.repeat(rating)}
+                        </option>
+                      )}
+                    </For>
+                  </select>
+                </div>
                 <div class="flex justify-end gap-2">
                   <Button onClick={() => setSongToEdit(null)}>Cancel</Button>
                   <Button variant="accent" type="submit">
@@ -153,6 +189,9 @@ const SongList: Component<{
         </Show>
       </Modal>
 
+      <Show when={!props.noSort}>
+        <SortSelect order={order} />
+      </Show>
       <div class="flex flex-col overflow-x-hidden">
         <For
           each={songs()}
@@ -165,6 +204,15 @@ const SongList: Component<{
               noCoverArt={!!props.album}
               coverArt={song.cover_art}
               isManager={props.isManager}
+              isFavorite={song.is_favorite}
+              onToggleFavorite={() =>
+                api
+                  .mutation([
+                    'library.setFavorite',
+                    { song_id: song.id, is_favorite: !song.is_favorite },
+                  ])
+                  .then(refetchLibrary)
+              }
               onDelete={() => {
                 setSongToEdit(song.id);
                 setOperation('DELETE');
@@ -180,7 +228,9 @@ const SongList: Component<{
                 api.mutation([
                   'player.playSong',
                   {
-                    scope: props.albums
+                    scope: props.scope
+                      ? props.scope
+                      : props.albums
                       ? {
                           Artist: song.artist_id,
                         }
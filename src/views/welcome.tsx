@@ -2,7 +2,7 @@ import FolderList from '@/components/folderList';
 import Button from '@/components/ui/button';
 import { Show, createSignal, type Component } from 'solid-js';
 import { api } from '../api';
-import { setConfig } from '../config';
+import { config, setConfig } from '../config';
 
 const Welcome: Component = () => {
   const [folders, setFolders] = createSignal<string[]>([]);
@@ -21,10 +21,8 @@ const Welcome: Component = () => {
           variant="accent"
           onClick={() =>
             setConfig({
+              ...config()!,
               music_folders: folders(),
-              accent_color: 'Emerald',
-              dark_mode: true,
-              main_color: 'Zinc',
             })
           }
           class="mx-auto"
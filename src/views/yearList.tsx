@@ -0,0 +1,72 @@
+import { api } from '@/api';
+import { YearEntry } from '@/gen/tauri-types';
+import { library } from '@/library';
+import { navigate } from '@/router';
+import { For, Show, createEffect, createSignal, type Component } from 'solid-js';
+
+type Decade = {
+  decade: number;
+  songCount: number;
+  years: YearEntry[];
+};
+
+const YearList: Component = () => {
+  const [years, setYears] = createSignal<YearEntry[]>([]);
+
+  createEffect(() => {
+    if (!library()) return;
+    api.query(['library.years']).then(setYears);
+  });
+
+  // Newest first, grouped by decade
+  const decades = () => {
+    const decades: Decade[] = [];
+    for (const year of [...years()].reverse()) {
+      const decade = year.year - (year.year % 10);
+      if (decades[decades.length - 1]?.decade !== decade)
+        decades.push({ decade, songCount: 0, years: [] });
+      decades[decades.length - 1].songCount += year.song_count;
+      decades[decades.length - 1].years.push(year);
+    }
+    return decades;
+  };
+
+  return (
+    <Show
+      when={decades().length}
+      fallback={<p class="text-center">No years found</p>}
+    >
+      <For each={decades()}>
+        {(decade) => (
+          <section aria-label={`${decade.decade}s`} class="mb-8">
+            <button
+              role="link"
+              class="mb-4 text-2xl font-bold hover:underline"
+              onClick={() => navigate({ name: 'decade', data: decade.decade })}
+            >
+              {decade.decade}s
+            </button>
+            <div class="grid grid-cols-[repeat(auto-fill,minmax(10rem,1fr))] gap-4">
+              <For each={decade.years}>
+                {(year) => (
+                  <button
+                    role="link"
+                    class="items-center overflow-hidden rounded-lg bg-primary-900 p-4 transition-colors hover:bg-primary-800"
+                    onClick={() => navigate({ name: 'year', data: year.year })}
+                  >
+                    <p class="truncate">{year.year}</p>
+                    <p class="text-sm opacity-70">
+                      {year.song_count} songs
+                    </p>
+                  </button>
+                )}
+              </For>
+            </div>
+          </section>
+        )}
+      </For>
+    </Show>
+  );
+};
+
+export default YearList;